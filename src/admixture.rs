@@ -0,0 +1,256 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+
+use rand::seq::{index, SliceRandom};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::cohort::SampleMetadata;
+use crate::error::{MeiosimError, Result};
+use crate::founders::{overlay, FounderGenome, FounderTract};
+use crate::meiosis::{CrossoverModel, Parents, simulate_offspring};
+use crate::seeds::{self, SeedTree};
+use crate::utils::OutputFile;
+
+/// A source population of an admixture, a population or superpopulation
+/// label of the sample metadata, with its share of the founders.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AdmixtureSource {
+    pub label: String,
+    pub proportion: f64,
+}
+
+/// Sources written as `EUR:0.8,AFR:0.2`. Proportions are normalized to sum
+/// to one, and sources without a proportion get an equal share.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AdmixtureSources(pub Vec<AdmixtureSource>);
+
+impl FromStr for AdmixtureSources {
+    type Err = MeiosimError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let invalid = |message: &str| MeiosimError::Config(format!("Invalid admixture sources '{}': {}", text, message));
+        let mut sources = Vec::new();
+        for source in text.split(',').map(|source| source.trim()).filter(|source| !source.is_empty()) {
+            let (label, proportion) = match source.split_once(':') {
+                Some((label, proportion)) => {
+                    let proportion = proportion.trim().parse::<f64>()
+                        .map_err(|_| invalid(&format!("{} is not a number", proportion)))?;
+                    (label.trim(), proportion)
+                },
+                None => (source, 1.0),
+            };
+            if !(proportion > 0.0 && proportion.is_finite()) {
+                return Err(invalid(&format!("the proportion of {} must be positive", label)));
+            }
+            if sources.iter().any(|other: &AdmixtureSource| other.label == label) {
+                return Err(invalid(&format!("{} is listed twice", label)));
+            }
+            sources.push(AdmixtureSource { label: label.to_string(), proportion });
+        }
+        if sources.len() < 2 {
+            return Err(invalid("at least two source populations are needed"));
+        }
+        let total: f64 = sources.iter().map(|source| source.proportion).sum();
+        sources.iter_mut().for_each(|source| source.proportion /= total);
+        Ok(Self(sources))
+    }
+}
+
+/// Admixed population founded by samples of the source populations, which
+/// then mate at random for a number of generations (hybrid isolation).
+#[derive(Clone, Debug, Serialize)]
+pub struct AdmixtureModel {
+    pub sources: AdmixtureSources,
+    /// generations of random mating after the founders
+    pub generations: u32,
+    /// number of individuals of each generation
+    pub size: usize,
+}
+
+impl AdmixtureModel {
+    pub fn new(sources: AdmixtureSources, generations: u32, size: usize) -> Result<Self> {
+        if generations == 0 {
+            return Err(MeiosimError::Config("The admixture needs at least one generation".to_string()));
+        }
+        if size < 2 {
+            return Err(MeiosimError::Config("The admixed population needs at least two individuals".to_string()));
+        }
+        Ok(Self { sources, generations, size })
+    }
+
+    // founders drawn from each source, the rounding remainder going to the
+    // sources with the largest fractional part
+    fn founder_counts(&self) -> Result<Vec<usize>> {
+        let shares: Vec<f64> = self.sources.0.iter().map(|source| source.proportion * self.size as f64).collect();
+        let mut counts: Vec<usize> = shares.iter().map(|share| share.floor() as usize).collect();
+        let mut by_remainder: Vec<usize> = (0..shares.len()).collect();
+        by_remainder.sort_by(|a, b| (shares[*b] - shares[*b].floor()).total_cmp(&(shares[*a] - shares[*a].floor())));
+        let missing = self.size - counts.iter().sum::<usize>();
+        by_remainder.iter().take(missing).for_each(|idx| counts[*idx] += 1);
+        if let Some((source, _)) = self.sources.0.iter().zip(counts.iter()).find(|(_, count)| **count == 0) {
+            return Err(MeiosimError::Config(format!(
+                "No founder is drawn from {} with {} individuals per generation, increase the size of the admixed population",
+                source.label, self.size)));
+        }
+        Ok(counts)
+    }
+}
+
+/// Last generation of an admixed population, with the source population
+/// of each founder.
+pub struct AdmixedPopulation {
+    /// founder sample -> label of its source population
+    pub founders: BTreeMap<String, String>,
+    pub individuals: Vec<FounderGenome>,
+}
+
+/// Simulates an admixed population. The founders are drawn without
+/// replacement among the samples of the panel labelled with each source
+/// population, and every individual of the next generations is the
+/// offspring of two random individuals of the previous one, with the
+/// meioses of `model`. Individuals of a generation are simulated in
+/// parallel on the current thread pool.
+pub fn simulate_admixture(admixture: &AdmixtureModel,
+        metadata: &SampleMetadata,
+        panel_samples: &[String],
+        model: CrossoverModel,
+        chromosomes: &[String],
+        contig_size: &HashMap<String, u64>,
+        seeds: &SeedTree) -> Result<AdmixedPopulation> {
+    let panel_samples: HashSet<&String> = panel_samples.iter().collect();
+    let mut founders = BTreeMap::new();
+    let mut generation = Vec::with_capacity(admixture.size);
+    for (source, count) in admixture.sources.0.iter().zip(admixture.founder_counts()?) {
+        let mut candidates: Vec<&String> = metadata.samples()
+            .filter(|info| info.population.as_ref() == Some(&source.label) || info.superpopulation.as_ref() == Some(&source.label))
+            .map(|info| &info.sample)
+            .filter(|sample| panel_samples.contains(sample) && !founders.contains_key(*sample))
+            .collect();
+        if candidates.len() < count {
+            return Err(MeiosimError::Config(format!(
+                "{} founders are needed from {}, but only {} samples of the panel have this label",
+                count, source.label, candidates.len())));
+        }
+        candidates.shuffle(&mut seeds.stream(&[seeds::FOUNDERS, &source.label]));
+        for sample in candidates.into_iter().take(count) {
+            founders.insert(sample.clone(), source.label.clone());
+            generation.push(FounderGenome::founder(sample, chromosomes, contig_size)?);
+        }
+    }
+
+    for generation_idx in 1..=admixture.generations {
+        let previous = generation;
+        generation = (0..admixture.size).into_par_iter()
+            .map(|individual_idx| {
+                let name = format!("gen{}_ind{}", generation_idx, individual_idx);
+                let mates = index::sample(&mut seeds.stream(&[&name, seeds::MATING]), previous.len(), 2);
+                let (parent1, parent2) = (mates.index(0), mates.index(1));
+                let parents = Parents::new(&format!("gen{}_ind{}", generation_idx - 1, parent1),
+                    &format!("gen{}_ind{}", generation_idx - 1, parent2));
+                let offspring = simulate_offspring(&name, &parents, model, chromosomes, contig_size, false, seeds)?;
+                FounderGenome::offspring(&previous[parent1], &previous[parent2], &offspring)
+            })
+            .collect::<Result<Vec<_>>>()?;
+    }
+    Ok(AdmixedPopulation {
+        founders,
+        individuals: generation,
+    })
+}
+
+impl AdmixedPopulation {
+    /// Draws two different individuals of the last generation.
+    pub fn choose_parents(&self, seeds: &SeedTree) -> [FounderGenome; 2] {
+        let mates = index::sample(&mut seeds.stream(&[seeds::PARENT_CHOICE]), self.individuals.len(), 2);
+        [self.individuals[mates.index(0)].clone(), self.individuals[mates.index(1)].clone()]
+    }
+
+    fn label(&self, tract: &FounderTract) -> &str {
+        self.founders.get(&tract.founder).map(|label| label.as_str()).unwrap_or("unknown")
+    }
+
+    /// Local ancestry of `genome`, as the stretches where the source
+    /// populations of its two haplotypes do not change:
+    /// (chromosome, start, end, ancestry of hap1, ancestry of hap2).
+    pub fn local_ancestry<'a>(&'a self, genome: &FounderGenome) -> Vec<(String, u64, u64, &'a str, &'a str)> {
+        let mut ancestry: Vec<(String, u64, u64, &str, &str)> = Vec::new();
+        for (chr, [hap1, hap2]) in genome.chromosomes.iter() {
+            for (start, end, tract1, tract2) in overlay(hap1, hap2) {
+                let labels = (self.label(tract1), self.label(tract2));
+                match ancestry.last_mut() {
+                    Some(last) if last.0 == *chr && last.2 == start && (last.3, last.4) == labels => last.2 = end,
+                    _ => ancestry.push((chr.clone(), start, end, labels.0, labels.1)),
+                }
+            }
+        }
+        ancestry
+    }
+
+    /// Fraction of the two haplotypes of `genome` inherited from each
+    /// source population.
+    pub fn ancestry_proportions(&self, genome: &FounderGenome) -> BTreeMap<String, f64> {
+        let mut lengths: BTreeMap<String, u64> = BTreeMap::new();
+        for tract in genome.chromosomes.values().flatten().flatten() {
+            *lengths.entry(self.label(tract).to_string()).or_default() += tract.end - tract.start;
+        }
+        let total: u64 = lengths.values().sum();
+        lengths.into_iter()
+            .map(|(label, length)| (label, length as f64 / total.max(1) as f64))
+            .collect()
+    }
+
+    /// Writes the local ancestry of `genome` as a BED file with the
+    /// ancestry of each haplotype in the 4th and 5th columns.
+    pub fn write_local_ancestry(&self, path: &str, genome: &FounderGenome) -> Result<()> {
+        let mut outputfile = OutputFile::create(path)?;
+        for (chr, start, end, ancestry1, ancestry2) in self.local_ancestry(genome) {
+            outputfile.write_str(&format!("{}\t{}\t{}\t{}\t{}\n", chr, start, end, ancestry1, ancestry2))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{AdmixedPopulation, AdmixtureModel, AdmixtureSources};
+    use crate::founders::{FounderGenome, FounderTract};
+
+    #[test]
+    fn test_sources_and_founder_counts() {
+        let sources: AdmixtureSources = "EUR:0.8, AFR:0.2".parse().unwrap();
+        assert_eq!(sources.0[0].label, "EUR");
+        assert!((sources.0[1].proportion - 0.2).abs() < 1e-9);
+        let sources: AdmixtureSources = "EUR,AFR,EAS".parse().unwrap();
+        let model = AdmixtureModel::new(sources, 4, 10).unwrap();
+        assert_eq!(model.founder_counts().unwrap().iter().sum::<usize>(), 10);
+        assert!("EUR".parse::<AdmixtureSources>().is_err());
+        assert!("EUR:0.5,AFR:-1".parse::<AdmixtureSources>().is_err());
+        let model = AdmixtureModel::new("EUR:0.99,AFR:0.01".parse().unwrap(), 4, 10).unwrap();
+        assert!(model.founder_counts().is_err());
+    }
+
+    #[test]
+    fn test_local_ancestry() {
+        let tract = |start, end, founder: &str| FounderTract { start, end, founder: founder.to_string(), hap: 0 };
+        let population = AdmixedPopulation {
+            founders: BTreeMap::from([("A".to_string(), "EUR".to_string()),
+                ("B".to_string(), "EUR".to_string()),
+                ("C".to_string(), "AFR".to_string())]),
+            individuals: Vec::new(),
+        };
+        let mut genome = FounderGenome::default();
+        genome.chromosomes.insert("chr1".to_string(), [
+            vec![tract(0, 100, "A"), tract(100, 300, "B"), tract(300, 1000, "C")],
+            vec![tract(0, 500, "C"), tract(500, 1000, "A")],
+        ]);
+        let ancestry: Vec<(u64, u64, &str, &str)> = population.local_ancestry(&genome).into_iter()
+            .map(|(_, start, end, ancestry1, ancestry2)| (start, end, ancestry1, ancestry2))
+            .collect();
+        assert_eq!(ancestry, vec![(0, 300, "EUR", "AFR"), (300, 500, "AFR", "AFR"), (500, 1000, "AFR", "EUR")]);
+        let proportions = population.ancestry_proportions(&genome);
+        assert!((proportions["AFR"] - 0.6).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,441 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use serde::Serialize;
+
+use crate::error::{MeiosimError, Result};
+use crate::meiosis::Parents;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sex {
+    Male,
+    Female,
+    Unknown,
+}
+
+impl Sex {
+    pub(crate) fn opposite(&self, other: &Sex) -> bool {
+        matches!((self, other), (Sex::Male, Sex::Female) | (Sex::Female, Sex::Male))
+    }
+}
+
+/// One sample of the population panel metadata.
+#[derive(Clone, Debug)]
+pub struct SampleInfo {
+    pub sample: String,
+    /// pedigree the sample belongs to, the sample itself if not given
+    pub family_id: String,
+    pub father: Option<String>,
+    pub mother: Option<String>,
+    pub sex: Sex,
+    pub population: Option<String>,
+    pub superpopulation: Option<String>,
+}
+
+/// Metadata of the samples of the population panel, as the 1kG
+/// `20130606_g1k_3202_samples_ped_population.txt` table.
+pub struct SampleMetadata {
+    samples: BTreeMap<String, SampleInfo>,
+}
+
+impl SampleMetadata {
+    pub fn from_path(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| MeiosimError::io(path, e))?;
+        Self::from_table(&text, path)
+    }
+
+    /// Parses a whitespace separated table with a header line. Only the
+    /// `SampleID` column is required, `FamilyID`, `FatherID`, `MotherID`,
+    /// `Sex` (1 male, 2 female), `Population` and `Superpopulation` are
+    /// used when present. Missing values are `0` or `NA`.
+    pub fn from_table(text: &str, path: &str) -> Result<Self> {
+        let mut lines = text.lines().enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let header: Vec<&str> = match lines.next() {
+            Some((_, line)) => line.trim_start_matches('#').split_whitespace().collect(),
+            None => return Err(MeiosimError::input(path, "the sample metadata is empty")),
+        };
+        let column = |name: &str| header.iter().position(|column| column.eq_ignore_ascii_case(name));
+        let sample_column = column("SampleID")
+            .ok_or_else(|| MeiosimError::parse(path, 1, "no SampleID column in the header"))?;
+        let family_column = column("FamilyID");
+        let father_column = column("FatherID");
+        let mother_column = column("MotherID");
+        let sex_column = column("Sex");
+        let population_column = column("Population");
+        let superpopulation_column = column("Superpopulation");
+
+        let mut samples = BTreeMap::new();
+        for (line_idx, line) in lines {
+            let line_number = line_idx + 1;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != header.len() {
+                return Err(MeiosimError::parse(path, line_number,
+                    &format!("expected {} columns, found {}", header.len(), fields.len())));
+            }
+            let value = |column: Option<usize>| {
+                column.map(|idx| fields[idx])
+                    .filter(|value| *value != "0" && *value != "NA")
+                    .map(|value| value.to_string())
+            };
+            let sample = fields[sample_column].to_string();
+            let sex = match value(sex_column).as_deref() {
+                Some("1") | Some("male") => Sex::Male,
+                Some("2") | Some("female") => Sex::Female,
+                _ => Sex::Unknown,
+            };
+            let info = SampleInfo {
+                family_id: value(family_column).unwrap_or_else(|| sample.clone()),
+                father: value(father_column),
+                mother: value(mother_column),
+                sex,
+                population: value(population_column),
+                superpopulation: value(superpopulation_column),
+                sample: sample.clone(),
+            };
+            if samples.insert(sample.clone(), info).is_some() {
+                return Err(MeiosimError::parse(path, line_number,
+                    &format!("sample {} is listed twice", sample)));
+            }
+        }
+        Ok(Self { samples })
+    }
+
+    pub fn get(&self, sample: &str) -> Option<&SampleInfo> {
+        self.samples.get(sample)
+    }
+
+    /// Samples sorted by name.
+    pub fn samples(&self) -> impl Iterator<Item = &SampleInfo> {
+        self.samples.values()
+    }
+
+    /// Whether two samples belong to the same pedigree or one is a parent
+    /// of the other.
+    pub fn are_related(&self, sample1: &str, sample2: &str) -> bool {
+        match (self.get(sample1), self.get(sample2)) {
+            (Some(info1), Some(info2)) => {
+                info1.family_id == info2.family_id
+                    || [&info1.father, &info1.mother].iter().any(|parent| parent.as_deref() == Some(sample2))
+                    || [&info2.father, &info2.mother].iter().any(|parent| parent.as_deref() == Some(sample1))
+            },
+            _ => false,
+        }
+    }
+}
+
+/// One family of a batch, its identifier names the output directory.
+#[derive(Clone, Debug)]
+pub struct FamilySpec {
+    pub family_id: String,
+    pub parents: Parents,
+}
+
+/// Reads a table of parent pairs, one family per line with the columns
+/// `parent1 parent2 [family_id]`. Families without an identifier are named
+/// after their line, `fam0`, `fam1`...
+pub fn read_parent_pairs(path: &str) -> Result<Vec<FamilySpec>> {
+    let text = fs::read_to_string(path).map_err(|e| MeiosimError::io(path, e))?;
+    let mut families = Vec::new();
+    let mut family_ids = HashSet::new();
+    for (line_idx, line) in text.lines().enumerate() {
+        let line_number = line_idx + 1;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 2 && fields.len() != 3 {
+            return Err(MeiosimError::parse(path, line_number,
+                &format!("expected 2 or 3 columns (parent1, parent2, family id), found {}", fields.len())));
+        }
+        let family_id = match fields.get(2) {
+            Some(family_id) => family_id.to_string(),
+            None => format!("fam{}", families.len()),
+        };
+        if family_id.contains('/') {
+            return Err(MeiosimError::parse(path, line_number,
+                &format!("family id {} cannot be used as a directory name", family_id)));
+        }
+        if !family_ids.insert(family_id.clone()) {
+            return Err(MeiosimError::parse(path, line_number,
+                &format!("family id {} is used twice", family_id)));
+        }
+        families.push(FamilySpec {
+            family_id,
+            parents: Parents::new(fields[0], fields[1]),
+        });
+    }
+    if families.is_empty() {
+        return Err(MeiosimError::input(path, "the table does not contain any parent pair"));
+    }
+    Ok(families)
+}
+
+/// Constraints on the couples drawn by [`random_pairs`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PairingConstraints {
+    pub same_population: bool,
+    pub same_superpopulation: bool,
+    pub unrelated: bool,
+}
+
+impl PairingConstraints {
+    fn allows(&self, metadata: &SampleMetadata, sample1: &SampleInfo, sample2: &SampleInfo) -> bool {
+        let same = |value1: &Option<String>, value2: &Option<String>| value1.is_some() && value1 == value2;
+        // couples of the same known sex are not drawn
+        let known_sexes = sample1.sex != Sex::Unknown && sample2.sex != Sex::Unknown;
+        (!known_sexes || sample1.sex.opposite(&sample2.sex))
+            && (!self.same_population || same(&sample1.population, &sample2.population))
+            && (!self.same_superpopulation || same(&sample1.superpopulation, &sample2.superpopulation))
+            && (!self.unrelated || !metadata.are_related(&sample1.sample, &sample2.sample))
+    }
+}
+
+/// Draws `count` couples among the samples of the panel that are listed in
+/// the metadata. Each sample is used in one couple at most, and couples are
+/// of opposite sex when it is known, the mother being parent1.
+pub fn random_pairs(metadata: &SampleMetadata,
+        panel_samples: &[String],
+        count: usize,
+        constraints: PairingConstraints,
+        rng: &mut StdRng) -> Result<Vec<FamilySpec>> {
+    let panel_samples: HashSet<&String> = panel_samples.iter().collect();
+    let mut candidates: Vec<&SampleInfo> = metadata.samples()
+        .filter(|info| panel_samples.contains(&info.sample))
+        .collect();
+    candidates.shuffle(rng);
+
+    let mut used = vec![false; candidates.len()];
+    let mut families = Vec::with_capacity(count);
+    for idx1 in 0..candidates.len() {
+        if families.len() == count {
+            break;
+        }
+        if used[idx1] {
+            continue;
+        }
+        let partner = (idx1 + 1..candidates.len()).find(|idx2| {
+            !used[*idx2] && constraints.allows(metadata, candidates[idx1], candidates[*idx2])
+        });
+        if let Some(idx2) = partner {
+            used[idx1] = true;
+            used[idx2] = true;
+            let (mother, father) = order_by_sex(candidates[idx1], candidates[idx2]);
+            families.push(FamilySpec {
+                family_id: format!("fam{}", families.len()),
+                parents: Parents::new(&mother.sample, &father.sample),
+            });
+        }
+    }
+    if families.len() < count {
+        return Err(MeiosimError::Config(format!(
+            "Only {} of the {} requested parent pairs satisfy the pairing constraints", families.len(), count)));
+    }
+    Ok(families)
+}
+
+fn order_by_sex<'a>(sample1: &'a SampleInfo, sample2: &'a SampleInfo) -> (&'a SampleInfo, &'a SampleInfo) {
+    if sample1.sex == Sex::Male || sample2.sex == Sex::Female {
+        (sample2, sample1)
+    } else {
+        (sample1, sample2)
+    }
+}
+
+/// Criteria to draw a parent from the panel, written as comma separated
+/// `key=value` pairs with the keys `population`, `superpopulation` and
+/// `sex`, e.g. `superpopulation=EUR,sex=female`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SampleQuery {
+    pub population: Option<String>,
+    pub superpopulation: Option<String>,
+    pub sex: Option<Sex>,
+}
+
+impl FromStr for SampleQuery {
+    type Err = MeiosimError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut query = SampleQuery::default();
+        for criterion in text.split(',').filter(|criterion| !criterion.trim().is_empty()) {
+            let (key, value) = criterion.split_once('=').ok_or_else(|| {
+                MeiosimError::Config(format!("Invalid sample query '{}', expected key=value pairs", text))
+            })?;
+            let value = value.trim().to_string();
+            match key.trim() {
+                "population" => query.population = Some(value),
+                "superpopulation" => query.superpopulation = Some(value),
+                "sex" => {
+                    query.sex = Some(match value.to_lowercase().as_str() {
+                        "female" | "f" | "2" => Sex::Female,
+                        "male" | "m" | "1" => Sex::Male,
+                        _ => return Err(MeiosimError::Config(format!("Invalid sex '{}' in sample query '{}'", value, text))),
+                    });
+                },
+                key => {
+                    return Err(MeiosimError::Config(format!(
+                        "Unknown key '{}' in sample query '{}', use population, superpopulation or sex", key, text)));
+                }
+            }
+        }
+        Ok(query)
+    }
+}
+
+impl fmt::Display for SampleQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut criteria = Vec::new();
+        if let Some(population) = &self.population {
+            criteria.push(format!("population={}", population));
+        }
+        if let Some(superpopulation) = &self.superpopulation {
+            criteria.push(format!("superpopulation={}", superpopulation));
+        }
+        match self.sex {
+            Some(Sex::Female) => criteria.push("sex=female".to_string()),
+            Some(Sex::Male) => criteria.push("sex=male".to_string()),
+            _ => {}
+        }
+        write!(f, "{}", criteria.join(","))
+    }
+}
+
+impl SampleQuery {
+    fn matches(&self, info: &SampleInfo) -> bool {
+        (self.population.is_none() || self.population == info.population)
+            && (self.superpopulation.is_none() || self.superpopulation == info.superpopulation)
+            && (self.sex.is_none() || self.sex == Some(info.sex))
+    }
+}
+
+/// How a parent of [`choose_parents`] is picked.
+#[derive(Clone, Debug)]
+pub enum ParentChoice {
+    Sample(String),
+    Query(SampleQuery),
+}
+
+/// Picks the two parents of a family, drawing at random among the samples
+/// of the panel that match the queries. The parents cannot be of the same
+/// known sex: a query without a sex draws the opposite sex of the other
+/// parent, or a mother for parent1 and a father for parent2.
+pub fn choose_parents(metadata: &SampleMetadata,
+        panel_samples: &[String],
+        parent1: &ParentChoice,
+        parent2: &ParentChoice,
+        unrelated: bool,
+        rng: &mut StdRng) -> Result<Parents> {
+    let panel_samples: HashSet<&String> = panel_samples.iter().collect();
+    let sex_of = |sample: &str| metadata.get(sample).map(|info| info.sex).unwrap_or(Sex::Unknown);
+    let pick = |choice: &ParentChoice, role_sex: Sex, other: Option<&str>, rng: &mut StdRng| -> Result<String> {
+        let query = match choice {
+            ParentChoice::Sample(sample) => return Ok(sample.clone()),
+            ParentChoice::Query(query) => query,
+        };
+        let default_sex = match other.map(sex_of) {
+            Some(Sex::Male) => Sex::Female,
+            Some(Sex::Female) => Sex::Male,
+            _ => role_sex,
+        };
+        let query = SampleQuery { sex: query.sex.or(Some(default_sex)), ..query.clone() };
+        let candidates: Vec<&SampleInfo> = metadata.samples()
+            .filter(|info| panel_samples.contains(&info.sample) && query.matches(info))
+            .filter(|info| match other {
+                Some(other) => info.sample != other && !(unrelated && metadata.are_related(&info.sample, other)),
+                None => true,
+            })
+            .collect();
+        candidates.choose(rng)
+            .map(|info| info.sample.clone())
+            .ok_or_else(|| MeiosimError::Config(format!("No sample of the panel matches '{}'", query)))
+    };
+    // a fixed parent is known before drawing the other one
+    let (parent1, parent2) = match (parent1, parent2) {
+        (ParentChoice::Query(_), ParentChoice::Sample(sample2)) => {
+            (pick(parent1, Sex::Female, Some(sample2), rng)?, sample2.clone())
+        },
+        _ => {
+            let sample1 = pick(parent1, Sex::Female, None, rng)?;
+            let sample2 = pick(parent2, Sex::Male, Some(&sample1), rng)?;
+            (sample1, sample2)
+        }
+    };
+    let (sex1, sex2) = (sex_of(&parent1), sex_of(&parent2));
+    if sex1 != Sex::Unknown && sex1 == sex2 {
+        return Err(MeiosimError::Config(format!("Parents {} and {} are of the same sex", parent1, parent2)));
+    }
+    if unrelated && metadata.are_related(&parent1, &parent2) {
+        return Err(MeiosimError::Config(format!("Parents {} and {} are related", parent1, parent2)));
+    }
+    Ok(Parents::new(&parent1, &parent2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{choose_parents, random_pairs, PairingConstraints, ParentChoice, SampleMetadata, Sex};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    const METADATA: &str = "\
+FamilyID SampleID FatherID MotherID Sex Population Superpopulation
+F1 A1 0 0 1 GBR EUR
+F1 A2 0 0 2 GBR EUR
+F1 A3 A1 A2 2 GBR EUR
+F2 B1 0 0 1 GBR EUR
+F3 C1 0 0 2 YRI AFR
+F4 C2 0 0 1 YRI AFR
+";
+
+    #[test]
+    fn test_random_pairs_constraints() {
+        let metadata = SampleMetadata::from_table(METADATA, "metadata.txt").unwrap();
+        assert!(metadata.are_related("A1", "A3"));
+        assert!(!metadata.are_related("A1", "B1"));
+
+        let panel: Vec<String> = ["A1", "A2", "A3", "B1", "C1", "C2"].iter().map(|s| s.to_string()).collect();
+        let constraints = PairingConstraints { same_superpopulation: true, unrelated: true, ..Default::default() };
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let families = random_pairs(&metadata, &panel, 2, constraints, &mut rng).unwrap();
+            for family in families {
+                let parent1 = metadata.get(&family.parents.parent1).unwrap();
+                let parent2 = metadata.get(&family.parents.parent2).unwrap();
+                assert_eq!(parent1.superpopulation, parent2.superpopulation);
+                assert!(!metadata.are_related(&parent1.sample, &parent2.sample));
+                assert_eq!((parent1.sex, parent2.sex), (Sex::Female, Sex::Male));
+            }
+        }
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(random_pairs(&metadata, &panel, 3, constraints, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_choose_parents() {
+        let metadata = SampleMetadata::from_table(METADATA, "metadata.txt").unwrap();
+        let panel: Vec<String> = ["A1", "A2", "A3", "B1", "C1", "C2"].iter().map(|s| s.to_string()).collect();
+        let query = |text: &str| ParentChoice::Query(text.parse().unwrap());
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let parents = choose_parents(&metadata, &panel,
+                &query("superpopulation=EUR"), &query("superpopulation=AFR"), false, &mut rng).unwrap();
+            assert!(["A2", "A3"].contains(&parents.parent1.as_str()));
+            assert_eq!(parents.parent2, "C2");
+
+            // the mother is drawn opposite to a fixed father
+            let parents = choose_parents(&metadata, &panel,
+                &query("population=GBR"), &ParentChoice::Sample("B1".to_string()), true, &mut rng).unwrap();
+            assert!(["A2", "A3"].contains(&parents.parent1.as_str()));
+        }
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(choose_parents(&metadata, &panel,
+            &query("population=GBR"), &ParentChoice::Sample("A1".to_string()), true, &mut rng).is_err());
+        assert!(choose_parents(&metadata, &panel,
+            &query("sex=male"), &query("sex=male"), false, &mut rng).is_err());
+        assert!("continent=EUR".parse::<super::SampleQuery>().is_err());
+    }
+}
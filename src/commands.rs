@@ -0,0 +1,1656 @@
+
+use crate::workflows::{wrk_generate_cohort, wrk_generate_tetraploid_family, wrk_sample_gamete_reads, wrk_write_genotypes, FamilyJob, PostzygoticSource};
+use crate::workflows::wrk_format_vcf;
+use crate::workflows::thread_pool;
+use crate::manifest::{AdmixtureSummary, CrossIndividual, CrossSummary, GameteRecord, InputFile, ParentRecord, PedigreeSummary, RecombinationMode, RunManifest, SiblingSummary};
+use crate::io::{EarlierOutputs, FamilyOut, GameteOut, IndividualOut};
+use crate::{CrossoverModel, MeiosisModel, Parents, RecombinationMapGenome, VCFCollection};
+use crate::variants::{GenotypePolicy, MissingPolicy, PhasingPolicy};
+use crate::filters::VariantFilter;
+use crate::regions::Regions;
+use crate::reference::{ReferenceCheck, ReferenceGenome, ReferencePolicy};
+use crate::dnm::{self, CollisionPolicy, DnmColumns};
+use crate::liftover::ChainFile;
+use crate::meiosis::{self, simulate_gamete, Gamete};
+use crate::error::{MeiosimError, Result};
+use crate::utils;
+use crate::utils::OutputFile;
+use crate::cohort::{self, FamilySpec, PairingConstraints, ParentChoice, SampleMetadata, Sex};
+use crate::config::SimulationConfig;
+use crate::admixture::{simulate_admixture, AdmixedPopulation, AdmixtureModel};
+use crate::founders::FounderGenome;
+use crate::embryo::{self, ChromosomeError, Embryo, EmbryoModel};
+use crate::pedigree::{Consanguinity, Pedigree};
+use crate::cross::{Cross as CrossSimulation, CrossDesign};
+use crate::ibd::{write_pairwise_ibd, PairRelatedness};
+use crate::workflows::SimulatedSibling;
+use crate::seeds::{self, SeedTree};
+
+use clap::{ArgGroup, Args};
+use log::{info, warn};
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+
+#[derive(Args)]
+pub struct Main  {
+    #[arg(short = 'c', long, value_name = "FILE", help = "Sets a TOML file with the simulation parameters, the other options override its values")]
+    config: Option<String>,
+    #[arg(long, help = "Sets the level of verbosity")]
+    verbose: bool,
+    #[arg(long, help = "Do recombination map use header?")]
+    recomheader: bool,
+    #[arg(short, long, value_name = "FOLDER", help = "Sets the folder path to recombination maps")]
+    recombination: Option<String>,
+    #[arg(short = 's', long, value_name = "CX", help = "Sets the number of crossovers for simple recombination, if not using recombination maps")]
+    simplerecombination: Option<u8>,
+    #[arg(short = 'v', long, value_name = "FOLDER", help = "Sets the folder path to VCF collection of population variants")]
+    population: Option<String>,
+    #[arg(short, long, value_name = "FOLDER", help = "Sets the folder path to VCF collection of denovo variants")]
+    denovo: Option<String>,
+    #[arg(short = 'p', long, value_name = "SAMPLE", help = "Sets the sample1 string option")]
+    parent1: Option<String>,
+    #[arg(short = 'P', long, value_name = "SAMPLE", help = "Sets the sample2 string option")]
+    parent2: Option<String>,
+    #[arg(long, value_name = "QUERY", conflicts_with = "parent1", help = "Draws parent1 from the samples matching the metadata, e.g. superpopulation=EUR,sex=female")]
+    parent1_query: Option<String>,
+    #[arg(long, value_name = "QUERY", conflicts_with = "parent2", help = "Draws parent2 from the samples matching the metadata, e.g. population=YRI")]
+    parent2_query: Option<String>,
+    #[arg(long, value_name = "FILE", help = "Sets the sample metadata (1kG ped population table), gives the sex and population of the parents")]
+    metadata: Option<String>,
+    #[arg(long, help = "Does not draw parents of the same pedigree")]
+    unrelated: bool,
+    #[arg(long, value_name = "SOURCES", conflicts_with_all = ["parent1", "parent2", "parent1_query", "parent2_query", "consanguinity", "pedigree"], help = "Uses admixed parents from random mating between populations of the metadata, e.g. EUR:0.8,AFR:0.2")]
+    admixture: Option<String>,
+    #[arg(long, value_name = "N", help = "Sets the generations of random mating of the admixed population [default: 8]")]
+    admixture_generations: Option<u32>,
+    #[arg(long, value_name = "N", help = "Sets the number of individuals per generation of the admixed population [default: 20]")]
+    admixture_size: Option<usize>,
+    #[arg(long, value_name = "UNION", conflicts_with_all = ["parent1", "parent2", "parent1_query", "parent2_query", "pedigree"], help = "Uses consanguineous parents simulated from random samples of the panel, first-cousins or uncle-niece")]
+    consanguinity: Option<String>,
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["parent1_query", "parent2_query"], help = "Sets a pedigree table (individual parent1 parent2, 0 for founders), the parents (-p, -P) are then members of it")]
+    pedigree: Option<String>,
+    #[arg(long, value_name = "PREFIX", help = "Sets the prefix string for the output")]
+    prefix: Option<String>,
+    #[arg(long, value_name = "POLICY", help = "Sets how unphased heterozygous genotypes of the panel are handled: refuse, random or phase-set (PS tag of read-backed phasing) [default: refuse]")]
+    unphased: Option<String>,
+    #[arg(long, value_name = "POLICY", help = "Sets how parental genotypes with a missing allele are handled: skip, ref, impute (from INFO/AF) or fail [default: skip]")]
+    missing: Option<String>,
+    #[command(flatten)]
+    filters: FilterArgs,
+    #[arg(long, value_name = "SEED", help = "Sets the seed")]
+    seed: Option<u64>,
+    #[arg(short = 'f', long, value_name = "SI  ZE", help = "Sets the family size of the generated family tree")]
+    familysize: Option<u8>,
+    #[arg(long, value_name = "N", help = "Adds an identical (MZ) co-twin to the first N siblings")]
+    mz_twins: Option<u8>,
+    #[arg(long, value_name = "N", help = "Adds a fraternal (DZ) co-twin to the N siblings after the MZ twins")]
+    dz_twins: Option<u8>,
+    #[arg(long, value_name = "N", help = "Sets the number of postzygotic mutations of each MZ twin [default: 5]")]
+    postzygotic: Option<usize>,
+    #[arg(long, value_name = "POLICY", help = "Sets what is done with a DNM at a site where the sibling carries an inherited variant: skip, resample (needs --reference) or combine [default: skip]")]
+    dnm_collision: Option<String>,
+    #[arg(long, value_name = "PARENT", help = "Simulates the meioses of the male or female parent without crossovers, as in Drosophila males, from the sexes of the sample metadata (--metadata), or of parent1 or parent2")]
+    achiasmatic: Option<String>,
+    #[arg(long, value_name = "PLOIDY", help = "Sets the ploidy of the parents and siblings, 2 or 4 for autotetraploids from a tetraploid panel [default: 2]")]
+    ploidy: Option<usize>,
+    #[arg(long, value_name = "DEPTH", help = "Simulates a low-coverage biopsy of each sibling as an IVF embryo, with this mean read depth")]
+    biopsy_coverage: Option<f64>,
+    #[arg(long, value_name = "RATE", help = "Sets the probability that a chromosome of an embryo is trisomic or monosomic [default: 0]")]
+    aneuploidy: Option<f64>,
+    #[arg(long, value_name = "RATE", help = "Sets the probability that an aneuploidy is mitotic, and found in part of the biopsy cells [default: 0]")]
+    mosaicism: Option<f64>,
+    #[arg(long, value_name = "RATE", help = "Sets the allele dropout rate of the heterozygous sites of the biopsies [default: 0]")]
+    dropout: Option<f64>,
+    #[arg(long, value_name = "RATE", help = "Sets the preferential amplification rate of the heterozygous sites of the biopsies [default: 0]")]
+    preferential_amplification: Option<f64>,
+    #[arg(short = 't', long, value_name = "THREADS", help = "Sets the number of threads, 0 uses all the available cores [default: 1]")]
+    threads: Option<usize>,
+    #[arg(short = 'g', long, value_name = "GENOME", help = "Sets the genome file or FASTA index (.fai), by default contig sizes are read from the VCF headers")]
+    genome: Option<String>,
+    #[arg(long, value_name = "REGIONS", help = "Restricts the simulation to regions, as chr:start-end (1-based, comma-separated) or a BED file")]
+    regions: Option<String>,
+    #[arg(long, value_name = "FASTA", help = "Checks the REF allele of the transmitted and de novo variants against a reference FASTA")]
+    reference: Option<String>,
+    #[arg(long, value_name = "POLICY", help = "Sets what is done with the transmitted and de novo variants whose REF is not the base of the reference: report, drop or fail [default: report]")]
+    reference_mismatch: Option<String>,
+}
+
+/// Parent given by name or drawn with a query, from the command line or
+/// else from the config.
+fn parent_choice(cli: (&Option<String>, &Option<String>),
+        config: (Option<String>, Option<String>),
+        role: &str) -> Result<ParentChoice> {
+    let (sample, query) = if cli.0.is_some() || cli.1.is_some() {
+        (cli.0.clone(), cli.1.clone())
+    } else {
+        config
+    };
+    match (sample, query) {
+        (Some(_), Some(_)) => Err(MeiosimError::Config(format!(
+            "Use either {} or {}_query in the --config file", role, role))),
+        (Some(sample), None) => Ok(ParentChoice::Sample(sample)),
+        (None, Some(query)) => Ok(ParentChoice::Query(query.parse()?)),
+        (None, None) => Err(MeiosimError::Config(format!(
+            "Missing --{} or --{}-query (or {} in [pedigree] of the --config file)", role, role, role))),
+    }
+}
+
+fn required<T: Clone>(cli: &Option<T>, config: Option<T>, flag: &str, key: &str) -> Result<T> {
+    cli.clone().or(config).ok_or_else(|| {
+        MeiosimError::Config(format!("Missing {} (or {} in the --config file)", flag, key))
+    })
+}
+
+/// How the parents of `meiosim main` are obtained.
+enum ParentSource {
+    /// samples of the panel, given by name or drawn from the metadata
+    Panel(ParentChoice, ParentChoice),
+    /// individuals of a simulated admixed population
+    Admixed(AdmixtureModel),
+    /// members of a pedigree table, simulated from its founders
+    Pedigree {
+        file: String,
+        parent1: String,
+        parent2: String,
+    },
+    /// couple of a consanguineous pedigree founded by samples of the panel
+    Consanguineous(Consanguinity),
+}
+
+/// Parents drawn from a simulated admixed population.
+struct AdmixedParents {
+    model: AdmixtureModel,
+    population: AdmixedPopulation,
+    genomes: [FounderGenome; 2],
+}
+
+impl AdmixedParents {
+    /// Writes the local ancestry of the siblings and summarizes the
+    /// ancestry of the family.
+    fn write_truth(&self, parents: &Parents, family: &FamilyOut, siblings: &[SimulatedSibling]) -> Result<AdmixtureSummary> {
+        let mut ancestry = BTreeMap::new();
+        ancestry.insert(parents.parent1.clone(), self.population.ancestry_proportions(&self.genomes[0]));
+        ancestry.insert(parents.parent2.clone(), self.population.ancestry_proportions(&self.genomes[1]));
+        for (sibling, sample) in siblings.iter().zip(family.samples.iter()) {
+            self.population.write_local_ancestry(&sample.targetancestryout, &sibling.genome)?;
+            ancestry.insert(sample.name.clone(), self.population.ancestry_proportions(&sibling.genome));
+        }
+        Ok(AdmixtureSummary {
+            model: self.model.clone(),
+            founders: self.population.founders.clone(),
+            ancestry,
+        })
+    }
+}
+
+/// Parents simulated as members of a pedigree.
+struct PedigreeParents {
+    consanguinity: Option<Consanguinity>,
+    pedigree: Pedigree,
+    genomes: [FounderGenome; 2],
+}
+
+impl PedigreeParents {
+    /// Writes the autozygous regions of the siblings and summarizes the
+    /// autozygosity of the family.
+    fn write_truth(&self, parents: &Parents, family: &FamilyOut, siblings: &[SimulatedSibling]) -> Result<PedigreeSummary> {
+        let mut autozygosity = BTreeMap::new();
+        autozygosity.insert(parents.parent1.clone(), self.genomes[0].autozygosity());
+        autozygosity.insert(parents.parent2.clone(), self.genomes[1].autozygosity());
+        for (sibling, sample) in siblings.iter().zip(family.samples.iter()) {
+            sibling.genome.write_autozygosity(&sample.targetautozygosityout)?;
+            autozygosity.insert(sample.name.clone(), sibling.genome.autozygosity());
+        }
+        Ok(PedigreeSummary {
+            consanguinity: self.consanguinity,
+            members: self.pedigree.members().to_vec(),
+            autozygosity,
+        })
+    }
+}
+
+/// Parents that are simulated individuals rather than samples of the panel.
+enum SimulatedParents {
+    Admixed(AdmixedParents),
+    Pedigree(PedigreeParents),
+}
+
+impl SimulatedParents {
+    fn genomes(&self) -> &[FounderGenome; 2] {
+        match self {
+            SimulatedParents::Admixed(admixed) => &admixed.genomes,
+            SimulatedParents::Pedigree(pedigree) => &pedigree.genomes,
+        }
+    }
+}
+
+/// Parameters of `meiosim main`, from the command line over the config file.
+struct Settings {
+    config: Option<String>,
+    verbose: bool,
+    recomheader: bool,
+    recombination: Option<String>,
+    simplerecombination: Option<u8>,
+    population: String,
+    denovo: String,
+    parents: ParentSource,
+    metadata: Option<String>,
+    unrelated: bool,
+    prefix: String,
+    seed: u64,
+    familysize: u8,
+    mz_twins: u8,
+    dz_twins: u8,
+    postzygotic: usize,
+    dnm_collision: CollisionPolicy,
+    achiasmatic: Option<String>,
+    ploidy: usize,
+    embryo: Option<EmbryoModel>,
+    unphased: String,
+    missing: String,
+    filters: FilterArgs,
+    threads: usize,
+    genome: Option<String>,
+    regions: Option<String>,
+    reference: Option<String>,
+    reference_mismatch: String,
+}
+
+impl Settings {
+    fn resolve(opts: &Main) -> Result<Self> {
+        let config = match &opts.config {
+            Some(config_file) => SimulationConfig::from_path(config_file)?,
+            None => SimulationConfig::default(),
+        };
+        // the recombination model is taken as a whole, either from the
+        // command line or from the config
+        let (recombination, simplerecombination, recomheader) =
+            if opts.recombination.is_some() || opts.simplerecombination.is_some() {
+                (opts.recombination.clone(), opts.simplerecombination, opts.recomheader)
+            } else {
+                (config.recombination.maps,
+                 config.recombination.simple,
+                 opts.recomheader || config.recombination.header.unwrap_or(false))
+            };
+        // parents given on the command line override the admixture and the
+        // consanguinity of the config, and the other way around
+        let cli_parents = [&opts.parent1, &opts.parent2, &opts.parent1_query, &opts.parent2_query]
+            .iter()
+            .any(|parent| parent.is_some());
+        let config_parents = [&config.pedigree.parent1, &config.pedigree.parent2, &config.pedigree.parent1_query, &config.pedigree.parent2_query]
+            .iter()
+            .any(|parent| parent.is_some());
+        let cli_simulated = opts.admixture.is_some() || opts.consanguinity.is_some() || opts.pedigree.is_some();
+        let (admixture, consanguinity, pedigree_file) = match (cli_simulated, cli_parents) {
+            (true, _) => (opts.admixture.clone(), opts.consanguinity.clone(), opts.pedigree.clone()),
+            (false, false) => (config.admixture.sources.clone(), config.pedigree.consanguinity.clone(), config.pedigree.file.clone()),
+            (false, true) => (None, None, config.pedigree.file.clone()),
+        };
+        if [admixture.is_some(), consanguinity.is_some(), pedigree_file.is_some()].iter().filter(|given| **given).count() > 1 {
+            return Err(MeiosimError::Config(
+                "Use only one of [admixture], consanguinity and file in [pedigree] in the --config file".to_string()));
+        }
+        if (admixture.is_some() || consanguinity.is_some()) && !cli_simulated && config_parents {
+            return Err(MeiosimError::Config(
+                "Use either [admixture], consanguinity or the parents in [pedigree] in the --config file".to_string()));
+        }
+        let parents = match (admixture, consanguinity, pedigree_file) {
+            (Some(sources), _, _) => ParentSource::Admixed(AdmixtureModel::new(sources.parse()?,
+                opts.admixture_generations.or(config.admixture.generations).unwrap_or(8),
+                opts.admixture_size.or(config.admixture.size).unwrap_or(20))?),
+            (None, Some(consanguinity), _) => ParentSource::Consanguineous(consanguinity.parse()?),
+            (None, None, pedigree_file) => {
+                let parent1 = parent_choice((&opts.parent1, &opts.parent1_query),
+                    (config.pedigree.parent1, config.pedigree.parent1_query), "parent1")?;
+                let parent2 = parent_choice((&opts.parent2, &opts.parent2_query),
+                    (config.pedigree.parent2, config.pedigree.parent2_query), "parent2")?;
+                match (pedigree_file, parent1, parent2) {
+                    (None, parent1, parent2) => ParentSource::Panel(parent1, parent2),
+                    (Some(file), ParentChoice::Sample(parent1), ParentChoice::Sample(parent2)) => {
+                        ParentSource::Pedigree { file, parent1, parent2 }
+                    },
+                    (Some(_), _, _) => {
+                        return Err(MeiosimError::Config(
+                            "The parents of a --pedigree are members given by name, not queries".to_string()));
+                    },
+                }
+            },
+        };
+
+        let mz_twins = opts.mz_twins.or(config.pedigree.mz_twins).unwrap_or(0);
+        let dz_twins = opts.dz_twins.or(config.pedigree.dz_twins).unwrap_or(0);
+        let ploidy = opts.ploidy.or(config.pedigree.ploidy).unwrap_or(2);
+        match ploidy {
+            2 => {},
+            4 if matches!(parents, ParentSource::Panel(..)) && mz_twins == 0 && dz_twins == 0 => {},
+            4 => {
+                return Err(MeiosimError::Config(
+                    "Tetraploid families need two samples of the panel as parents, and no twins".to_string()));
+            },
+            _ => return Err(MeiosimError::Config(format!("Ploidy {} is not supported, use 2 or 4", ploidy))),
+        }
+        let unphased = opts.unphased.clone().or(config.inputs.unphased).unwrap_or_else(|| "refuse".to_string());
+        unphased.parse::<PhasingPolicy>()?;
+        let missing = opts.missing.clone().or(config.inputs.missing).unwrap_or_else(|| "skip".to_string());
+        missing.parse::<MissingPolicy>()?;
+        let reference_mismatch = opts.reference_mismatch.clone().or(config.inputs.reference_mismatch).unwrap_or_else(|| "report".to_string());
+        reference_mismatch.parse::<ReferencePolicy>()?;
+        let dnm_collision = match opts.dnm_collision.clone().or(config.mutation.collision) {
+            Some(policy) => policy.parse()?,
+            None => CollisionPolicy::default(),
+        };
+        if dnm_collision == CollisionPolicy::Combine && ploidy != 2 {
+            return Err(MeiosimError::Config("DNM collisions of tetraploid siblings are skipped or resampled, not combined".to_string()));
+        }
+        let embryo = match opts.biopsy_coverage.or(config.embryo.coverage) {
+            Some(_) if ploidy != 2 => {
+                return Err(MeiosimError::Config("Embryo biopsies are only simulated for diploid siblings".to_string()));
+            },
+            Some(coverage) => Some(EmbryoModel::new(opts.aneuploidy.or(config.embryo.aneuploidy).unwrap_or(0.0),
+                opts.mosaicism.or(config.embryo.mosaicism).unwrap_or(0.0),
+                opts.dropout.or(config.embryo.dropout).unwrap_or(0.0),
+                opts.preferential_amplification.or(config.embryo.preferential_amplification).unwrap_or(0.0),
+                coverage)?),
+            None => None,
+        };
+
+        Ok(Self {
+            config: opts.config.clone(),
+            verbose: opts.verbose || config.verbose.unwrap_or(false),
+            recomheader,
+            recombination,
+            simplerecombination,
+            population: required(&opts.population, config.inputs.population, "--population (-v)", "population in [inputs]")?,
+            denovo: required(&opts.denovo, config.mutation.denovo, "--denovo (-d)", "denovo in [mutation]")?,
+            parents,
+            metadata: opts.metadata.clone().or(config.inputs.metadata),
+            unrelated: opts.unrelated || config.pedigree.unrelated.unwrap_or(false),
+            prefix: required(&opts.prefix, config.outputs.prefix, "--prefix", "prefix in [outputs]")?,
+            seed: required(&opts.seed, config.seed, "--seed", "seed")?,
+            familysize: required(&opts.familysize, config.pedigree.siblings, "--familysize (-f)", "siblings in [pedigree]")?,
+            mz_twins,
+            dz_twins,
+            postzygotic: opts.postzygotic.or(config.mutation.postzygotic).unwrap_or(5),
+            dnm_collision,
+            achiasmatic: opts.achiasmatic.clone().or(config.recombination.achiasmatic),
+            ploidy,
+            embryo,
+            unphased,
+            missing,
+            filters: FilterArgs {
+                pass_only: opts.filters.pass_only || config.filters.pass_only.unwrap_or(false),
+                min_af: opts.filters.min_af.or(config.filters.min_af),
+                max_af: opts.filters.max_af.or(config.filters.max_af),
+                variant_type: opts.filters.variant_type.clone().or(config.filters.variant_type),
+                include_regions: opts.filters.include_regions.clone().or(config.filters.include_regions),
+                exclude_regions: opts.filters.exclude_regions.clone().or(config.filters.exclude_regions),
+            },
+            threads: opts.threads.or(config.threads).unwrap_or(1),
+            genome: opts.genome.clone().or(config.inputs.genome),
+            regions: opts.regions.clone().or(config.inputs.regions),
+            reference: opts.reference.clone().or(config.inputs.reference),
+            reference_mismatch,
+        })
+    }
+
+
+    /// Resolves the parents, drawing the ones given by a query with the
+    /// `PARENT_CHOICE` stream. The metadata of the parents is recorded when
+    /// a metadata table is given.
+    fn choose_parents(&self,
+            parent1: &ParentChoice,
+            parent2: &ParentChoice,
+            popvars: &VCFCollection,
+            seeds: &SeedTree) -> Result<(Parents, Vec<ParentRecord>)> {
+        let metadata = match (&self.metadata, parent1, parent2) {
+            (Some(metadata_file), _, _) => SampleMetadata::from_path(metadata_file)?,
+            (None, ParentChoice::Sample(parent1), ParentChoice::Sample(parent2)) if !self.unrelated => {
+                return Ok((Parents::new(parent1, parent2), Vec::new()));
+            },
+            (None, _, _) => {
+                return Err(MeiosimError::Config(
+                    "--parent1-query, --parent2-query and --unrelated need the sample metadata (--metadata)".to_string()));
+            }
+        };
+        let parents = cohort::choose_parents(&metadata,
+            &popvars.samples(),
+            parent1,
+            parent2,
+            self.unrelated,
+            &mut seeds.stream(&[seeds::PARENT_CHOICE]))?;
+        let records = vec![
+            ParentRecord::new("parent1", &parents.parent1, parent1, &metadata),
+            ParentRecord::new("parent2", &parents.parent2, parent2, &metadata),
+        ];
+        for record in records.iter().filter(|record| record.query.is_some()) {
+            info!("Drew {} {} for {}", record.role, record.sample, record.query.as_deref().unwrap_or_default());
+        }
+        Ok((parents, records))
+    }
+
+    /// Simulates the admixed population and draws the two parents from its
+    /// last generation, with streams derived from the `ADMIXTURE` branch of
+    /// the seeds.
+    fn simulate_admixed_parents(&self, admixture: &AdmixtureModel, inputs: &Inputs, seeds: &SeedTree) -> Result<AdmixedParents> {
+        let metadata_file = self.metadata.as_ref().ok_or_else(|| {
+            MeiosimError::Config("--admixture needs the sample metadata (--metadata)".to_string())
+        })?;
+        let metadata = SampleMetadata::from_path(metadata_file)?;
+        let input_opts = self.input_options();
+        let admixture_seeds = seeds.child(seeds::ADMIXTURE);
+        info!("Simulating {} generations of admixture between {}",
+            admixture.generations,
+            admixture.sources.0.iter().map(|source| source.label.as_str()).collect::<Vec<_>>().join(", "));
+        let population = thread_pool(self.threads)?.install(|| {
+            simulate_admixture(admixture,
+                &metadata,
+                &inputs.popvars.samples(),
+                inputs.model(&input_opts),
+                &inputs.popvars.chromosomes(),
+                &inputs.genome_hash,
+                &admixture_seeds)
+        })?;
+        let genomes = population.choose_parents(&admixture_seeds);
+        Ok(AdmixedParents {
+            model: admixture.clone(),
+            population,
+            genomes,
+        })
+    }
+
+    /// Simulates the members of `pedigree` down to the two parents, with
+    /// streams derived from the `PEDIGREE` branch of the seeds.
+    fn simulate_pedigree_parents(&self,
+            pedigree: Pedigree,
+            consanguinity: Option<Consanguinity>,
+            parents: &Parents,
+            inputs: &Inputs,
+            seeds: &SeedTree) -> Result<PedigreeParents> {
+        inputs.popvars.check_samples(&pedigree.founders())?;
+        let input_opts = self.input_options();
+        info!("Simulating a pedigree of {} members founded by {}",
+            pedigree.members().len(),
+            pedigree.founders().join(", "));
+        let mut genomes = pedigree.simulate(inputs.model(&input_opts),
+            &inputs.popvars.chromosomes(),
+            &inputs.genome_hash,
+            &seeds.child(seeds::PEDIGREE))?;
+        let mut parent_genome = |parent: &str| genomes.remove(parent).ok_or_else(|| {
+            MeiosimError::Config(format!("Parent {} is not a member of the pedigree", parent))
+        });
+        let genomes = [parent_genome(&parents.parent1)?, parent_genome(&parents.parent2)?];
+        Ok(PedigreeParents {
+            consanguinity,
+            pedigree,
+            genomes,
+        })
+    }
+
+    fn input_options(&self) -> InputOptions<'_> {
+        InputOptions {
+            verbose: self.verbose,
+            recomheader: self.recomheader,
+            recombination: self.recombination.as_ref(),
+            simplerecombination: self.simplerecombination,
+            population: &self.population,
+            denovo: Some(&self.denovo),
+            genome: self.genome.as_ref(),
+            regions: self.regions.as_ref(),
+            reference: self.reference.as_ref(),
+            reference_mismatch: &self.reference_mismatch,
+            unphased: &self.unphased,
+            missing: &self.missing,
+            filters: &self.filters,
+            seed: self.seed,
+        }
+    }
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("families").required(true).args(["pairs", "random_pairs"])))]
+pub struct Batch  {
+    #[arg(short, long, value_name = "FOLDER", help = "Sets the folder path to VCF collection of denovo variants")]
+    denovo: String,
+    #[arg(long, value_name = "POLICY", default_value = "skip", help = "Sets what is done with a DNM at a site where the sibling carries an inherited variant: skip, resample (needs --reference) or combine")]
+    dnm_collision: String,
+    #[arg(long, value_name = "FILE", help = "Sets a table of parent pairs, one family per line (parent1 parent2 [family_id])")]
+    pairs: Option<String>,
+    #[arg(long, value_name = "N", help = "Sets the number of families to simulate from randomly paired samples")]
+    random_pairs: Option<usize>,
+    #[arg(long, value_name = "FILE", help = "Sets the sample metadata (1kG ped population table) used by --random-pairs, gives the sex and population of the parents")]
+    metadata: Option<String>,
+    #[arg(long, help = "Pairs samples of the same population")]
+    same_population: bool,
+    #[arg(long, help = "Pairs samples of the same superpopulation")]
+    same_superpopulation: bool,
+    #[arg(long, help = "Does not pair samples of the same pedigree")]
+    unrelated: bool,
+    #[arg(long, value_name = "PARENT", help = "Simulates the meioses of the male or female parent of each family without crossovers, from the sexes of the sample metadata (--metadata), or of parent1 or parent2")]
+    achiasmatic: Option<String>,
+    #[arg(long, value_name = "PREFIX", help = "Sets the output folder, with one folder per family")]
+    prefix: String,
+    #[arg(short = 'f', long, value_name = "SIZE", help = "Sets the number of siblings per family")]
+    familysize: u8,
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args)]
+pub struct Cross  {
+    #[arg(short = 'p', long, value_name = "SAMPLE", help = "Sets the first parent (P1), the recurrent parent of backcrosses and the selfed parent")]
+    parent1: String,
+    #[arg(short = 'P', long, value_name = "SAMPLE", help = "Sets the second parent (P2), not used by selfing")]
+    parent2: Option<String>,
+    #[arg(long, value_name = "DESIGN", help = "Sets the cross design: selfing, backcross, f2 or ril")]
+    design: String,
+    #[arg(long, value_name = "N", default_value_t = 10, help = "Sets the number of offspring, or of lines for RILs")]
+    size: usize,
+    #[arg(long, value_name = "N", default_value_t = 7, help = "Sets the generations of inbreeding after the F1 for RILs")]
+    generations: u32,
+    #[arg(long, value_name = "MATING", default_value = "self", help = "Sets the mating of the F1 and of the RIL generations: self or sib")]
+    mating: String,
+    #[arg(long, value_name = "PREFIX", help = "Sets the output folder")]
+    prefix: String,
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args)]
+pub struct Gametes  {
+    #[arg(short = 'p', long, value_name = "SAMPLE", help = "Sets the donor, the sample the gametes come from")]
+    donor: String,
+    #[arg(short = 'n', long, value_name = "N", default_value_t = 10, help = "Sets the number of gametes")]
+    count: usize,
+    #[arg(long, value_name = "DEPTH", help = "Samples reads of each gamete at the heterozygous sites of the donor, with this mean depth (e.g. 0.05 for low-coverage single cells)")]
+    coverage: Option<f64>,
+    #[arg(long, value_name = "PREFIX", help = "Sets the output folder")]
+    prefix: String,
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args)]
+pub struct Vcf2dwgsim  {
+    #[arg(long, help = "Sets the level of verbosity")]
+    verbose: bool,
+    #[arg(short, long, value_name = "FILE", help = "VCF to convert to DWGSIM format")]
+    vcf: String,
+    #[arg(long, value_name = "PREFIX", help = "Sets the prefix string for the output")]
+    prefix: String,
+    #[arg(long, value_name = "POLICY", default_value = "refuse", help = "Sets how unphased heterozygous genotypes of the panel are handled: refuse, random or phase-set (PS tag of read-backed phasing)")]
+    unphased: String,
+    #[arg(long, value_name = "SEED", default_value_t = 0, help = "Sets the seed of the random phases")]
+    seed: u64,
+}
+
+#[derive(Args)]
+pub struct PrepareDnm  {
+    #[arg(long, help = "Sets the level of verbosity")]
+    verbose: bool,
+    #[arg(short, long, value_name = "FILE", help = "DNM catalogue, a VCF or a tab, comma or space separated table with a header")]
+    input: String,
+    #[arg(long, value_name = "FOLDER", help = "Sets the folder of the per-proband VCFs, to use with --denovo")]
+    prefix: String,
+    #[arg(long, value_name = "FILE", help = "Lifts the DNMs over to another build with a UCSC chain file")]
+    chain: Option<String>,
+    #[arg(long, value_name = "FASTA", help = "Drops the DNMs whose REF is not the base of this reference (of the target build)")]
+    reference: Option<String>,
+    #[arg(long, value_name = "COLUMN", default_value = "Proband_id", help = "Column of the table (or INFO field of the VCF) with the proband")]
+    proband_column: String,
+    #[arg(long, value_name = "COLUMN", default_value = "Chr", help = "Column of the table with the chromosome")]
+    chr_column: String,
+    #[arg(long, value_name = "COLUMN", default_value = "Pos", help = "Column of the table with the position")]
+    pos_column: String,
+    #[arg(long, value_name = "COLUMN", default_value = "Ref", help = "Column of the table with the REF allele")]
+    ref_column: String,
+    #[arg(long, value_name = "COLUMN", default_value = "Alt", help = "Column of the table with the ALT allele")]
+    alt_column: String,
+    #[arg(long, help = "The positions of the table are 0-based, as in a BED file")]
+    zero_based: bool,
+}
+
+/// Inputs shared by `batch`, `cross` and `gametes`.
+#[derive(Args)]
+struct InputArgs {
+    #[arg(long, help = "Sets the level of verbosity")]
+    verbose: bool,
+    #[arg(long, help = "Do recombination map use header?")]
+    recomheader: bool,
+    #[arg(short, long, value_name = "FOLDER", help = "Sets the folder path to recombination maps")]
+    recombination: Option<String>,
+    #[arg(short = 's', long, value_name = "CX", help = "Sets the number of crossovers for simple recombination, if not using recombination maps")]
+    simplerecombination: Option<u8>,
+    #[arg(short = 'v', long, value_name = "FOLDER", help = "Sets the folder path to VCF collection of population variants")]
+    population: String,
+    #[arg(long, value_name = "POLICY", default_value = "refuse", help = "Sets how unphased heterozygous genotypes of the panel are handled: refuse, random or phase-set (PS tag of read-backed phasing)")]
+    unphased: String,
+    #[arg(long, value_name = "POLICY", default_value = "skip", help = "Sets how parental genotypes with a missing allele are handled: skip, ref, impute (from INFO/AF) or fail")]
+    missing: String,
+    #[command(flatten)]
+    filters: FilterArgs,
+    #[arg(long, value_name = "SEED", help = "Sets the seed")]
+    seed: u64,
+    #[arg(short = 't', long, value_name = "THREADS", default_value_t = 1, help = "Sets the number of threads, 0 uses all the available cores")]
+    threads: usize,
+    #[arg(short = 'g', long, value_name = "GENOME", help = "Sets the genome file or FASTA index (.fai), by default contig sizes are read from the VCF headers")]
+    genome: Option<String>,
+    #[arg(long, value_name = "REGIONS", help = "Restricts the simulation to regions, as chr:start-end (1-based, comma-separated) or a BED file")]
+    regions: Option<String>,
+    #[arg(long, value_name = "FASTA", help = "Checks the REF allele of the transmitted and de novo variants against a reference FASTA")]
+    reference: Option<String>,
+    #[arg(long, value_name = "POLICY", default_value = "report", help = "Sets what is done with the transmitted and de novo variants whose REF is not the base of the reference: report, drop or fail")]
+    reference_mismatch: String,
+}
+
+impl InputArgs {
+    fn input_options<'a>(&'a self, denovo: Option<&'a str>) -> InputOptions<'a> {
+        InputOptions {
+            verbose: self.verbose,
+            recomheader: self.recomheader,
+            recombination: self.recombination.as_ref(),
+            simplerecombination: self.simplerecombination,
+            population: &self.population,
+            denovo,
+            genome: self.genome.as_ref(),
+            regions: self.regions.as_ref(),
+            reference: self.reference.as_ref(),
+            reference_mismatch: &self.reference_mismatch,
+            unphased: &self.unphased,
+            missing: &self.missing,
+            filters: &self.filters,
+            seed: self.seed,
+        }
+    }
+}
+
+/// Filters of the population variants transmitted to the children.
+#[derive(Args, Clone, Default)]
+struct FilterArgs {
+    #[arg(long, help = "Only transmits the variants of the panel with FILTER PASS")]
+    pass_only: bool,
+    #[arg(long, value_name = "AF", help = "Only transmits the variants with an INFO/AF of at least AF")]
+    min_af: Option<f64>,
+    #[arg(long, value_name = "AF", help = "Only transmits the variants with an INFO/AF of at most AF")]
+    max_af: Option<f64>,
+    #[arg(long, value_name = "TYPE", help = "Only transmits the SNVs of this type: snv, transition or transversion")]
+    variant_type: Option<String>,
+    #[arg(long, value_name = "BED", help = "Only transmits the variants in the regions of a BED file")]
+    include_regions: Option<String>,
+    #[arg(long, value_name = "BED", help = "Does not transmit the variants in the regions of a BED file")]
+    exclude_regions: Option<String>,
+}
+
+impl FilterArgs {
+    fn variant_filter(&self) -> Result<VariantFilter> {
+        let regions = |bed: &Option<String>| bed.as_deref().map(Regions::from_bed).transpose();
+        VariantFilter::new(self.pass_only,
+            self.min_af,
+            self.max_af,
+            self.variant_type.as_deref().map(str::parse).transpose()?,
+            regions(&self.include_regions)?,
+            regions(&self.exclude_regions)?)
+    }
+}
+
+pub fn run_main(opts: &Main) -> Result<()> {
+    let opts = &Settings::resolve(opts)?;
+    let verbose = opts.verbose;
+    let prefix = &opts.prefix;
+
+    let inputs = load_inputs(&opts.input_options())?;
+    let seeds = SeedTree::new(opts.seed);
+    let (parents, parent_metadata, simulated) = match &opts.parents {
+        ParentSource::Panel(parent1, parent2) => {
+            let (parents, parent_metadata) = opts.choose_parents(parent1, parent2, &inputs.popvars, &seeds)?;
+            (parents, parent_metadata, None)
+        },
+        ParentSource::Admixed(admixture) => {
+            let admixed = opts.simulate_admixed_parents(admixture, &inputs, &seeds)?;
+            (Parents::new("admixed1", "admixed2"), Vec::new(), Some(SimulatedParents::Admixed(admixed)))
+        },
+        ParentSource::Pedigree { file, parent1, parent2 } => {
+            let parents = Parents::new(parent1, parent2);
+            let pedigree = opts.simulate_pedigree_parents(Pedigree::from_path(file)?, None, &parents, &inputs, &seeds)?;
+            (parents, Vec::new(), Some(SimulatedParents::Pedigree(pedigree)))
+        },
+        ParentSource::Consanguineous(consanguinity) => {
+            // founders are drawn at random from the panel
+            let mut founders = inputs.popvars.samples();
+            if founders.len() < consanguinity.founders() {
+                return Err(MeiosimError::input(&opts.population,
+                    &format!("{} samples are needed to found the pedigree, {} found", consanguinity.founders(), founders.len())));
+            }
+            founders.shuffle(&mut seeds.child(seeds::PEDIGREE).stream(&[seeds::FOUNDERS]));
+            founders.truncate(consanguinity.founders());
+            let (pedigree, parents) = Pedigree::consanguineous(*consanguinity, &founders)?;
+            let pedigree = opts.simulate_pedigree_parents(pedigree, Some(*consanguinity), &parents, &inputs, &seeds)?;
+            (parents, Vec::new(), Some(SimulatedParents::Pedigree(pedigree)))
+        },
+    };
+    if verbose {
+        info!("Parent1: {}", parents.parent1);
+        info!("Parent2: {}", parents.parent2);
+        info!("Prefix: {}", prefix);
+    }
+
+    let achiasmatic = resolve_achiasmatic(opts.achiasmatic.as_deref(), &parent_metadata)?;
+
+    // Generate the family out:
+    let mut family = FamilyOut::new(prefix, &parents.parent1, &parents.parent2, opts.familysize, verbose)?
+        .with_twins(opts.mz_twins, opts.dz_twins)?;
+    family.set_aside_earlier_outputs()?;
+    if verbose {
+        family.samples.iter().for_each(|s| {
+            info!("{} -> {}", s.name, s.targetvcfout);
+        });
+    }
+
+    let result = simulate_family(opts, &inputs, &family, &parents, parent_metadata, achiasmatic, simulated.as_ref());
+    match result {
+        Ok(_) => family.keep_outputs(),
+        Err(_) => family.remove_outputs(),
+    }
+    result
+}
+
+/// Options used to load the inputs, shared by `main` and `batch`.
+struct InputOptions<'a> {
+    verbose: bool,
+    recomheader: bool,
+    recombination: Option<&'a String>,
+    simplerecombination: Option<u8>,
+    population: &'a str,
+    /// folder of DNM VCFs, not used by crosses
+    denovo: Option<&'a str>,
+    genome: Option<&'a String>,
+    /// regions the simulation is restricted to
+    regions: Option<&'a String>,
+    /// reference FASTA the REF alleles are checked against, with what is
+    /// done with the mismatches
+    reference: Option<&'a String>,
+    reference_mismatch: &'a str,
+    /// phasing and missing genotype policies of the panel, with the run
+    /// seed for random phases and imputed alleles
+    unphased: &'a str,
+    missing: &'a str,
+    filters: &'a FilterArgs,
+    seed: u64,
+}
+
+/// Inputs of a simulation, loaded and checked against each other.
+struct Inputs {
+    genome_recomb_map: Option<RecombinationMapGenome>,
+    popvars: VCFCollection,
+    genome_hash: HashMap<String, u64>,
+    dnm_files: Vec<String>,
+}
+
+fn load_inputs(opts: &InputOptions) -> Result<Inputs> {
+    let verbose = opts.verbose;
+    if verbose {
+        info!("Population variants folder: {}", opts.population);
+        info!("Denovo variants folder: {}", opts.denovo.unwrap_or("none"));
+    }
+
+    let mut genome_recomb_map = match opts.recombination {
+        Some(recomb_maps) => {
+            // load recombination maps
+            info!("Recombination maps folder: {}", recomb_maps);
+            let genome_recomb_map = RecombinationMapGenome::from_path(
+                    recomb_maps,
+                    "map",
+                    opts.recomheader)?;
+            Some(genome_recomb_map)
+        },
+        None => {
+            if verbose {
+                info!("Using simple recombination mode, no recombination map supplied.");
+            }
+            None
+        }
+    };
+
+    match  opts.simplerecombination {
+        Some(simple_recombination) => {
+            if verbose {
+                info!("Simple recombination: {}", simple_recombination);
+            }
+        },
+        None => {
+            if verbose {
+                info!("Simple recombination: None");
+            }
+        }
+    }
+    if genome_recomb_map.is_some() == opts.simplerecombination.is_some() {
+        return Err(MeiosimError::Config("Use either recombination maps (-r) or simple recombination (-s)".to_string()));
+    }
+
+    let mut popvars = VCFCollection::from_path(opts.population, "gz", verbose)?;
+    popvars.set_genotype_policy(GenotypePolicy::new(opts.unphased.parse()?, opts.missing.parse()?, SeedTree::new(opts.seed)));
+    popvars.set_variant_filter(opts.filters.variant_filter()?);
+    let genome_hash = match opts.genome {
+        Some(genome_file) => {
+            info!("Contig sizes from: {}", genome_file);
+            let genome_hash = utils::read_genome_file(genome_file)?;
+            popvars.flag_contig_mismatches(&genome_hash);
+            genome_hash
+        },
+        None => {
+            info!("Contig sizes from the population VCF headers");
+            popvars.contig_sizes()?
+        }
+    };
+    if let Some(genome_recomb_map) = &genome_recomb_map {
+        genome_recomb_map.flag_outside_contigs(&genome_hash);
+    }
+    popvars.flag_records_outside_contigs(&genome_hash)?;
+    if let Some(spec) = opts.regions {
+        let regions = Regions::from_spec(spec)?.within(&genome_hash);
+        if regions.is_empty() {
+            return Err(MeiosimError::Config(format!("The regions {} are beyond the ends of the contigs", spec)));
+        }
+        info!("Simulation restricted to {} bp of {} contigs",
+            regions.contigs().map(|chr| regions.length(chr)).sum::<u64>(), regions.contigs().count());
+        genome_recomb_map = genome_recomb_map.map(|genome_recomb_map| genome_recomb_map.restrict(&regions));
+        popvars.set_regions(regions)?;
+    }
+    if let Some(fasta) = opts.reference {
+        info!("Checking the reference alleles against {}", fasta);
+        let reference = ReferenceCheck::new(fasta, opts.reference_mismatch.parse()?, &popvars.chromosomes())?;
+        popvars.set_reference_check(reference);
+    }
+
+    let dnm_files = match opts.denovo {
+        Some(denovo) => utils::list_files_in_directory(denovo, "vcf")?,
+        None => Vec::new(),
+    };
+
+    Ok(Inputs {
+        genome_recomb_map,
+        popvars,
+        genome_hash,
+        dnm_files,
+    })
+}
+
+impl Inputs {
+    fn model(&self, opts: &InputOptions) -> CrossoverModel<'_> {
+        match (&self.genome_recomb_map, opts.simplerecombination) {
+            (Some(genome_recomb_map), _) => CrossoverModel::Map(genome_recomb_map),
+            (None, simple_recombination) => match self.popvars.regions() {
+                Some(regions) => CrossoverModel::SimpleInRegions(simple_recombination.unwrap_or_default(), regions),
+                None => CrossoverModel::Simple(simple_recombination.unwrap_or_default()),
+            },
+        }
+    }
+
+    /// DNM files for the siblings of a family. All the files are shuffled
+    /// and the first ones taken, so that each sibling gets the same file
+    /// whatever the family size.
+    fn select_dnm_files(&self, opts: &InputOptions, siblings: usize, seeds: &SeedTree) -> Result<Vec<String>> {
+        if self.dnm_files.len() < siblings {
+            return Err(MeiosimError::input(opts.denovo.unwrap_or_default(),
+                &format!("{} DNM files found but {} are needed, one per sibling", self.dnm_files.len(), siblings)));
+        }
+        let mut selected_dnm_files = self.dnm_files.clone();
+        selected_dnm_files.shuffle(&mut seeds.stream(&[seeds::DNM_CHOICE]));
+        selected_dnm_files.truncate(siblings);
+        Ok(selected_dnm_files)
+    }
+
+    /// DNM files the postzygotic mutations of the MZ twins of the family
+    /// are drawn from, one per twin among the files the family does not
+    /// use.
+    fn select_postzygotic_files(&self,
+            opts: &InputOptions,
+            family: &FamilyOut,
+            family_dnm_files: &[String],
+            count: usize,
+            seeds: &SeedTree) -> Result<Vec<Option<PostzygoticSource>>> {
+        let twins = family.samples.iter().filter(|sample| sample.is_monozygotic_twin()).count();
+        if twins == 0 {
+            return Ok(Vec::new());
+        }
+        let mut unused: Vec<&String> = self.dnm_files.iter()
+            .filter(|dnm_file| !family_dnm_files.contains(dnm_file))
+            .collect();
+        if unused.len() < twins {
+            return Err(MeiosimError::input(opts.denovo.unwrap_or_default(),
+                &format!("{} DNM files are not used by the siblings but {} are needed, one per MZ twin", unused.len(), twins)));
+        }
+        unused.shuffle(&mut seeds.stream(&[seeds::POSTZYGOTIC]));
+        let mut unused = unused.into_iter();
+        Ok(family.samples.iter()
+            .map(|sample| match sample.is_monozygotic_twin() {
+                true => unused.next().map(|file| PostzygoticSource { file: file.clone(), count }),
+                false => None,
+            })
+            .collect())
+    }
+
+    /// Input files used by every family, with their role in the manifest.
+    fn manifest_files(&self, opts: &InputOptions) -> Result<Vec<(&'static str, String)>> {
+        let mut input_files = Vec::new();
+        if let Some(recomb_maps) = opts.recombination {
+            for map_file in utils::list_files_in_directory(recomb_maps, "map")? {
+                input_files.push(("recombination_map", map_file));
+            }
+        }
+        for chr in self.popvars.chromosomes() {
+            input_files.push(("population_vcf", self.popvars.vcfs[&chr].file_path.clone()));
+        }
+        if let Some(genome_file) = opts.genome {
+            input_files.push(("genome", genome_file.clone()));
+        }
+        if let Some(bed) = &opts.filters.include_regions {
+            input_files.push(("include_regions", bed.clone()));
+        }
+        if let Some(bed) = &opts.filters.exclude_regions {
+            input_files.push(("exclude_regions", bed.clone()));
+        }
+        if let Some(bed) = opts.regions.filter(|spec| Path::new(spec).is_file()) {
+            input_files.push(("regions", bed.clone()));
+        }
+        if let Some(fasta) = opts.reference {
+            input_files.push(("reference", fasta.clone()));
+        }
+        Ok(input_files)
+    }
+}
+
+fn recombination_mode(model: CrossoverModel, opts: &InputOptions) -> RecombinationMode {
+    match model {
+        CrossoverModel::Map(_) => RecombinationMode::Map {
+            folder: opts.recombination.cloned().unwrap_or_default(),
+            header: opts.recomheader,
+        },
+        CrossoverModel::Simple(cxcount) | CrossoverModel::SimpleInRegions(cxcount, _) => RecombinationMode::Simple {
+            crossovers: cxcount,
+        },
+        CrossoverModel::None => RecombinationMode::None,
+    }
+}
+
+fn simulate_family(opts: &Settings,
+        inputs: &Inputs,
+        family: &FamilyOut,
+        parents: &Parents,
+        parent_metadata: Vec<ParentRecord>,
+        achiasmatic: Option<&str>,
+        simulated: Option<&SimulatedParents>) -> Result<()> {
+    let verbose = opts.verbose;
+    let input_opts = opts.input_options();
+
+    if opts.ploidy == 4 {
+        return simulate_tetraploid_family(opts, inputs, family, parents, parent_metadata, achiasmatic);
+    }
+    let model = inputs.model(&input_opts);
+    let meiosis = meiosis_model(model, achiasmatic)?;
+    let seeds = SeedTree::new(opts.seed);
+    // the second of MZ twins gets the DNM file of the first
+    let independent = family.samples.iter().filter(|sample| sample.meiosis_of().is_none()).count();
+    let mut independent_dnm_files = inputs.select_dnm_files(&input_opts, independent, &seeds)?.into_iter();
+    let mut selected_dnm_files: Vec<String> = Vec::with_capacity(family.samples.len());
+    for sample in family.samples.iter() {
+        let dnm_file = match sample.meiosis_of() {
+            Some(co_twin) => family.samples.iter()
+                .position(|other| other.name == co_twin)
+                .map(|co_twin_idx| selected_dnm_files[co_twin_idx].clone()),
+            None => independent_dnm_files.next(),
+        };
+        selected_dnm_files.extend(dnm_file);
+    }
+    let postzygotic = inputs.select_postzygotic_files(&input_opts, family, &selected_dnm_files, opts.postzygotic, &seeds)?;
+
+    let job = FamilyJob {
+        family,
+        denovo: &selected_dnm_files,
+        seeds,
+        meiosis,
+        parent_genomes: simulated.map(|simulated| simulated.genomes()),
+        postzygotic: &postzygotic,
+        collision: opts.dnm_collision,
+    };
+    let siblings = wrk_generate_cohort(&[job],
+                        &inputs.popvars,
+                        verbose,
+                        &inputs.genome_hash,
+                        opts.threads)?
+        .pop()
+        .unwrap_or_default();
+
+    // local ancestry or autozygosity truth of the siblings of simulated
+    // parents
+    let (admixture, pedigree) = match simulated {
+        Some(SimulatedParents::Admixed(admixed)) => (Some(admixed.write_truth(parents, family, &siblings)?), None),
+        Some(SimulatedParents::Pedigree(pedigree)) => (None, Some(pedigree.write_truth(parents, family, &siblings)?)),
+        None => (None, None),
+    };
+
+    let parent_genomes = match simulated {
+        Some(simulated) => simulated.genomes().clone(),
+        None => panel_parent_genomes(parents, inputs)?,
+    };
+    let relatedness = write_family_ibd(family, parents, &parent_genomes, &siblings)?;
+    let embryos = match &opts.embryo {
+        Some(embryo_model) => Some(write_embryo_biopsies(embryo_model, inputs, family, &parent_genomes, &siblings, opts)?),
+        None => None,
+    };
+
+    // record how the family was produced
+    let mut input_files = inputs.manifest_files(&input_opts)?;
+    if let Some(config_file) = &opts.config {
+        input_files.push(("config", config_file.clone()));
+    }
+    if let Some(metadata_file) = &opts.metadata {
+        input_files.push(("sample_metadata", metadata_file.clone()));
+    }
+    if let ParentSource::Pedigree { file, .. } = &opts.parents {
+        input_files.push(("pedigree", file.clone()));
+    }
+    for (sample, dnm_file) in family.samples.iter().zip(selected_dnm_files.iter()) {
+        if sample.meiosis_of().is_none() {
+            input_files.push(("dnm_vcf", dnm_file.clone()));
+        }
+    }
+    for source in postzygotic.iter().flatten() {
+        input_files.push(("postzygotic_vcf", source.file.clone()));
+    }
+    let checksums = thread_pool(opts.threads)?.install(|| InputFile::checksum_all(&input_files))?;
+
+    let manifest = RunManifest {
+        parent_metadata,
+        achiasmatic: achiasmatic.map(str::to_string),
+        embryo: opts.embryo.clone(),
+        admixture,
+        pedigree,
+        siblings: siblings.iter()
+            .zip(selected_dnm_files.iter())
+            .enumerate()
+            .map(|(sibling_idx, (sibling, dnm_file))| {
+                let mut summary = SiblingSummary::new(sibling, dnm_file);
+                if let Some((errors, sites)) = &embryos {
+                    summary.chromosome_errors = errors[sibling_idx].clone();
+                    summary.biopsy_sites = Some(sites[sibling_idx]);
+                }
+                summary
+            })
+            .collect(),
+        relatedness,
+        ..RunManifest::new(std::env::args().collect(),
+            opts.seed,
+            &parents.parent1,
+            &parents.parent2,
+            recombination_mode(model, &input_opts),
+            checksums)
+    };
+    manifest.write(&family.manifest_path())?;
+    info!("Manifest written to {}", family.manifest_path());
+    Ok(())
+}
+
+/// Treats the siblings as IVF embryos: draws their chromosomal errors,
+/// written as the true karyotype of each, and simulates the reads of a
+/// biopsy of each. Returns the errors and the sites with reads of each
+/// sibling.
+fn write_embryo_biopsies(model: &EmbryoModel,
+        inputs: &Inputs,
+        family: &FamilyOut,
+        parent_genomes: &[FounderGenome; 2],
+        siblings: &[SimulatedSibling],
+        opts: &Settings) -> Result<(Vec<Vec<ChromosomeError>>, Vec<u64>)> {
+    let seeds = SeedTree::new(opts.seed);
+    let chromosomes = inputs.popvars.chromosomes();
+    let errors: Vec<Vec<ChromosomeError>> = family.samples.iter()
+        .map(|sample| embryo::draw_chromosome_errors(model, &sample.name, &chromosomes, &seeds))
+        .collect();
+    for (sample, sample_errors) in family.samples.iter().zip(errors.iter()) {
+        embryo::write_karyotype(&sample.targetkaryotypeout, sample_errors, &inputs.genome_hash)?;
+    }
+    let embryos: Vec<Embryo> = family.samples.iter()
+        .zip(siblings.iter())
+        .zip(errors.iter())
+        .map(|((sample, sibling), sample_errors)| Embryo {
+            name: &sample.name,
+            offspring: &sibling.offspring,
+            genome: &sibling.genome,
+            errors: sample_errors,
+        })
+        .collect();
+    info!("Simulating the biopsies of {} embryos with a mean depth of {}", embryos.len(), model.coverage);
+    let outputs: Vec<String> = family.samples.iter().map(|sample| sample.targetbiopsyout.clone()).collect();
+    let sites = embryo::write_biopsy_reads(&inputs.popvars, parent_genomes, &embryos, model, &outputs, opts.verbose, &seeds)?;
+    Ok((errors, sites))
+}
+
+/// Simulates the siblings of two autotetraploid samples of the panel.
+fn simulate_tetraploid_family(opts: &Settings,
+        inputs: &Inputs,
+        family: &FamilyOut,
+        parents: &Parents,
+        parent_metadata: Vec<ParentRecord>,
+        achiasmatic: Option<&str>) -> Result<()> {
+    let input_opts = opts.input_options();
+    let meiosis = meiosis_model(inputs.model(&input_opts), achiasmatic)?;
+    let seeds = SeedTree::new(opts.seed);
+    let dnm_files = inputs.select_dnm_files(&input_opts, family.samples.len(), &seeds)?;
+    info!("Simulating {} autotetraploid siblings", family.samples.len());
+    let siblings = wrk_generate_tetraploid_family(family,
+        meiosis,
+        &inputs.popvars,
+        &dnm_files,
+        opts.verbose,
+        &inputs.genome_hash,
+        &seeds,
+        opts.dnm_collision,
+        opts.threads)?;
+
+    let mut input_files = inputs.manifest_files(&input_opts)?;
+    if let Some(config_file) = &opts.config {
+        input_files.push(("config", config_file.clone()));
+    }
+    if let Some(metadata_file) = &opts.metadata {
+        input_files.push(("sample_metadata", metadata_file.clone()));
+    }
+    input_files.extend(dnm_files.iter().map(|dnm_file| ("dnm_vcf", dnm_file.clone())));
+    let checksums = thread_pool(opts.threads)?.install(|| InputFile::checksum_all(&input_files))?;
+
+    let manifest = RunManifest {
+        parent_metadata,
+        achiasmatic: achiasmatic.map(str::to_string),
+        ploidy: Some(4),
+        siblings: siblings.iter()
+            .zip(dnm_files.iter())
+            .map(|(sibling, dnm_file)| SiblingSummary::tetraploid(sibling, dnm_file))
+            .collect(),
+        ..RunManifest::new(std::env::args().collect(),
+            opts.seed,
+            &parents.parent1,
+            &parents.parent2,
+            recombination_mode(inputs.model(&input_opts), &input_opts),
+            checksums)
+    };
+    manifest.write(&family.manifest_path())?;
+    info!("Manifest written to {}", family.manifest_path());
+    Ok(())
+}
+
+/// Genomes of parents that are samples of the panel.
+fn panel_parent_genomes(parents: &Parents, inputs: &Inputs) -> Result<[FounderGenome; 2]> {
+    let chromosomes = inputs.popvars.chromosomes();
+    Ok([FounderGenome::founder(&parents.parent1, &chromosomes, &inputs.genome_hash)?,
+        FounderGenome::founder(&parents.parent2, &chromosomes, &inputs.genome_hash)?])
+}
+
+/// Writes the IBD segments between every pair of parents and siblings of
+/// the family.
+fn write_family_ibd(family: &FamilyOut,
+        parents: &Parents,
+        parent_genomes: &[FounderGenome; 2],
+        siblings: &[SimulatedSibling]) -> Result<Vec<PairRelatedness>> {
+    let mut individuals = vec![(parents.parent1.as_str(), &parent_genomes[0]), (parents.parent2.as_str(), &parent_genomes[1])];
+    for (sample, sibling) in family.samples.iter().zip(siblings.iter()) {
+        individuals.push((sample.name.as_str(), &sibling.genome));
+    }
+    write_pairwise_ibd(&family.ibd_path(), &individuals)
+}
+
+pub fn run_batch(opts: &Batch) -> Result<()> {
+    let verbose = opts.input.verbose;
+    let collision: CollisionPolicy = opts.dnm_collision.parse()?;
+    let input_opts = opts.input.input_options(Some(&opts.denovo));
+    let inputs = load_inputs(&input_opts)?;
+    let seeds = SeedTree::new(opts.input.seed);
+
+    let metadata = opts.metadata.as_ref()
+        .map(|metadata_file| SampleMetadata::from_path(metadata_file))
+        .transpose()?;
+    let families = match (&opts.pairs, opts.random_pairs) {
+        (Some(pairs_file), _) => cohort::read_parent_pairs(pairs_file)?,
+        (None, Some(count)) => {
+            let metadata = metadata.as_ref().ok_or_else(|| {
+                MeiosimError::Config("--random-pairs needs the sample metadata (--metadata)".to_string())
+            })?;
+            let constraints = PairingConstraints {
+                same_population: opts.same_population,
+                same_superpopulation: opts.same_superpopulation,
+                unrelated: opts.unrelated,
+            };
+            cohort::random_pairs(metadata,
+                &inputs.popvars.samples(),
+                count,
+                constraints,
+                &mut seeds.stream(&[seeds::PAIRING]))?
+        },
+        (None, None) => {
+            return Err(MeiosimError::Config("Use either a table of parent pairs (--pairs) or --random-pairs".to_string()));
+        }
+    };
+    info!("Simulating {} families", families.len());
+
+    // the achiasmatic parent of each family is resolved before anything
+    // is written
+    let mut resolved = Vec::with_capacity(families.len());
+    for family in families.iter() {
+        let parent_metadata = metadata.as_ref().map(|metadata| vec![
+            ParentRecord::new("parent1", &family.parents.parent1, &ParentChoice::Sample(family.parents.parent1.clone()), metadata),
+            ParentRecord::new("parent2", &family.parents.parent2, &ParentChoice::Sample(family.parents.parent2.clone()), metadata),
+        ]).unwrap_or_default();
+        let achiasmatic = resolve_achiasmatic(opts.achiasmatic.as_deref(), &parent_metadata)?;
+        resolved.push((parent_metadata, achiasmatic));
+    }
+
+    utils::ensure_directory_exists(&opts.prefix, verbose).map_err(|e| MeiosimError::io(&opts.prefix, e))?;
+    let mut batch_families = Vec::with_capacity(families.len());
+    for (spec, (parent_metadata, achiasmatic)) in families.into_iter().zip(resolved) {
+        let family_seeds = seeds.child(&spec.family_id);
+        let out = FamilyOut::new(&format!("{}/{}", opts.prefix, spec.family_id),
+            &spec.parents.parent1,
+            &spec.parents.parent2,
+            opts.familysize,
+            verbose)?;
+        let dnm_files = inputs.select_dnm_files(&input_opts, opts.familysize as usize, &family_seeds)?;
+        batch_families.push(BatchFamily { spec, out, dnm_files, parent_metadata, achiasmatic });
+    }
+
+    let family_table = EarlierOutputs::set_aside(vec![family_table_path(&opts.prefix)])?;
+    let result = batch_families.iter_mut()
+        .try_for_each(|family| family.out.set_aside_earlier_outputs())
+        .and_then(|_| simulate_batch(opts, &input_opts, &inputs, &batch_families, collision));
+    match result {
+        Ok(_) => {
+            family_table.release();
+            batch_families.iter().for_each(|family| family.out.keep_outputs());
+        }
+        Err(_) => {
+            family_table.restore();
+            batch_families.iter().for_each(|family| family.out.remove_outputs());
+        }
+    }
+    result
+}
+
+/// A family of a batch run, with what is drawn and resolved for it before
+/// the simulation.
+struct BatchFamily {
+    spec: FamilySpec,
+    out: FamilyOut,
+    dnm_files: Vec<String>,
+    /// metadata of the parents, when a sample metadata table is given
+    parent_metadata: Vec<ParentRecord>,
+    /// parent whose meiosis has no crossovers
+    achiasmatic: Option<&'static str>,
+}
+
+fn simulate_batch(opts: &Batch,
+        input_opts: &InputOptions,
+        inputs: &Inputs,
+        families: &[BatchFamily],
+        collision: CollisionPolicy) -> Result<()> {
+    let model = inputs.model(input_opts);
+    let seeds = SeedTree::new(opts.input.seed);
+    let jobs: Vec<FamilyJob> = families.iter()
+        .map(|family| Ok(FamilyJob {
+            family: &family.out,
+            denovo: &family.dnm_files,
+            seeds: seeds.child(&family.spec.family_id),
+            meiosis: meiosis_model(model, family.achiasmatic)?,
+            parent_genomes: None,
+            postzygotic: &[],
+            collision,
+        }))
+        .collect::<Result<_>>()?;
+
+    let siblings = wrk_generate_cohort(&jobs,
+        &inputs.popvars,
+        opts.input.verbose,
+        &inputs.genome_hash,
+        opts.input.threads)?;
+
+    // the shared inputs are only checksummed once
+    let pool = thread_pool(opts.input.threads)?;
+    let mut shared_files = inputs.manifest_files(input_opts)?;
+    if let Some(metadata_file) = &opts.metadata {
+        shared_files.push(("sample_metadata", metadata_file.clone()));
+    }
+    let shared_checksums = pool.install(|| InputFile::checksum_all(&shared_files))?;
+    let mut family_table = OutputFile::create(&family_table_path(&opts.prefix))?;
+    family_table.write_str("family_id\tparent1\tparent2\n")?;
+    for (family, family_siblings) in families.iter().zip(siblings.iter()) {
+        let parents = &family.spec.parents;
+        family_table.write_str(&format!("{}\t{}\t{}\n", family.spec.family_id, parents.parent1, parents.parent2))?;
+
+        let relatedness = write_family_ibd(&family.out,
+            parents,
+            &panel_parent_genomes(parents, inputs)?,
+            family_siblings)?;
+        let dnm_inputs: Vec<(&str, String)> = family.dnm_files.iter().map(|dnm_file| ("dnm_vcf", dnm_file.clone())).collect();
+        let mut checksums = shared_checksums.clone();
+        checksums.extend(pool.install(|| InputFile::checksum_all(&dnm_inputs))?);
+        let manifest = RunManifest {
+            family_id: Some(family.spec.family_id.clone()),
+            parent_metadata: family.parent_metadata.clone(),
+            achiasmatic: family.achiasmatic.map(str::to_string),
+            siblings: family_siblings.iter()
+                .zip(family.dnm_files.iter())
+                .map(|(sibling, dnm_file)| SiblingSummary::new(sibling, dnm_file))
+                .collect(),
+            relatedness,
+            ..RunManifest::new(std::env::args().collect(),
+                opts.input.seed,
+                &parents.parent1,
+                &parents.parent2,
+                recombination_mode(model, input_opts),
+                checksums)
+        };
+        manifest.write(&family.out.manifest_path())?;
+    }
+    info!("Families written to {}", family_table_path(&opts.prefix));
+    Ok(())
+}
+
+/// Role of the achiasmatic parent, given by sex or by role, from the sexes
+/// of the metadata of the parents.
+fn resolve_achiasmatic(achiasmatic: Option<&str>, parent_metadata: &[ParentRecord]) -> Result<Option<&'static str>> {
+    let sex = |role: &str| parent_metadata.iter()
+        .find(|record| record.role == role)
+        .map(|record| record.sex)
+        .unwrap_or(Sex::Unknown);
+    achiasmatic
+        .map(|achiasmatic| meiosis::achiasmatic_parent(achiasmatic, [sex("parent1"), sex("parent2")]))
+        .transpose()
+}
+
+/// Crossover models of the meioses of the two parents, without
+/// crossovers for the achiasmatic one. The meioses of the ancestors of
+/// simulated parents keep their crossovers.
+fn meiosis_model<'a>(model: CrossoverModel<'a>, achiasmatic: Option<&str>) -> Result<MeiosisModel<'a>> {
+    match achiasmatic {
+        Some(parentid) => MeiosisModel::new(model).achiasmatic(parentid),
+        None => Ok(MeiosisModel::new(model)),
+    }
+}
+
+fn family_table_path(prefix: &str) -> String {
+    format!("{}/families.tsv", prefix)
+}
+
+pub fn run_cross(opts: &Cross) -> Result<()> {
+    let design: CrossDesign = opts.design.parse()?;
+    let parent2 = match (design, &opts.parent2) {
+        (_, Some(parent2)) => parent2.clone(),
+        (CrossDesign::Selfing, None) => opts.parent1.clone(),
+        (_, None) => {
+            return Err(MeiosimError::Config(format!("The {} design needs a second parent (-P)", opts.design)));
+        },
+    };
+    let cross = CrossSimulation::new(design, opts.size, opts.generations, opts.mating.parse()?)?;
+    let (pedigree, individuals) = cross.pedigree(&opts.parent1, &parent2)?;
+
+    let input_opts = opts.input.input_options(None);
+    let inputs = load_inputs(&input_opts)?;
+    inputs.popvars.check_samples(&pedigree.founders())?;
+    let seeds = SeedTree::new(opts.input.seed);
+    let model = inputs.model(&input_opts);
+    info!("Simulating a {} cross of {} members", opts.design, pedigree.members().len());
+    let mut genomes = pedigree.simulate(model,
+        &inputs.popvars.chromosomes(),
+        &inputs.genome_hash,
+        &seeds.child(seeds::PEDIGREE))?;
+    let genomes: Vec<FounderGenome> = individuals.iter()
+        .filter_map(|name| genomes.remove(name))
+        .collect();
+
+    utils::ensure_directory_exists(&opts.prefix, opts.input.verbose).map_err(|e| MeiosimError::io(&opts.prefix, e))?;
+    let outputs: Vec<IndividualOut> = individuals.iter().map(|name| IndividualOut::new(&opts.prefix, name)).collect();
+    let earlier = EarlierOutputs::set_aside(outputs.iter()
+        .flat_map(|output| output.output_files())
+        .cloned()
+        .chain([folder_manifest_path(&opts.prefix)])
+        .collect())?;
+    let result = write_cross(opts, &input_opts, &inputs, &cross, &pedigree, &outputs, &genomes);
+    match result {
+        Ok(_) => earlier.release(),
+        Err(_) => earlier.restore(),
+    }
+    result
+}
+
+fn folder_manifest_path(prefix: &str) -> String {
+    format!("{}/meiosim_manifest.json", prefix)
+}
+
+fn write_cross(opts: &Cross,
+        input_opts: &InputOptions,
+        inputs: &Inputs,
+        cross: &CrossSimulation,
+        pedigree: &Pedigree,
+        outputs: &[IndividualOut],
+        genomes: &[FounderGenome]) -> Result<()> {
+    for (output, genome) in outputs.iter().zip(genomes.iter()) {
+        genome.write_founder_tracts(&output.targetfoundersout)?;
+    }
+    let vcf_outputs: Vec<String> = outputs.iter().map(|output| output.targetvcfout.clone()).collect();
+    let variants_written = wrk_write_genotypes(&inputs.popvars, genomes, &vcf_outputs, opts.input.verbose, opts.input.threads)?;
+
+    let input_files = inputs.manifest_files(input_opts)?;
+    let checksums = thread_pool(opts.input.threads)?.install(|| InputFile::checksum_all(&input_files))?;
+    let manifest = RunManifest {
+        pedigree: Some(PedigreeSummary {
+            consanguinity: None,
+            members: pedigree.members().to_vec(),
+            autozygosity: outputs.iter()
+                .zip(genomes.iter())
+                .map(|(output, genome)| (output.name.clone(), genome.autozygosity()))
+                .collect(),
+        }),
+        cross: Some(CrossSummary {
+            cross: cross.clone(),
+            individuals: outputs.iter()
+                .zip(variants_written)
+                .map(|(output, variants_written)| CrossIndividual { name: output.name.clone(), variants_written })
+                .collect(),
+        }),
+        ..RunManifest::new(std::env::args().collect(),
+            opts.input.seed,
+            &opts.parent1,
+            opts.parent2.as_ref().unwrap_or(&opts.parent1),
+            recombination_mode(inputs.model(input_opts), input_opts),
+            checksums)
+    };
+    manifest.write(&folder_manifest_path(&opts.prefix))?;
+    info!("Cross written to {}", opts.prefix);
+    Ok(())
+}
+
+pub fn run_gametes(opts: &Gametes) -> Result<()> {
+    if opts.count == 0 {
+        return Err(MeiosimError::Config("At least one gamete is needed (-n)".to_string()));
+    }
+    if let Some(coverage) = opts.coverage {
+        if !(coverage > 0.0 && coverage.is_finite()) {
+            return Err(MeiosimError::Config(format!("Invalid coverage {}, it must be positive", coverage)));
+        }
+    }
+    let input_opts = opts.input.input_options(None);
+    let inputs = load_inputs(&input_opts)?;
+    inputs.popvars.check_samples(std::slice::from_ref(&opts.donor))?;
+    let seeds = SeedTree::new(opts.input.seed);
+    let model = inputs.model(&input_opts);
+    let chromosomes = inputs.popvars.chromosomes();
+    let donor = FounderGenome::founder(&opts.donor, &chromosomes, &inputs.genome_hash)?;
+    info!("Simulating {} gametes of {}", opts.count, opts.donor);
+    let gametes = (0..opts.count)
+        .map(|idx| simulate_gamete(&format!("gamete{}", idx), &opts.donor, model, &chromosomes, &inputs.genome_hash, &seeds))
+        .collect::<Result<Vec<Gamete>>>()?;
+    let genomes = gametes.iter()
+        .map(|gamete| FounderGenome::gamete(&donor, gamete))
+        .collect::<Result<Vec<FounderGenome>>>()?;
+
+    utils::ensure_directory_exists(&opts.prefix, opts.input.verbose).map_err(|e| MeiosimError::io(&opts.prefix, e))?;
+    let outputs: Vec<GameteOut> = gametes.iter().map(|gamete| GameteOut::new(&opts.prefix, &gamete.name)).collect();
+    let earlier = EarlierOutputs::set_aside(outputs.iter()
+        .flat_map(|output| output.output_files())
+        .cloned()
+        .chain([folder_manifest_path(&opts.prefix)])
+        .collect())?;
+    let result = write_gametes(opts, &input_opts, &inputs, &donor, &gametes, &outputs, &genomes);
+    match result {
+        Ok(_) => earlier.release(),
+        Err(_) => earlier.restore(),
+    }
+    result
+}
+
+fn write_gametes(opts: &Gametes,
+        input_opts: &InputOptions,
+        inputs: &Inputs,
+        donor: &FounderGenome,
+        gametes: &[Gamete],
+        outputs: &[GameteOut],
+        genomes: &[FounderGenome]) -> Result<()> {
+    for (output, gamete) in outputs.iter().zip(gametes.iter()) {
+        gamete.write_haplotypes(&output.targetbedout, inputs.popvars.regions())?;
+        gamete.write_crossovers(&output.targetcrossoverout)?;
+    }
+    let vcf_outputs: Vec<String> = outputs.iter().map(|output| output.targetvcfout.clone()).collect();
+    let variants_written = wrk_write_genotypes(&inputs.popvars, genomes, &vcf_outputs, opts.input.verbose, opts.input.threads)?;
+    let observed_sites = match opts.coverage {
+        Some(coverage) => {
+            info!("Sampling reads at the heterozygous sites of {} with a mean depth of {}", opts.donor, coverage);
+            let names: Vec<String> = gametes.iter().map(|gamete| gamete.name.clone()).collect();
+            let reads_outputs: Vec<String> = outputs.iter().map(|output| output.targetreadsout.clone()).collect();
+            wrk_sample_gamete_reads(&inputs.popvars, donor, genomes, &names, &reads_outputs, coverage, opts.input.verbose, &SeedTree::new(opts.input.seed))?
+                .into_iter()
+                .map(Some)
+                .collect()
+        },
+        None => vec![None; gametes.len()],
+    };
+
+    let input_files = inputs.manifest_files(input_opts)?;
+    let checksums = thread_pool(opts.input.threads)?.install(|| InputFile::checksum_all(&input_files))?;
+    let manifest = RunManifest {
+        gametes: gametes.iter()
+            .zip(variants_written)
+            .zip(observed_sites)
+            .map(|((gamete, variants_written), observed_sites)| GameteRecord {
+                name: gamete.name.clone(),
+                crossovers: gamete.crossovers.values().map(|crossovers| crossovers.len()).sum(),
+                variants_written,
+                observed_sites,
+            })
+            .collect(),
+        coverage: opts.coverage,
+        ..RunManifest::new(std::env::args().collect(),
+            opts.input.seed,
+            &opts.donor,
+            "",
+            recombination_mode(inputs.model(input_opts), input_opts),
+            checksums)
+    };
+    manifest.write(&folder_manifest_path(&opts.prefix))?;
+    info!("Gametes written to {}", opts.prefix);
+    Ok(())
+}
+
+pub fn run_vcf2dwgsim(opts: &Vcf2dwgsim) -> Result<()> {
+    let verbose = opts.verbose;
+    let vcf_file = &opts.vcf;
+    let prefix = &opts.prefix;
+
+    if verbose {
+        info!("Mode: VCF -> DWGSIM");
+        info!("VCF file: {}", vcf_file);
+        info!("Prefix: {}", prefix);
+    }
+
+    let outputfilename = format!("{}_meiosimvariants.txt", prefix);
+    let policy = GenotypePolicy::new(opts.unphased.parse()?, MissingPolicy::Fail, SeedTree::new(opts.seed));
+    let earlier = EarlierOutputs::set_aside(vec![outputfilename.clone()])?;
+    let result = wrk_format_vcf(
+        &outputfilename,
+        vcf_file,
+        &policy,
+        verbose
+    );
+    match result {
+        Ok(_) => earlier.release(),
+        Err(_) => earlier.restore(),
+    }
+    result
+}
+
+pub fn run_prepare_dnm(opts: &PrepareDnm) -> Result<()> {
+    let verbose = opts.verbose;
+    if verbose {
+        info!("Mode: DNM catalogue -> per-proband VCFs");
+        info!("DNM catalogue: {}", opts.input);
+        info!("Prefix: {}", opts.prefix);
+    }
+
+    let columns = DnmColumns {
+        proband: opts.proband_column.clone(),
+        chr: opts.chr_column.clone(),
+        pos: opts.pos_column.clone(),
+        reference: opts.ref_column.clone(),
+        alternative: opts.alt_column.clone(),
+    };
+    let sites = dnm::read_dnm_catalogue(&opts.input, &columns, !opts.zero_based)?;
+    let chain = opts.chain.as_deref().map(ChainFile::from_path).transpose()?;
+    let reference = opts.reference.as_deref().map(ReferenceGenome::from_path).transpose()?;
+    let total = sites.len();
+    let prepared = dnm::prepare_dnms(sites, chain.as_ref(), reference.as_ref())?;
+
+    utils::ensure_directory_exists(&opts.prefix, verbose).map_err(|e| MeiosimError::io(&opts.prefix, e))?;
+    let written = dnm::write_proband_vcfs(&opts.prefix, &prepared)?;
+    if verbose {
+        for path in written.iter() {
+            info!("DNMs written to {}", path);
+        }
+    }
+    let kept: usize = prepared.probands.values().map(Vec::len).sum();
+    info!("{} DNMs of {} sites written for {} probands in {}", kept, total, written.len(), opts.prefix);
+    if !prepared.unmapped.is_empty() {
+        let unmapped_path = format!("{}/unmapped.tsv", opts.prefix);
+        dnm::write_unmapped(&unmapped_path, &prepared.unmapped)?;
+        warn!("{} sites could not be used, see {}", prepared.unmapped.len(), unmapped_path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::{Main, Settings};
+    use crate::dnm::CollisionPolicy;
+
+    #[derive(Parser)]
+    struct MainCli {
+        #[command(flatten)]
+        main: Main,
+    }
+
+    fn resolve(extra: &[&str]) -> crate::Result<Settings> {
+        let args = ["meiosim", "-p", "S1", "-P", "S2", "-r", "maps", "-v", "pop", "-d", "dnm", "--prefix", "out", "-f", "2"];
+        let cli = MainCli::try_parse_from(args.iter().chain(extra)).unwrap();
+        Settings::resolve(&cli.main)
+    }
+
+    #[test]
+    fn test_settings_resolve() {
+        let settings = resolve(&["--seed", "3", "--ploidy", "4", "--dnm-collision", "resample"]).unwrap();
+        assert_eq!(settings.ploidy, 4);
+        assert_eq!(settings.dnm_collision, CollisionPolicy::Resample);
+        assert_eq!(settings.familysize, 2);
+
+        let combined = resolve(&["--seed", "3", "--ploidy", "4", "--dnm-collision", "combine"]);
+        assert_eq!(combined.err().map(|e| e.exit_code()), Some(2), "Combined DNMs of tetraploid siblings were accepted");
+        assert!(resolve(&[]).is_err(), "A run without a seed was accepted");
+    }
+}
@@ -0,0 +1,219 @@
+use std::fs;
+
+use serde::Deserialize;
+
+use crate::error::{MeiosimError, Result};
+
+/// Simulation parameters read from a TOML file (`meiosim main --config`).
+///
+/// Every value is optional, so that a file can hold only part of the
+/// parameters and the rest be given on the command line:
+///
+/// ```toml
+/// seed = 3
+/// threads = 4
+///
+/// [inputs]
+/// population = "debug/vcfcollectionssmall2/"
+/// genome = "debug/hg38.genome"
+///
+/// [pedigree]
+/// parent1 = "NA21123"
+/// parent2 = "NA20752"
+/// siblings = 5
+///
+/// [recombination]
+/// maps = "debug/recombmaps2/"
+/// header = false
+///
+/// [mutation]
+/// denovo = "debug/decode_DNMs/vcfs/"
+///
+/// [outputs]
+/// prefix = "testout"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimulationConfig {
+    pub seed: Option<u64>,
+    pub threads: Option<usize>,
+    pub verbose: Option<bool>,
+    #[serde(default)]
+    pub inputs: InputsConfig,
+    #[serde(default)]
+    pub pedigree: PedigreeConfig,
+    #[serde(default)]
+    pub admixture: AdmixtureConfig,
+    #[serde(default)]
+    pub recombination: RecombinationConfig,
+    #[serde(default)]
+    pub mutation: MutationConfig,
+    #[serde(default)]
+    pub filters: FiltersConfig,
+    #[serde(default)]
+    pub embryo: EmbryoConfig,
+    #[serde(default)]
+    pub outputs: OutputsConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InputsConfig {
+    /// folder with the population VCFs, one per chromosome
+    pub population: Option<String>,
+    /// genome file or FASTA index with the contig sizes
+    pub genome: Option<String>,
+    /// sample metadata (1kG ped population table)
+    pub metadata: Option<String>,
+    /// unphased genotypes of the panel: "refuse", "random" or "phase-set"
+    pub unphased: Option<String>,
+    /// parental genotypes with a missing allele: "skip", "ref", "impute"
+    /// or "fail"
+    pub missing: Option<String>,
+    /// regions the simulation is restricted to, "chr:start-end" or a BED
+    pub regions: Option<String>,
+    /// reference FASTA the REF alleles are checked against
+    pub reference: Option<String>,
+    /// mismatches with the reference: "report", "drop" or "fail"
+    pub reference_mismatch: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PedigreeConfig {
+    pub parent1: Option<String>,
+    pub parent2: Option<String>,
+    /// draws parent1 from the metadata instead, e.g. "superpopulation=EUR"
+    pub parent1_query: Option<String>,
+    pub parent2_query: Option<String>,
+    /// whether drawn parents must belong to different pedigrees
+    pub unrelated: Option<bool>,
+    pub siblings: Option<u8>,
+    /// siblings with an identical (MZ) and a fraternal (DZ) co-twin
+    pub mz_twins: Option<u8>,
+    pub dz_twins: Option<u8>,
+    /// pedigree table, parent1 and parent2 are then members of it
+    pub file: Option<String>,
+    /// consanguineous union of the parents, "first-cousins" or "uncle-niece"
+    pub consanguinity: Option<String>,
+    /// homologs per chromosome of the parents and siblings, 2 or 4
+    pub ploidy: Option<usize>,
+}
+
+/// Admixed parents instead of `parent1` and `parent2` in `[pedigree]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdmixtureConfig {
+    /// source populations, e.g. "EUR:0.8,AFR:0.2"
+    pub sources: Option<String>,
+    pub generations: Option<u32>,
+    pub size: Option<usize>,
+}
+
+/// Either `maps` (with `header`) or `simple`, as `-r` and `-s`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecombinationConfig {
+    /// folder with the recombination maps
+    pub maps: Option<String>,
+    /// whether the maps have a header line
+    pub header: Option<bool>,
+    /// number of crossovers per chromosome for simple recombination
+    pub simple: Option<u8>,
+    /// parent whose meiosis has no crossovers, "male" or "female" from the
+    /// sample metadata, or "parent1" or "parent2"
+    pub achiasmatic: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MutationConfig {
+    /// folder with the DNM VCFs, one per proband
+    pub denovo: Option<String>,
+    /// postzygotic mutations of each MZ twin
+    pub postzygotic: Option<usize>,
+    /// DNMs at a site with an inherited variant: "skip", "resample" or
+    /// "combine"
+    pub collision: Option<String>,
+}
+
+/// Filters of the population variants transmitted to the siblings.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FiltersConfig {
+    /// keeps only the variants with FILTER PASS
+    pub pass_only: Option<bool>,
+    /// range of INFO/AF of the kept variants
+    pub min_af: Option<f64>,
+    pub max_af: Option<f64>,
+    /// "snv", "transition" or "transversion"
+    pub variant_type: Option<String>,
+    /// BED files of the regions whose variants are kept, or dropped
+    pub include_regions: Option<String>,
+    pub exclude_regions: Option<String>,
+}
+
+/// Embryo biopsies of the siblings, simulated when `coverage` is given.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmbryoConfig {
+    /// probability that a chromosome of an embryo is aneuploid
+    pub aneuploidy: Option<f64>,
+    /// probability that an aneuploidy is mitotic, and so mosaic
+    pub mosaicism: Option<f64>,
+    /// allele dropout rate of the heterozygous sites
+    pub dropout: Option<f64>,
+    /// preferential amplification rate of the heterozygous sites
+    pub preferential_amplification: Option<f64>,
+    /// mean read depth of the biopsies
+    pub coverage: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputsConfig {
+    pub prefix: Option<String>,
+}
+
+impl SimulationConfig {
+    pub fn from_path(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| MeiosimError::io(path, e))?;
+        Self::from_toml(&text).map_err(|e| match e {
+            MeiosimError::Config(message) => MeiosimError::Config(format!("{}: {}", path, message)),
+            e => e,
+        })
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| MeiosimError::Config(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SimulationConfig;
+
+    #[test]
+    fn test_from_toml() {
+        let config = SimulationConfig::from_toml(r#"
+            seed = 3
+            [pedigree]
+            parent1 = "NA21123"
+            siblings = 2
+            [recombination]
+            simple = 1
+        "#).unwrap();
+        assert_eq!(config.seed, Some(3));
+        assert_eq!(config.pedigree.parent1.as_deref(), Some("NA21123"));
+        assert_eq!(config.pedigree.parent2, None);
+        assert_eq!(config.pedigree.siblings, Some(2));
+        assert_eq!(config.recombination.simple, Some(1));
+        assert!(config.inputs.population.is_none());
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        assert!(SimulationConfig::from_toml("[pedigree]\nparent3 = \"NA21123\"\n").is_err());
+        assert!(SimulationConfig::from_toml("[family]\nparent1 = \"NA21123\"\n").is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::error::{MeiosimError, Result};
+use crate::pedigree::{Pedigree, PedigreeMember};
+
+/// Experimental cross between two inbred parents, P1 and P2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CrossDesign {
+    /// offspring of P1 by self-fertilization
+    Selfing,
+    /// offspring of the F1 (P1 x P2) crossed back to P1
+    Backcross,
+    /// offspring of the F1 mated with itself or with another F1
+    F2,
+    /// recombinant inbred lines, inbred from the F1 for several generations
+    Ril,
+}
+
+impl FromStr for CrossDesign {
+    type Err = MeiosimError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "selfing" => Ok(CrossDesign::Selfing),
+            "backcross" => Ok(CrossDesign::Backcross),
+            "f2" => Ok(CrossDesign::F2),
+            "ril" => Ok(CrossDesign::Ril),
+            _ => Err(MeiosimError::Config(format!(
+                "Unknown cross design '{}', use selfing, backcross, f2 or ril", text))),
+        }
+    }
+}
+
+/// How the F1 is mated for the F2 and the lines are inbred for RILs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mating {
+    /// self-fertilization
+    #[serde(rename = "self")]
+    Selfing,
+    /// mating of two siblings
+    Sib,
+}
+
+impl FromStr for Mating {
+    type Err = MeiosimError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "self" => Ok(Mating::Selfing),
+            "sib" => Ok(Mating::Sib),
+            _ => Err(MeiosimError::Config(format!("Unknown mating '{}', use self or sib", text))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Cross {
+    pub design: CrossDesign,
+    /// number of offspring, or of lines for RILs
+    pub size: usize,
+    /// generations of inbreeding after the F1, for RILs
+    pub generations: u32,
+    pub mating: Mating,
+}
+
+impl Cross {
+    pub fn new(design: CrossDesign, size: usize, generations: u32, mating: Mating) -> Result<Self> {
+        if size == 0 {
+            return Err(MeiosimError::Config("The cross needs at least one offspring".to_string()));
+        }
+        if design == CrossDesign::Ril && generations == 0 {
+            return Err(MeiosimError::Config("Recombinant inbred lines need at least one generation of inbreeding".to_string()));
+        }
+        Ok(Self { design, size, generations, mating })
+    }
+
+    /// Pedigree of the cross from samples `parent1` (P1) and `parent2`
+    /// (P2) of the panel, with the names of the individuals it produces.
+    pub fn pedigree(&self, parent1: &str, parent2: &str) -> Result<(Pedigree, Vec<String>)> {
+        let mut members = vec![PedigreeMember::founder(parent1)];
+        if self.design != CrossDesign::Selfing {
+            members.push(PedigreeMember::founder(parent2));
+            members.push(PedigreeMember::offspring("F1", parent1, parent2));
+            if self.mating == Mating::Sib && self.design != CrossDesign::Backcross {
+                members.push(PedigreeMember::offspring("F1b", parent1, parent2));
+            }
+        }
+        // the F1 mates with itself, or with the other F1
+        let f1_mate = match self.mating {
+            Mating::Selfing => "F1",
+            Mating::Sib => "F1b",
+        };
+        let mut individuals = Vec::with_capacity(self.size);
+        for idx in 1..=self.size {
+            let name = match self.design {
+                CrossDesign::Selfing => {
+                    let name = format!("S1_{}", idx);
+                    members.push(PedigreeMember::offspring(&name, parent1, parent1));
+                    name
+                },
+                CrossDesign::Backcross => {
+                    let name = format!("BC1_{}", idx);
+                    members.push(PedigreeMember::offspring(&name, "F1", parent1));
+                    name
+                },
+                CrossDesign::F2 => {
+                    let name = format!("F2_{}", idx);
+                    members.push(PedigreeMember::offspring(&name, "F1", f1_mate));
+                    name
+                },
+                CrossDesign::Ril => self.inbred_line(&mut members, idx, f1_mate),
+            };
+            individuals.push(name);
+        }
+        Ok((Pedigree::new(members, "the cross")?, individuals))
+    }
+
+    // adds the generations of a recombinant inbred line, as RIL{idx}_F{g}
+    // (and RIL{idx}_F{g}b for the second sibling of sib-mating), and
+    // returns the last one
+    fn inbred_line(&self, members: &mut Vec<PedigreeMember>, idx: usize, f1_mate: &str) -> String {
+        let (mut current, mut mate) = ("F1".to_string(), f1_mate.to_string());
+        for generation in 2..=self.generations + 1 {
+            let name = format!("RIL{}_F{}", idx, generation);
+            members.push(PedigreeMember::offspring(&name, &current, &mate));
+            let next_mate = match self.mating {
+                Mating::Selfing => name.clone(),
+                Mating::Sib => {
+                    let sibling = format!("{}b", name);
+                    members.push(PedigreeMember::offspring(&sibling, &current, &mate));
+                    sibling
+                },
+            };
+            (current, mate) = (name, next_mate);
+        }
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cross, CrossDesign, Mating};
+
+    #[test]
+    fn test_cross_pedigrees() {
+        let cross = Cross::new(CrossDesign::Ril, 2, 3, Mating::Sib).unwrap();
+        let (pedigree, individuals) = cross.pedigree("P1", "P2").unwrap();
+        assert_eq!(individuals, vec!["RIL1_F4", "RIL2_F4"]);
+        let last = pedigree.members().iter().find(|member| member.name == "RIL1_F4").unwrap();
+        assert_eq!((last.parent1.as_deref(), last.parent2.as_deref()), (Some("RIL1_F3"), Some("RIL1_F3b")));
+        assert_eq!(pedigree.founders(), vec!["P1", "P2"]);
+
+        let (pedigree, individuals) = Cross::new(CrossDesign::Selfing, 3, 0, Mating::Selfing).unwrap().pedigree("P1", "P2").unwrap();
+        assert_eq!(individuals.len(), 3);
+        assert_eq!(pedigree.founders(), vec!["P1"]);
+        assert!(Cross::new(CrossDesign::Ril, 2, 0, Mating::Selfing).is_err());
+    }
+}
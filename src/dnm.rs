@@ -0,0 +1,744 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::str::FromStr;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+use rust_htslib::bcf::{self, Read};
+use serde::Serialize;
+
+use crate::error::{MeiosimError, Result};
+use crate::liftover::ChainFile;
+use crate::reference::{ReferenceCheck, ReferenceGenome};
+use crate::regions::Regions;
+use crate::seeds::{self, SeedTree};
+use crate::utils::{from_vu8_to_string, OutputFile};
+use crate::variants::ChildGenotype;
+
+// a resampled mutation first moves a random distance of up to this many
+// bases downstream, then on to the next free base of the reference that is
+// its REF, up to the end of the contig or region
+const RESAMPLE_WINDOW: u64 = 1000;
+// bases of the reference scanned at once for a resampled mutation
+const SCAN_WINDOW: u64 = 4096;
+
+/// Columns of a DNM table, matched to its header regardless of case.
+#[derive(Clone, Debug)]
+pub struct DnmColumns {
+    pub proband: String,
+    pub chr: String,
+    pub pos: String,
+    pub reference: String,
+    pub alternative: String,
+}
+
+impl Default for DnmColumns {
+    /// Columns of the deCODE DNM tables.
+    fn default() -> Self {
+        Self {
+            proband: "Proband_id".to_string(),
+            chr: "Chr".to_string(),
+            pos: "Pos".to_string(),
+            reference: "Ref".to_string(),
+            alternative: "Alt".to_string(),
+        }
+    }
+}
+
+/// A de novo mutation of a catalogue, with a 0-based position.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DnmSite {
+    pub proband: String,
+    pub chr: String,
+    pub pos: u64,
+    pub reference: String,
+    pub alternative: String,
+}
+
+/// DNMs of a catalogue ready to be written, per proband and sorted, with
+/// the sites that could not be used and why.
+#[derive(Debug, Default)]
+pub struct PreparedDnms {
+    pub probands: BTreeMap<String, Vec<DnmSite>>,
+    pub unmapped: Vec<UnmappedSite>,
+}
+
+/// A site of a catalogue that could not be used.
+#[derive(Debug)]
+pub struct UnmappedSite {
+    /// the site as given in the catalogue
+    pub site: DnmSite,
+    /// the site in the build of the reference, when it was lifted over
+    /// and checked against it
+    pub target: Option<DnmSite>,
+    pub reason: String,
+}
+
+impl UnmappedSite {
+    fn new(site: DnmSite, reason: &str) -> Self {
+        Self { site, target: None, reason: reason.to_string() }
+    }
+}
+
+/// Reads the DNMs of a VCF (`.vcf`, `.vcf.gz` or `.bcf`) or else of a
+/// tab, comma or space separated table with a header line. `pos_1based`
+/// tells if the positions of a table are 1-based, as in a VCF.
+pub fn read_dnm_catalogue(path: &str, columns: &DnmColumns, pos_1based: bool) -> Result<Vec<DnmSite>> {
+    let is_vcf = [".vcf", ".vcf.gz", ".bcf"].iter().any(|extension| path.ends_with(extension));
+    match is_vcf {
+        true => read_dnm_vcf(path, &columns.proband),
+        false => {
+            let file = File::open(path).map_err(|e| MeiosimError::io(path, e))?;
+            read_dnm_table(io::BufReader::new(file), path, columns, pos_1based)
+        },
+    }
+}
+
+/// Reads the DNMs of a table, `path` naming it in the errors.
+pub fn read_dnm_table(reader: impl BufRead, path: &str, columns: &DnmColumns, pos_1based: bool) -> Result<Vec<DnmSite>> {
+    let mut lines = reader.lines().enumerate();
+    let (header, delimiter) = loop {
+        let Some((_, line)) = lines.next() else {
+            return Err(MeiosimError::input(path, "no header line found"));
+        };
+        let line = line.map_err(|e| MeiosimError::io(path, e))?;
+        if line.trim().is_empty() || line.starts_with("##") {
+            continue;
+        }
+        let delimiter = [Some('\t'), Some(',')].into_iter()
+            .find(|delimiter| delimiter.is_some_and(|delimiter| line.contains(delimiter)))
+            .flatten();
+        let header: Vec<String> = split(line.trim_start_matches('#'), delimiter).iter().map(|field| field.to_string()).collect();
+        break (header, delimiter);
+    };
+    let column = |name: &str| header.iter()
+        .position(|field| field.eq_ignore_ascii_case(name))
+        .ok_or_else(|| MeiosimError::input(path, &format!("no column '{}' in the header, the columns are {}", name, header.join(", "))));
+    let indices = [column(&columns.proband)?, column(&columns.chr)?, column(&columns.pos)?, column(&columns.reference)?, column(&columns.alternative)?];
+
+    let mut sites = Vec::new();
+    for (line_idx, line) in lines {
+        let line = line.map_err(|e| MeiosimError::io(path, e))?;
+        let line_number = line_idx + 1;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = split(&line, delimiter);
+        let values = indices.iter()
+            .map(|idx| fields.get(*idx).copied())
+            .collect::<Option<Vec<&str>>>()
+            .ok_or_else(|| MeiosimError::parse(path, line_number, &format!("expected {} columns, found {}", header.len(), fields.len())))?;
+        let pos = values[2].parse::<u64>().ok()
+            .filter(|pos| *pos > 0 || !pos_1based)
+            .ok_or_else(|| MeiosimError::parse(path, line_number, &format!("invalid position '{}'", values[2])))?;
+        sites.push(DnmSite {
+            proband: values[0].to_string(),
+            chr: values[1].to_string(),
+            pos: if pos_1based { pos - 1 } else { pos },
+            reference: values[3].to_ascii_uppercase(),
+            alternative: values[4].to_ascii_uppercase(),
+        });
+    }
+    Ok(sites)
+}
+
+fn split(line: &str, delimiter: Option<char>) -> Vec<&str> {
+    match delimiter {
+        Some(delimiter) => line.split(delimiter).map(str::trim).collect(),
+        None => line.split_whitespace().collect(),
+    }
+}
+
+/// Reads the DNMs of a VCF. The proband of each record is its INFO field
+/// `proband_key` when the header declares it, else every sample carrying
+/// the alternative allele, else the VCF holds the DNMs of a single proband
+/// named after the file. Records without the INFO field have an empty
+/// proband, reported by [`prepare_dnms`].
+pub fn read_dnm_vcf(path: &str, proband_key: &str) -> Result<Vec<DnmSite>> {
+    let mut reader = bcf::Reader::from_path(path).map_err(|e| MeiosimError::htslib(path, e))?;
+    let header = reader.header().clone();
+    let has_key = header.info_type(proband_key.as_bytes()).is_ok();
+    // a sites-only VCF has no sample list to read
+    let samples = match header.sample_count() {
+        0 => Vec::new(),
+        _ => from_vu8_to_string(header.samples()),
+    };
+    let file_proband = Path::new(path).file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.trim_end_matches(".gz").trim_end_matches(".vcf").trim_end_matches(".bcf").to_string())
+        .unwrap_or_default();
+
+    let mut sites = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| MeiosimError::htslib(path, e))?;
+        let chr = record.rid()
+            .and_then(|rid| header.rid2name(rid).ok())
+            .map(|name| String::from_utf8_lossy(name).to_string())
+            .ok_or_else(|| MeiosimError::input(path, &format!("record at {} without a contig", record.pos() + 1)))?;
+        let alleles = from_vu8_to_string(record.alleles());
+        let probands: Vec<(String, Vec<usize>)> = if has_key {
+            let proband = record.info(proband_key.as_bytes()).string().ok().flatten()
+                .and_then(|values| values.first().map(|value| String::from_utf8_lossy(value).to_string()));
+            vec![(proband.unwrap_or_default(), (1..alleles.len()).collect())]
+        } else if samples.is_empty() {
+            vec![(file_proband.clone(), (1..alleles.len()).collect())]
+        } else {
+            let genotypes = record.genotypes().map_err(|e| MeiosimError::htslib(path, e))?;
+            samples.iter().enumerate()
+                .map(|(sample_idx, sample)| {
+                    let carried: BTreeSet<usize> = genotypes.get(sample_idx).iter()
+                        .filter_map(|allele| allele.index())
+                        .map(|allele| allele as usize)
+                        .filter(|allele| *allele > 0)
+                        .collect();
+                    (sample.clone(), carried.into_iter().collect())
+                })
+                .filter(|(_, carried): &(String, Vec<usize>)| !carried.is_empty())
+                .collect()
+        };
+        for (proband, carried) in probands {
+            for allele in carried {
+                sites.push(DnmSite {
+                    proband: proband.clone(),
+                    chr: chr.clone(),
+                    pos: record.pos() as u64,
+                    reference: alleles[0].to_ascii_uppercase(),
+                    alternative: alleles[allele].to_ascii_uppercase(),
+                });
+            }
+        }
+    }
+    Ok(sites)
+}
+
+/// Normalizes the DNMs of a catalogue: lifts them over with `chain`,
+/// checks their REF against `reference`, and keeps the SNVs, once each,
+/// sorted per proband. The other sites are reported with the reason.
+pub fn prepare_dnms(sites: Vec<DnmSite>, chain: Option<&ChainFile>, reference: Option<&ReferenceGenome>) -> Result<PreparedDnms> {
+    let mut prepared = PreparedDnms::default();
+    for site in sites {
+        if site.proband.is_empty() {
+            prepared.unmapped.push(UnmappedSite::new(site, "no proband"));
+            continue;
+        }
+        let is_snv = |allele: &str| allele.len() == 1 && "ACGT".contains(allele);
+        if !is_snv(&site.reference) || !is_snv(&site.alternative) || site.reference == site.alternative {
+            prepared.unmapped.push(UnmappedSite::new(site, "not an SNV"));
+            continue;
+        }
+        let lifted = match chain {
+            Some(chain) => match chain.lift(&site.chr, site.pos) {
+                Some(lifted) => DnmSite {
+                    proband: site.proband.clone(),
+                    chr: lifted.chr,
+                    pos: lifted.pos,
+                    reference: complement(&site.reference, lifted.reverse),
+                    alternative: complement(&site.alternative, lifted.reverse),
+                },
+                None => {
+                    prepared.unmapped.push(UnmappedSite::new(site, "not in the chain file"));
+                    continue;
+                },
+            },
+            None => site.clone(),
+        };
+        if let Some(reference) = reference {
+            if !reference.has_contig(&lifted.chr) {
+                let reason = format!("{} is not in the reference", lifted.chr);
+                prepared.unmapped.push(UnmappedSite { site, target: Some(lifted), reason });
+                continue;
+            }
+            let base = reference.fetch(&lifted.chr, lifted.pos, lifted.pos + 1)?;
+            if base != lifted.reference.as_bytes() {
+                let reason = format!("REF {} is not the reference base {}", lifted.reference, String::from_utf8_lossy(&base));
+                prepared.unmapped.push(UnmappedSite { site, target: Some(lifted), reason });
+                continue;
+            }
+        }
+        prepared.probands.entry(lifted.proband.clone()).or_default().push(lifted);
+    }
+    for dnms in prepared.probands.values_mut() {
+        dnms.sort();
+        dnms.dedup();
+    }
+    Ok(prepared)
+}
+
+fn complement(allele: &str, reverse: bool) -> String {
+    if !reverse {
+        return allele.to_string();
+    }
+    allele.chars().rev().map(|base| match base {
+        'A' => 'T',
+        'C' => 'G',
+        'G' => 'C',
+        'T' => 'A',
+        other => other,
+    }).collect()
+}
+
+/// Writes the DNMs of each proband as a sites-only VCF in `folder`, named
+/// after the proband, as read by
+/// [`flush_dnm_to_file`](crate::variants::flush_dnm_to_file). Returns the
+/// files written. Probands whose names only differ by the characters
+/// replaced in the file names are an error.
+pub fn write_proband_vcfs(folder: &str, prepared: &PreparedDnms) -> Result<Vec<String>> {
+    let mut names: BTreeMap<String, &String> = BTreeMap::new();
+    for proband in prepared.probands.keys() {
+        let name: String = proband.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+            .collect();
+        if let Some(other) = names.insert(name.clone(), proband) {
+            return Err(MeiosimError::input(folder, &format!(
+                "probands {} and {} would both be written to {}.vcf", other, proband, name)));
+        }
+    }
+    let mut written = Vec::with_capacity(prepared.probands.len());
+    for (name, proband) in names {
+        let dnms = &prepared.probands[proband];
+        let path = format!("{}/{}.vcf", folder, name);
+        let mut outputfile = OutputFile::create(&path)?;
+        outputfile.write_str("##fileformat=VCFv4.2\n")?;
+        let contigs: BTreeSet<&str> = dnms.iter().map(|dnm| dnm.chr.as_str()).collect();
+        for contig in contigs {
+            outputfile.write_str(&format!("##contig=<ID={}>\n", contig))?;
+        }
+        outputfile.write_str(&format!("##source=meiosim prepare-dnm, proband {}\n", proband))?;
+        outputfile.write_str("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n")?;
+        for dnm in dnms {
+            outputfile.write_str(&format!("{}\t{}\t.\t{}\t{}\t.\t.\t.\n", dnm.chr, dnm.pos + 1, dnm.reference, dnm.alternative))?;
+        }
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Writes the sites that could not be used as `proband chr pos ref alt
+/// target_chr target_pos target_ref target_alt reason` lines, with their
+/// 1-based position in the catalogue and, for the sites checked against
+/// the reference, in its build (`.` otherwise).
+pub fn write_unmapped(path: &str, unmapped: &[UnmappedSite]) -> Result<()> {
+    let mut outputfile = OutputFile::create(path)?;
+    outputfile.write_str("proband\tchr\tpos\tref\talt\ttarget_chr\ttarget_pos\ttarget_ref\ttarget_alt\treason\n")?;
+    for unmapped_site in unmapped {
+        let site = &unmapped_site.site;
+        let target = match &unmapped_site.target {
+            Some(target) => format!("{}\t{}\t{}\t{}", target.chr, target.pos + 1, target.reference, target.alternative),
+            None => ".\t.\t.\t.".to_string(),
+        };
+        outputfile.write_str(&format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            site.proband, site.chr, site.pos + 1, site.reference, site.alternative, target, unmapped_site.reason))?;
+    }
+    Ok(())
+}
+
+/// What is done with a de novo or postzygotic mutation at a site where
+/// the child already carries an inherited variant, or another mutation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CollisionPolicy {
+    /// the mutation is not written
+    #[default]
+    Skip,
+    /// the mutation moves downstream, to a free base of the reference
+    /// that is its REF
+    Resample,
+    /// the mutation replaces the allele of its haplotype at the site
+    Combine,
+}
+
+impl FromStr for CollisionPolicy {
+    type Err = MeiosimError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "skip" => Ok(CollisionPolicy::Skip),
+            "resample" => Ok(CollisionPolicy::Resample),
+            "combine" => Ok(CollisionPolicy::Combine),
+            _ => Err(MeiosimError::Config(format!(
+                "Unknown DNM collision policy '{}', use skip, resample or combine", text))),
+        }
+    }
+}
+
+/// De novo and postzygotic mutations of a child, to write among its
+/// inherited genotypes.
+#[derive(Clone, Debug)]
+pub struct ChildMutations {
+    pub policy: CollisionPolicy,
+    /// seeds of the child, for the resampled positions
+    pub seeds: SeedTree,
+    pub dnms: Vec<ChildGenotype>,
+    pub postzygotic: Vec<ChildGenotype>,
+}
+
+/// Mutations written for a child, with the lines of its truth files.
+#[derive(Debug, Default)]
+pub struct MergedMutations {
+    pub dnms: usize,
+    pub postzygotic: usize,
+    /// mutations that hit an inherited variant or another mutation
+    pub collisions: usize,
+    pub dnm_truth: Vec<String>,
+    pub postzygotic_truth: Vec<String>,
+}
+
+impl MergedMutations {
+    pub fn append(&mut self, other: MergedMutations) {
+        self.dnms += other.dnms;
+        self.postzygotic += other.postzygotic;
+        self.collisions += other.collisions;
+        self.dnm_truth.extend(other.dnm_truth);
+        self.postzygotic_truth.extend(other.postzygotic_truth);
+    }
+}
+
+struct Mutation {
+    genotype: ChildGenotype,
+    postzygotic: bool,
+}
+
+impl Mutation {
+    fn label(&self) -> &'static str {
+        if self.postzygotic { "postzygotic" } else { "de novo" }
+    }
+}
+
+/// Opens the reference genome once for the [`MutationMerge`]s of a
+/// chromosome stream, `None` when no child resamples its collisions.
+pub fn resample_genome(mutations: &[ChildMutations], reference: Option<&ReferenceCheck>) -> Result<Option<ReferenceGenome>> {
+    if mutations.iter().all(|child_mutations| child_mutations.policy != CollisionPolicy::Resample) {
+        return Ok(None);
+    }
+    let reference = reference.ok_or_else(|| {
+        MeiosimError::Config("Resampled DNM positions need a reference genome (--reference)".to_string())
+    })?;
+    ReferenceGenome::from_path(&reference.path).map(Some)
+}
+
+/// Writes the mutations of a child on one chromosome in position order
+/// among its inherited genotypes, so that each site has a single line.
+/// A mutation at a site already written for the child is a collision,
+/// resolved as set by the [`CollisionPolicy`] and logged in the truth
+/// file of the mutation as a `#collision chr pos mutation action detail`
+/// line.
+pub struct MutationMerge<'a> {
+    chr: String,
+    policy: CollisionPolicy,
+    // mutations written as a single copy among four homologs
+    tetraploid: bool,
+    // mutations left to write, by 0-based position
+    pending: BTreeMap<i64, Vec<Mutation>>,
+    regions: Option<&'a Regions>,
+    // shared by the merges of a chromosome stream, see `resample_genome`
+    genome: Option<&'a ReferenceGenome>,
+    rng: StdRng,
+    merged: MergedMutations,
+}
+
+impl<'a> MutationMerge<'a> {
+    /// Merge of the mutations of `chr`. [`CollisionPolicy::Resample`]
+    /// needs the reference `genome`.
+    pub fn new(chr: &str,
+            mutations: &ChildMutations,
+            regions: Option<&'a Regions>,
+            genome: Option<&'a ReferenceGenome>) -> Result<Self> {
+        let policy = mutations.policy;
+        if policy == CollisionPolicy::Resample && genome.is_none() {
+            return Err(MeiosimError::Config("Resampled DNM positions need a reference genome (--reference)".to_string()));
+        }
+        let mut pending: BTreeMap<i64, Vec<Mutation>> = BTreeMap::new();
+        let dnms = mutations.dnms.iter().map(|genotype| (genotype, false));
+        let postzygotic = mutations.postzygotic.iter().map(|genotype| (genotype, true));
+        for (genotype, postzygotic) in dnms.chain(postzygotic).filter(|(genotype, _)| genotype.seqname == chr) {
+            pending.entry(genotype.pos).or_default().push(Mutation { genotype: genotype.clone(), postzygotic });
+        }
+        Ok(Self {
+            chr: chr.to_string(),
+            policy,
+            tetraploid: false,
+            pending,
+            regions,
+            genome,
+            rng: mutations.seeds.stream(&[chr, seeds::DNM_COLLISION]),
+            merged: MergedMutations::default(),
+        })
+    }
+
+    /// Merge of the mutations of an autotetraploid child, written as a
+    /// single copy on the first homolog from the parent they were assigned
+    /// to. Only [`CollisionPolicy::Skip`] and [`CollisionPolicy::Resample`]
+    /// are available, combined sites would need four haplotypes.
+    pub fn tetraploid(mut self) -> Result<Self> {
+        if self.policy == CollisionPolicy::Combine {
+            return Err(MeiosimError::Config("DNM collisions of tetraploid siblings are skipped or resampled, not combined".to_string()));
+        }
+        self.tetraploid = true;
+        Ok(self)
+    }
+
+    /// Writes an inherited genotype of the child, after the mutations
+    /// before it and merged with the mutations at its position. Returns
+    /// whether a line was written, i.e. the child is not homozygous for
+    /// the reference allele.
+    pub fn write_inherited(&mut self, genotype: ChildGenotype, outputfile: &mut OutputFile) -> Result<bool> {
+        let line_out = genotype.to_dwgsim()?;
+        if line_out.is_empty() {
+            return Ok(false);
+        }
+        self.write_before(genotype.pos, outputfile)?;
+        match self.pending.remove(&genotype.pos) {
+            Some(mutations) => {
+                let genotype = self.resolve(genotype, "inherited", mutations)?;
+                outputfile.write_str(&genotype.to_dwgsim()?)?;
+            },
+            None => outputfile.write_str(&line_out)?,
+        }
+        Ok(true)
+    }
+
+    /// Writes the inherited genotype of a tetraploid child at `pos`, with
+    /// the alleles of its four homologs, after the mutations before it.
+    /// The mutations at its position are collisions.
+    pub fn write_inherited_tetraploid(&mut self, pos: i64, alleles: &[String], homologs: [i32; 4], outputfile: &mut OutputFile) -> Result<()> {
+        self.write_before(pos, outputfile)?;
+        let [a, b, c, d] = homologs;
+        let genotype = format!("{}>{} {}|{}|{}|{}", alleles[0], alleles[1..].join(","), a, b, c, d);
+        for mutation in self.pending.remove(&pos).unwrap_or_default() {
+            self.merged.collisions += 1;
+            self.displace(mutation, &format!("with inherited {}", genotype))?;
+        }
+        outputfile.write_str(&format!("{}\t{}\t{}\t{}\t{}|{}|{}|{}\n", self.chr, pos + 1, alleles[0], alleles[1], a, b, c, d))
+    }
+
+    /// Writes the mutations left, returning the mutations written and the
+    /// lines of the truth files.
+    pub fn finish(mut self, outputfile: &mut OutputFile) -> Result<MergedMutations> {
+        self.write_before(i64::MAX, outputfile)?;
+        Ok(self.merged)
+    }
+
+    // writes the mutations before `pos`, which may move further down
+    fn write_before(&mut self, pos: i64, outputfile: &mut OutputFile) -> Result<()> {
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.key() >= pos {
+                break;
+            }
+            let mut mutations = entry.remove().into_iter();
+            let Some(first) = mutations.next() else {
+                continue;
+            };
+            self.push_truth(&first);
+            let genotype = self.resolve(first.genotype.clone(), first.label(), mutations.collect())?;
+            outputfile.write_str(&self.mutation_line(&genotype)?)?;
+        }
+        Ok(())
+    }
+
+    // resolves the collisions of `mutations` with the genotype already
+    // at their site, returning the genotype to write
+    fn resolve(&mut self, mut genotype: ChildGenotype, label: &str, mutations: Vec<Mutation>) -> Result<ChildGenotype> {
+        for mutation in mutations {
+            self.merged.collisions += 1;
+            let detail = format!("with {} {}", label, self.describe(&genotype));
+            match self.policy {
+                CollisionPolicy::Combine => match combine(&genotype, &mutation.genotype) {
+                    Ok(combined) => {
+                        genotype = combined;
+                        self.push_collision(&mutation, "combined", &detail);
+                        self.push_truth(&mutation);
+                    },
+                    Err(reason) => self.push_collision(&mutation, "skipped", &format!("{}, {}", detail, reason)),
+                },
+                CollisionPolicy::Skip | CollisionPolicy::Resample => self.displace(mutation, &detail)?,
+            }
+        }
+        Ok(genotype)
+    }
+
+    // skips a mutation at a site already written, or moves it downstream
+    // when resampled
+    fn displace(&mut self, mut mutation: Mutation, detail: &str) -> Result<()> {
+        if self.policy != CollisionPolicy::Resample {
+            self.push_collision(&mutation, "skipped", detail);
+            return Ok(());
+        }
+        match self.resample(&mutation.genotype)? {
+            Some(pos) => {
+                self.push_collision(&mutation, "resampled", &format!("{}, moved to {}", detail, pos + 1));
+                mutation.genotype.pos = pos;
+                self.pending.entry(pos).or_default().push(mutation);
+            },
+            None => self.push_collision(&mutation, "skipped", &format!("{}, no free position downstream", detail)),
+        }
+        Ok(())
+    }
+
+    // line of a mutation in the genotype file of the child
+    fn mutation_line(&self, genotype: &ChildGenotype) -> Result<String> {
+        if !self.tetraploid {
+            return genotype.to_dwgsim();
+        }
+        Ok(format!("{}\t{}\t{}\t{}\t{}\n", genotype.seqname, genotype.pos + 1, genotype.alleles[0], genotype.alleles[1], tetraploid_gt(genotype)))
+    }
+
+    fn describe(&self, genotype: &ChildGenotype) -> String {
+        match self.tetraploid {
+            true => format!("{}>{} {}", genotype.alleles[0], genotype.alleles[1], tetraploid_gt(genotype)),
+            false => describe(genotype),
+        }
+    }
+
+    // free position downstream of the mutation with its REF as reference
+    // base, in the simulated regions, `None` past the end of the contig
+    fn resample(&mut self, genotype: &ChildGenotype) -> Result<Option<i64>> {
+        // checked by new
+        let Some(genome) = self.genome else {
+            return Ok(None);
+        };
+        let reference_base = genotype.alleles[0].to_ascii_uppercase();
+        let mut candidate = genotype.pos as u64 + self.rng.gen_range(1..=RESAMPLE_WINDOW);
+        let resampled = loop {
+            let end = match self.regions {
+                Some(regions) => {
+                    let Some(&(start, end)) = regions.contig(&self.chr).iter().find(|(_, end)| *end > candidate) else {
+                        break None;
+                    };
+                    candidate = candidate.max(start);
+                    end.min(candidate + SCAN_WINDOW)
+                },
+                None => candidate + SCAN_WINDOW,
+            };
+            let bases = genome.fetch(&self.chr, candidate, end)?;
+            if bases.is_empty() {
+                break None;
+            }
+            let free = bases.iter().enumerate().position(|(offset, base)| {
+                reference_base.as_bytes() == std::slice::from_ref(base)
+                    && !self.pending.contains_key(&((candidate + offset as u64) as i64))
+            });
+            if let Some(offset) = free {
+                break Some((candidate + offset as u64) as i64);
+            }
+            candidate += bases.len() as u64;
+        };
+        Ok(resampled)
+    }
+
+    fn push_truth(&mut self, mutation: &Mutation) {
+        let line = format!("{}\t{}\n", mutation.genotype.seqname, mutation.genotype.pos + 1);
+        if mutation.postzygotic {
+            self.merged.postzygotic += 1;
+            self.merged.postzygotic_truth.push(line);
+        } else {
+            self.merged.dnms += 1;
+            self.merged.dnm_truth.push(line);
+        }
+    }
+
+    fn push_collision(&mut self, mutation: &Mutation, action: &str, detail: &str) {
+        let genotype = &mutation.genotype;
+        let line = format!("#collision\t{}\t{}\t{}>{}\t{}\t{}\n",
+            genotype.seqname, genotype.pos + 1, genotype.alleles[0], genotype.alleles[1], action, detail);
+        if mutation.postzygotic {
+            self.merged.postzygotic_truth.push(line);
+        } else {
+            self.merged.dnm_truth.push(line);
+        }
+    }
+}
+
+// genotype of a mutation of a tetraploid, a single copy on the first
+// homolog from its parent
+fn tetraploid_gt(genotype: &ChildGenotype) -> &'static str {
+    if genotype.gt.0 != 0 { "1|0|0|0" } else { "0|0|1|0" }
+}
+
+// alleles and genotype of a site, e.g. `C>T 0|1`
+fn describe(genotype: &ChildGenotype) -> String {
+    format!("{}>{} {}|{}", genotype.alleles[0], genotype.alleles[1..].join(","), genotype.gt.0, genotype.gt.1)
+}
+
+// genotype of a site where the haplotype of the mutation carries its ALT
+// allele, an error if the site has two ALT alleles, which the DWGSIM
+// files do not hold
+fn combine(genotype: &ChildGenotype, mutation: &ChildGenotype) -> std::result::Result<ChildGenotype, &'static str> {
+    let reference = &genotype.alleles[0];
+    if !reference.eq_ignore_ascii_case(&mutation.alleles[0]) {
+        return Err("different REF");
+    }
+    let allele = |idx: i32| genotype.alleles.get(idx as usize).ok_or("missing allele");
+    let mut haplotypes = [allele(genotype.gt.0)?, allele(genotype.gt.1)?];
+    let alternative = &mutation.alleles[1];
+    match mutation.gt {
+        (1, _) => haplotypes[0] = alternative,
+        _ => haplotypes[1] = alternative,
+    }
+    let alternatives: BTreeSet<&String> = haplotypes.iter()
+        .copied()
+        .filter(|haplotype| !haplotype.eq_ignore_ascii_case(reference))
+        .collect();
+    let [alternative] = alternatives.into_iter().collect::<Vec<_>>()[..] else {
+        return Err("two ALT alleles");
+    };
+    let index = |haplotype: &String| if haplotype.eq_ignore_ascii_case(reference) { 0 } else { 1 };
+    Ok(ChildGenotype {
+        seqname: genotype.seqname.clone(),
+        pos: genotype.pos,
+        alleles: vec![reference.clone(), alternative.clone()],
+        gt: (index(haplotypes[0]), index(haplotypes[1])),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{combine, prepare_dnms, read_dnm_table, DnmColumns};
+    use crate::liftover::ChainFile;
+    use crate::variants::ChildGenotype;
+
+    #[test]
+    fn test_combine_collisions() {
+        let genotype = |alleles: [&str; 2], gt: (i32, i32)| ChildGenotype {
+            seqname: "chr1".to_string(),
+            pos: 10,
+            alleles: alleles.iter().map(|allele| allele.to_string()).collect(),
+            gt,
+        };
+        // the same ALT on the other haplotype makes the site homozygous
+        let combined = combine(&genotype(["C", "T"], (0, 1)), &genotype(["C", "T"], (1, 0))).unwrap();
+        assert_eq!((combined.alleles, combined.gt), (vec!["C".to_string(), "T".to_string()], (1, 1)));
+        // a DNM on the haplotype of the inherited ALT replaces it
+        let combined = combine(&genotype(["C", "T"], (1, 0)), &genotype(["C", "A"], (1, 0))).unwrap();
+        assert_eq!((combined.alleles, combined.gt), (vec!["C".to_string(), "A".to_string()], (1, 0)));
+        assert_eq!(combine(&genotype(["C", "T"], (1, 0)), &genotype(["C", "A"], (0, 1))).unwrap_err(), "two ALT alleles");
+        assert_eq!(combine(&genotype(["C", "T"], (1, 1)), &genotype(["G", "A"], (0, 1))).unwrap_err(), "different REF");
+    }
+
+    #[test]
+    fn test_prepare_dnm_table() {
+        let table = "\
+Chr,Pos,Ref,Alt,Proband_id,Phase
+chr1,150,a,g,P1,mother
+chr1,101,C,T,P1,father
+chr1,155,C,T,P2,father
+chr1,120,CA,C,P2,mother
+chr1,130,C,T,,father
+";
+        let sites = read_dnm_table(table.as_bytes(), "dnms.csv", &DnmColumns::default(), true).unwrap();
+        assert_eq!(sites.len(), 5);
+        assert_eq!((sites[0].pos, sites[0].reference.as_str()), (149, "A"));
+        let chain = ChainFile::from_reader("chain 10 chr1 1000 + 100 300 chr1 2000 - 500 710 1\n50 10 20\n140\n".as_bytes(), "test.chain").unwrap();
+        let prepared = prepare_dnms(sites, Some(&chain), None).unwrap();
+        // P1 sites are lifted to the reverse strand and sorted
+        let p1: Vec<(u64, &str, &str)> = prepared.probands["P1"].iter()
+            .map(|dnm| (dnm.pos, dnm.reference.as_str(), dnm.alternative.as_str()))
+            .collect();
+        assert_eq!(p1, vec![(1450, "T", "C"), (1499, "G", "A")]);
+        let reasons: Vec<&str> = prepared.unmapped.iter().map(|unmapped| unmapped.reason.as_str()).collect();
+        assert_eq!(reasons, vec!["not in the chain file", "not an SNV", "no proband"]);
+        let columns = DnmColumns { pos: "Pos_hg38".to_string(), ..DnmColumns::default() };
+        assert!(read_dnm_table(table.as_bytes(), "dnms.csv", &columns, true).is_err());
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+use rand_distr::{Binomial, Distribution, Poisson};
+use serde::Serialize;
+
+use crate::error::{MeiosimError, Result};
+use crate::founders::{inherit, FounderGenome, FounderTract};
+use crate::meiosis::Offspring;
+use crate::seeds::{self, SeedTree};
+use crate::utils::OutputFile;
+use crate::variants::VCFCollection;
+
+/// Fold by which the favoured allele of a site with preferential
+/// amplification is over-represented in the reads.
+pub const AMPLIFICATION_BIAS: f64 = 4.0;
+
+/// Chromosomal errors and whole-genome-amplification artifacts of the
+/// embryo biopsies of a PGT simulation.
+#[derive(Clone, Debug, Serialize)]
+pub struct EmbryoModel {
+    /// probability that a chromosome of an embryo is aneuploid
+    pub aneuploidy: f64,
+    /// probability that an aneuploidy is mitotic, and so mosaic
+    pub mosaicism: f64,
+    /// probability that one allele of a heterozygous site is not amplified
+    pub dropout: f64,
+    /// probability that one allele of a heterozygous site is amplified
+    /// more than the other
+    pub preferential_amplification: f64,
+    /// mean read depth of the biopsy of a euploid chromosome
+    pub coverage: f64,
+}
+
+impl EmbryoModel {
+    pub fn new(aneuploidy: f64, mosaicism: f64, dropout: f64, preferential_amplification: f64, coverage: f64) -> Result<Self> {
+        let rates = [("aneuploidy", aneuploidy), ("mosaicism", mosaicism), ("dropout", dropout), ("preferential amplification", preferential_amplification)];
+        for (name, rate) in rates {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(MeiosimError::Config(format!("The {} rate must be between 0 and 1, {} given", name, rate)));
+            }
+        }
+        if !(coverage > 0.0 && coverage.is_finite()) {
+            return Err(MeiosimError::Config(format!("Invalid biopsy coverage {}, it must be positive", coverage)));
+        }
+        Ok(Self { aneuploidy, mosaicism, dropout, preferential_amplification, coverage })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aneuploidy {
+    /// an extra copy of the chromosome from one parent
+    Trisomy,
+    /// the copy of one parent is missing
+    Monosomy,
+}
+
+/// A whole-chromosome error of an embryo.
+#[derive(Clone, Debug, Serialize)]
+pub struct ChromosomeError {
+    pub chr: String,
+    pub kind: Aneuploidy,
+    /// parent of the extra or missing copy, "parent1" or "parent2"
+    pub parent: String,
+    /// whether the error arose in the mitoses of the embryo rather than in
+    /// the meiosis of the parent
+    pub mitotic: bool,
+    /// fraction of the cells of the biopsy with the error, 1 for meiotic
+    /// errors
+    pub fraction: f64,
+}
+
+impl ChromosomeError {
+    /// Copies of the chromosome in the cells with the error.
+    pub fn copies(&self) -> usize {
+        match self.kind {
+            Aneuploidy::Trisomy => 3,
+            Aneuploidy::Monosomy => 1,
+        }
+    }
+}
+
+/// Draws the chromosomal errors of the embryo `name`, from streams of
+/// `seeds` named after it and the chromosome.
+///
+/// Each chromosome is aneuploid with probability `aneuploidy`, a trisomy
+/// or a monosomy of either parent with equal chances. With probability
+/// `mosaicism` the error is mitotic, and is found in a fraction of the
+/// cells drawn uniformly.
+pub fn draw_chromosome_errors(model: &EmbryoModel, name: &str, chromosomes: &[String], seeds: &SeedTree) -> Vec<ChromosomeError> {
+    let mut errors = Vec::new();
+    for chr in chromosomes {
+        let mut rng = seeds.stream(&[name, chr, seeds::EMBRYO]);
+        if !rng.gen_bool(model.aneuploidy) {
+            continue;
+        }
+        let kind = if rng.gen_bool(0.5) { Aneuploidy::Trisomy } else { Aneuploidy::Monosomy };
+        let parent = if rng.gen_bool(0.5) { "parent1" } else { "parent2" };
+        let mitotic = rng.gen_bool(model.mosaicism);
+        let fraction = if mitotic { rng.gen_range(0.05..1.0) } else { 1.0 };
+        errors.push(ChromosomeError {
+            chr: chr.clone(),
+            kind,
+            parent: parent.to_string(),
+            mitotic,
+            fraction,
+        });
+    }
+    errors
+}
+
+/// Writes the chromosomal errors as a BED file with the copies in the
+/// aneuploid cells, the parent of the extra or missing copy, the origin
+/// (meiotic or mitotic) and the fraction of aneuploid cells in the 4th to
+/// 7th columns. Euploid chromosomes are not listed.
+pub fn write_karyotype(path: &str, errors: &[ChromosomeError], contig_size: &HashMap<String, u64>) -> Result<()> {
+    let mut outputfile = OutputFile::create(path)?;
+    for error in errors {
+        let size = contig_size.get(&error.chr).copied().unwrap_or_default();
+        let origin = if error.mitotic { "mitotic" } else { "meiotic" };
+        outputfile.write_str(&format!("{}\t0\t{}\t{}\t{}\t{}\t{:.4}\n",
+            error.chr, size, error.copies(), error.parent, origin, error.fraction))?;
+    }
+    Ok(())
+}
+
+/// An embryo to biopsy: its meioses, founder tracts and chromosomal errors.
+pub struct Embryo<'a> {
+    pub name: &'a str,
+    pub offspring: &'a Offspring,
+    pub genome: &'a FounderGenome,
+    pub errors: &'a [ChromosomeError],
+}
+
+// extra copy of a trisomic chromosome: the homolog the parent did not
+// transmit for a meiotic error, or a duplicate of the transmitted one
+// for a mitotic error
+fn extra_copy(embryo: &Embryo, error: &ChromosomeError, parent_genomes: &[FounderGenome; 2]) -> Result<Vec<FounderTract>> {
+    let parent_idx = if error.parent == "parent1" { 0 } else { 1 };
+    if error.mitotic {
+        return Ok(embryo.genome.haplotypes(&error.chr)?[parent_idx].clone());
+    }
+    let segments = embryo.offspring.segments.get(&error.chr).ok_or_else(|| MeiosimError::ContigNotFound {
+        contig: error.chr.clone(),
+        source_name: format!("the segments of {}", embryo.name),
+    })?;
+    let parent_haplotypes = parent_genomes[parent_idx].haplotypes(&error.chr)?;
+    Ok(inherit(parent_haplotypes, segments.iter().map(|segment| {
+        let hap = if parent_idx == 0 { segment.hap1 } else { segment.hap2 };
+        (segment.start, segment.end, 1 - hap)
+    })))
+}
+
+/// Simulates the low-coverage sequencing of a biopsy of each embryo, at
+/// the sites where either parent carries the alternative allele. Reads
+/// follow the copies of the chromosome in the euploid and aneuploid cells
+/// of the biopsy, after allele dropout or preferential amplification of
+/// the heterozygous sites. Sites with reads are written as
+/// `chr pos ref alt ref_reads alt_reads` lines (1-based), one file per
+/// embryo in `outputs`, and the number of sites with reads is returned.
+///
+/// The reads of an embryo are drawn from streams of `seeds` named after
+/// it and the chromosome.
+pub fn write_biopsy_reads(popvars: &VCFCollection,
+        parent_genomes: &[FounderGenome; 2],
+        embryos: &[Embryo],
+        model: &EmbryoModel,
+        outputs: &[String],
+        verbose: bool,
+        seeds: &SeedTree) -> Result<Vec<u64>> {
+    let mut outputfiles = outputs.iter()
+        .map(|output| OutputFile::create(output))
+        .collect::<Result<Vec<_>>>()?;
+    let mut sites = vec![0; embryos.len()];
+    for chr in popvars.chromosomes() {
+        let vcf_obj = popvars.vcfs.get(&chr).ok_or_else(|| MeiosimError::ContigNotFound {
+            contig: chr.clone(),
+            source_name: "the population VCFs".to_string(),
+        })?;
+        // each embryo is streamed with the extra copy of a trisomy (its
+        // own haplotypes otherwise), then the parents
+        let mut extra_copies = Vec::with_capacity(embryos.len());
+        let mut errors = Vec::with_capacity(embryos.len());
+        for embryo in embryos {
+            let error = embryo.errors.iter().find(|error| error.chr == chr);
+            extra_copies.push(match error {
+                Some(error) if error.kind == Aneuploidy::Trisomy => extra_copy(embryo, error, parent_genomes)?,
+                _ => embryo.genome.haplotypes(&chr)?[0].clone(),
+            });
+            errors.push(error);
+        }
+        let mut children = Vec::with_capacity(2 * embryos.len() + 2);
+        for (embryo, extra) in embryos.iter().zip(extra_copies.iter()) {
+            let [hap1, hap2] = embryo.genome.haplotypes(&chr)?;
+            children.push([hap1.as_slice(), hap2.as_slice()]);
+            children.push([extra.as_slice(), extra.as_slice()]);
+        }
+        for genome in parent_genomes {
+            let [hap1, hap2] = genome.haplotypes(&chr)?;
+            children.push([hap1.as_slice(), hap2.as_slice()]);
+        }
+        let mut rngs: Vec<_> = embryos.iter().map(|embryo| seeds.stream(&[embryo.name, &chr, seeds::COVERAGE])).collect();
+
+        for family_genotypes in vcf_obj.stream_founder_genotypes(&children, verbose)? {
+            let family_genotypes = family_genotypes?;
+            let parents = &family_genotypes.gts[2 * embryos.len()..];
+            let variable = parents.iter().flatten().any(|(allele1, allele2)| *allele1 != 0 || *allele2 != 0);
+            if !variable {
+                continue;
+            }
+            for (embryo_idx, (outputfile, rng)) in outputfiles.iter_mut().zip(rngs.iter_mut()).enumerate() {
+                let (Some((allele1, allele2)), Some((extra, _))) = (family_genotypes.gts[2 * embryo_idx], family_genotypes.gts[2 * embryo_idx + 1]) else {
+                    continue;
+                };
+                let (ref_reads, alt_reads) = sample_reads(model, [allele1, allele2], extra, errors[embryo_idx], rng)?;
+                if ref_reads + alt_reads == 0 {
+                    continue;
+                }
+                outputfile.write_str(&format!("{}\t{}\t{}\t{}\t{}\t{}\n",
+                    family_genotypes.seqname,
+                    family_genotypes.pos + 1,
+                    family_genotypes.alleles[0],
+                    family_genotypes.alleles[1],
+                    ref_reads,
+                    alt_reads))?;
+                sites[embryo_idx] += 1;
+            }
+        }
+    }
+    Ok(sites)
+}
+
+// reads of one site of the biopsy, as (reference, alternative)
+fn sample_reads<R: Rng>(model: &EmbryoModel,
+        alleles: [i32; 2],
+        extra: i32,
+        error: Option<&ChromosomeError>,
+        rng: &mut R) -> Result<(u64, u64)> {
+    let alt = |copies: &[i32]| copies.iter().filter(|allele| **allele != 0).count() as f64;
+    // DNA of each allele in the biopsy, mixing the euploid cells and the
+    // aneuploid ones
+    let (mut alt_dose, mut total_dose) = (alt(&alleles), 2.0);
+    if let Some(error) = error {
+        let aneuploid = match (error.kind, error.parent.as_str()) {
+            (Aneuploidy::Trisomy, _) => vec![alleles[0], alleles[1], extra],
+            (Aneuploidy::Monosomy, "parent1") => vec![alleles[1]],
+            (Aneuploidy::Monosomy, _) => vec![alleles[0]],
+        };
+        alt_dose = (1.0 - error.fraction) * alt_dose + error.fraction * alt(&aneuploid);
+        total_dose = (1.0 - error.fraction) * total_dose + error.fraction * aneuploid.len() as f64;
+    }
+    let mut alt_fraction = alt_dose / total_dose;
+
+    // amplification artifacts of heterozygous sites
+    if alt_fraction > 0.0 && alt_fraction < 1.0 {
+        let artifact: f64 = rng.gen();
+        if artifact < model.dropout {
+            alt_fraction = if rng.gen_bool(0.5) { 0.0 } else { 1.0 };
+        } else if artifact < model.dropout + model.preferential_amplification {
+            let bias = if rng.gen_bool(0.5) { AMPLIFICATION_BIAS } else { 1.0 / AMPLIFICATION_BIAS };
+            alt_fraction = alt_fraction * bias / (alt_fraction * bias + 1.0 - alt_fraction);
+        }
+    }
+
+    let depth = Poisson::new(model.coverage * total_dose / 2.0)
+        .map_err(|_| MeiosimError::Config(format!("Invalid biopsy coverage {}", model.coverage)))?
+        .sample(rng) as u64;
+    if depth == 0 {
+        return Ok((0, 0));
+    }
+    let alt_reads = Binomial::new(depth, alt_fraction.clamp(0.0, 1.0))
+        .map_err(|_| MeiosimError::Config(format!("Invalid allele fraction {}", alt_fraction)))?
+        .sample(rng);
+    Ok((depth - alt_reads, alt_reads))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::{sample_reads, Aneuploidy, ChromosomeError, EmbryoModel};
+
+    #[test]
+    fn test_biopsy_reads() {
+        let model = EmbryoModel::new(0.0, 0.0, 0.0, 0.0, 40.0).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        let total = |alleles: [i32; 2], error: Option<&ChromosomeError>, rng: &mut StdRng| {
+            (0..200).fold((0, 0), |(r, a), _| {
+                let (ref_reads, alt_reads) = sample_reads(&model, alleles, 1, error, rng).unwrap();
+                (r + ref_reads, a + alt_reads)
+            })
+        };
+
+        // homozygous sites only give reads of their allele
+        assert_eq!(total([0, 0], None, &mut rng).1, 0);
+        let (ref_reads, alt_reads) = total([0, 1], None, &mut rng);
+        let alt_fraction = alt_reads as f64 / (ref_reads + alt_reads) as f64;
+        assert!((alt_fraction - 0.5).abs() < 0.02);
+
+        // a meiotic trisomy with an extra alternative allele gives 2/3 of
+        // alternative reads and 1.5 times the depth
+        let trisomy = ChromosomeError { chr: "chr1".to_string(), kind: Aneuploidy::Trisomy, parent: "parent2".to_string(), mitotic: false, fraction: 1.0 };
+        let (ref_trisomy, alt_trisomy) = total([0, 1], Some(&trisomy), &mut rng);
+        let depth_ratio = (ref_trisomy + alt_trisomy) as f64 / (ref_reads + alt_reads) as f64;
+        assert!((depth_ratio - 1.5).abs() < 0.05);
+        assert!((alt_trisomy as f64 / (ref_trisomy + alt_trisomy) as f64 - 2.0 / 3.0).abs() < 0.02);
+
+        // a full dropout leaves a single allele at heterozygous sites
+        let dropout = EmbryoModel::new(0.0, 0.0, 1.0, 0.0, 40.0).unwrap();
+        let (ref_reads, alt_reads) = sample_reads(&dropout, [1, 0], 1, None, &mut rng).unwrap();
+        assert!(ref_reads == 0 || alt_reads == 0);
+        assert!(EmbryoModel::new(1.5, 0.0, 0.0, 0.0, 1.0).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use std::io;
+
+use rust_htslib::errors::Error as HtslibError;
+use thiserror::Error;
+
+/// Errors raised while loading the inputs or simulating a family.
+#[derive(Debug, Error)]
+pub enum MeiosimError {
+    /// Invalid combination of options, or an option with an invalid value.
+    #[error("{0}")]
+    Config(String),
+    #[error("{path}: {source}")]
+    Io {
+        path: String,
+        source: io::Error,
+    },
+    #[error("{path}: {source}")]
+    Htslib {
+        path: String,
+        source: HtslibError,
+    },
+    /// Input file or folder that cannot be used as is.
+    #[error("{path}: {message}")]
+    Input {
+        path: String,
+        message: String,
+    },
+    #[error("{path}, line {line}: {message}")]
+    Parse {
+        path: String,
+        line: usize,
+        message: String,
+    },
+    #[error("Sample {sample} not found in {path}")]
+    SampleNotFound {
+        sample: String,
+        path: String,
+    },
+    #[error("Contig {contig} not found in {source_name}")]
+    ContigNotFound {
+        contig: String,
+        source_name: String,
+    },
+    #[error("IUPAC code not found for {0} and {1}")]
+    Iupac(String, String),
+    #[error("{path}: invalid genotype at {contig}:{pos}, {message}")]
+    Genotype {
+        path: String,
+        contig: String,
+        pos: i64,
+        message: String,
+    },
+    #[error("{path}: REF {reference} at {contig}:{pos} is not the reference base {base}")]
+    ReferenceMismatch {
+        path: String,
+        contig: String,
+        pos: i64,
+        reference: String,
+        base: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, MeiosimError>;
+
+impl MeiosimError {
+    pub fn io(path: &str, source: io::Error) -> Self {
+        MeiosimError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn htslib(path: &str, source: HtslibError) -> Self {
+        MeiosimError::Htslib {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn input(path: &str, message: &str) -> Self {
+        MeiosimError::Input {
+            path: path.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn parse(path: &str, line: usize, message: &str) -> Self {
+        MeiosimError::Parse {
+            path: path.to_string(),
+            line,
+            message: message.to_string(),
+        }
+    }
+
+    /// Exit code used by the CLI: 2 for invalid options, 3 for invalid
+    /// input data and 4 for I/O failures.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MeiosimError::Config(_) => 2,
+            MeiosimError::Input { .. }
+            | MeiosimError::Parse { .. }
+            | MeiosimError::SampleNotFound { .. }
+            | MeiosimError::ContigNotFound { .. }
+            | MeiosimError::Iupac(..)
+            | MeiosimError::Genotype { .. }
+            | MeiosimError::ReferenceMismatch { .. } => 3,
+            MeiosimError::Io { .. }
+            | MeiosimError::Htslib { .. } => 4,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+use crate::error::{MeiosimError, Result};
+use crate::regions::Regions;
+
+/// Class of the SNVs transmitted to the children.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VariantType {
+    /// any SNV
+    Snv,
+    /// A<->G and C<->T substitutions
+    Transition,
+    /// the other substitutions
+    Transversion,
+}
+
+impl FromStr for VariantType {
+    type Err = MeiosimError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "snv" => Ok(VariantType::Snv),
+            "transition" => Ok(VariantType::Transition),
+            "transversion" => Ok(VariantType::Transversion),
+            _ => Err(MeiosimError::Config(format!(
+                "Unknown variant type '{}', use snv, transition or transversion", text))),
+        }
+    }
+}
+
+impl VariantType {
+    fn matches(&self, reference: &str, alternative: &str) -> bool {
+        let transition = matches!((reference, alternative),
+            ("A", "G") | ("G", "A") | ("C", "T") | ("T", "C"));
+        match self {
+            VariantType::Snv => true,
+            VariantType::Transition => transition,
+            VariantType::Transversion => !transition,
+        }
+    }
+}
+
+/// Filters of the records of the population panel, as with `bcftools
+/// view --include`/`--exclude`. Records that do not pass are not
+/// transmitted to the children.
+#[derive(Clone, Debug, Default)]
+pub struct VariantFilter {
+    /// keeps only the records with FILTER PASS (or `.`)
+    pub pass_only: bool,
+    /// range of INFO/AF (or AC/AN) of the kept records, records without an
+    /// allele frequency are dropped when a bound is set
+    pub min_af: Option<f64>,
+    pub max_af: Option<f64>,
+    pub variant_type: Option<VariantType>,
+    /// keeps only the records in these regions
+    pub include: Option<Regions>,
+    /// drops the records in these regions
+    pub exclude: Option<Regions>,
+}
+
+impl VariantFilter {
+    pub fn new(pass_only: bool,
+            min_af: Option<f64>,
+            max_af: Option<f64>,
+            variant_type: Option<VariantType>,
+            include: Option<Regions>,
+            exclude: Option<Regions>) -> Result<Self> {
+        for af in min_af.iter().chain(max_af.iter()) {
+            if !(0.0..=1.0).contains(af) {
+                return Err(MeiosimError::Config(format!("Allele frequency bound {} is not between 0 and 1", af)));
+            }
+        }
+        if let (Some(min_af), Some(max_af)) = (min_af, max_af) {
+            if min_af > max_af {
+                return Err(MeiosimError::Config(format!("The minimum allele frequency {} is above the maximum {}", min_af, max_af)));
+            }
+        }
+        Ok(Self { pass_only, min_af, max_af, variant_type, include, exclude })
+    }
+
+    /// Whether any record can be filtered out.
+    pub fn is_active(&self) -> bool {
+        self.pass_only
+            || self.needs_allele_frequency()
+            || self.variant_type.is_some_and(|variant_type| variant_type != VariantType::Snv)
+            || self.include.is_some()
+            || self.exclude.is_some()
+    }
+
+    pub fn needs_allele_frequency(&self) -> bool {
+        self.min_af.is_some() || self.max_af.is_some()
+    }
+
+    /// Whether the SNV at the 0-based `pos` of `chr` passes the filters.
+    /// `pass` tells if its FILTER is PASS, and `allele_frequency` is its
+    /// INFO/AF, if any.
+    pub fn keep(&self, chr: &str, pos: u64, alleles: &[String], pass: bool, allele_frequency: Option<f64>) -> bool {
+        if self.pass_only && !pass {
+            return false;
+        }
+        if self.needs_allele_frequency() {
+            let Some(allele_frequency) = allele_frequency else {
+                return false;
+            };
+            if self.min_af.is_some_and(|min_af| allele_frequency < min_af)
+                || self.max_af.is_some_and(|max_af| allele_frequency > max_af) {
+                return false;
+            }
+        }
+        if let (Some(variant_type), [reference, alternative]) = (self.variant_type, alleles) {
+            if !variant_type.matches(reference, alternative) {
+                return false;
+            }
+        }
+        if self.include.as_ref().is_some_and(|include| !include.contains(chr, pos)) {
+            return false;
+        }
+        !self.exclude.as_ref().is_some_and(|exclude| exclude.contains(chr, pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VariantFilter, VariantType};
+    use crate::regions::Regions;
+
+    #[test]
+    fn test_variant_filter() {
+        let alleles = |reference: &str, alternative: &str| vec![reference.to_string(), alternative.to_string()];
+        let exclude = Regions::from_intervals(vec![("chr1".to_string(), 100, 200)]);
+        let filter = VariantFilter::new(true, Some(0.01), Some(0.5), Some(VariantType::Transition), None, Some(exclude)).unwrap();
+        assert!(filter.keep("chr1", 10, &alleles("A", "G"), true, Some(0.2)));
+        assert!(!filter.keep("chr1", 10, &alleles("A", "G"), false, Some(0.2)));
+        assert!(!filter.keep("chr1", 10, &alleles("A", "G"), true, Some(0.6)));
+        assert!(!filter.keep("chr1", 10, &alleles("A", "G"), true, None));
+        assert!(!filter.keep("chr1", 10, &alleles("A", "C"), true, Some(0.2)));
+        assert!(!filter.keep("chr1", 150, &alleles("C", "T"), true, Some(0.2)));
+        assert!(!VariantFilter::default().is_active());
+        assert!(VariantFilter::new(false, Some(0.6), Some(0.5), None, None, None).is_err());
+    }
+}
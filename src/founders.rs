@@ -0,0 +1,239 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::error::{MeiosimError, Result};
+use crate::meiosis::{Gamete, Offspring};
+use crate::utils::OutputFile;
+
+/// A stretch of a simulated haplotype copied from one haplotype of a
+/// founder, a sample of the population panel. Coordinates are 0-based,
+/// half-open.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FounderTract {
+    pub start: u64,
+    pub end: u64,
+    pub founder: String,
+    /// haplotype index (0/1) of the founder
+    pub hap: usize,
+}
+
+/// Genome of a simulated individual as the founder tracts of its two
+/// haplotypes, per chromosome. The haplotype inherited from parent1 comes
+/// first, as in [`HaplotypeSegment`].
+#[derive(Clone, Debug, Default)]
+pub struct FounderGenome {
+    pub chromosomes: BTreeMap<String, [Vec<FounderTract>; 2]>,
+}
+
+impl FounderGenome {
+    /// Genome of a sample of the panel, each haplotype being a single tract.
+    pub fn founder(sample: &str, chromosomes: &[String], contig_size: &HashMap<String, u64>) -> Result<Self> {
+        let mut genome = Self::default();
+        for chr in chromosomes {
+            let size = *contig_size.get(chr).ok_or_else(|| MeiosimError::ContigNotFound {
+                contig: chr.clone(),
+                source_name: "the contig sizes".to_string(),
+            })?;
+            let tract = |hap: usize| vec![FounderTract {
+                start: 0,
+                end: size,
+                founder: sample.to_string(),
+                hap,
+            }];
+            genome.chromosomes.insert(chr.clone(), [tract(0), tract(1)]);
+        }
+        Ok(genome)
+    }
+
+    /// Genome of `offspring`, following the haplotype segments it inherited
+    /// from the genomes of its two parents.
+    pub fn offspring(parent1: &FounderGenome, parent2: &FounderGenome, offspring: &Offspring) -> Result<Self> {
+        let mut genome = Self::default();
+        for (chr, segments) in offspring.segments.iter() {
+            let hap1 = inherit(parent1.haplotypes(chr)?, segments.iter().map(|segment| (segment.start, segment.end, segment.hap1)));
+            let hap2 = inherit(parent2.haplotypes(chr)?, segments.iter().map(|segment| (segment.start, segment.end, segment.hap2)));
+            genome.chromosomes.insert(chr.clone(), [hap1, hap2]);
+        }
+        Ok(genome)
+    }
+
+    /// Haploid genome of `gamete`, held as two copies of the haplotype it
+    /// inherited from `parent` so that its variants are written as
+    /// homozygous.
+    pub fn gamete(parent: &FounderGenome, gamete: &Gamete) -> Result<Self> {
+        let mut genome = Self::default();
+        for (chr, segments) in gamete.segments.iter() {
+            let haplotype = inherit(parent.haplotypes(chr)?, segments.iter().map(|segment| (segment.start, segment.end, segment.hap)));
+            genome.chromosomes.insert(chr.clone(), [haplotype.clone(), haplotype]);
+        }
+        Ok(genome)
+    }
+
+    pub fn haplotypes(&self, chr: &str) -> Result<&[Vec<FounderTract>; 2]> {
+        self.chromosomes.get(chr).ok_or_else(|| MeiosimError::ContigNotFound {
+            contig: chr.to_string(),
+            source_name: "the founder tracts".to_string(),
+        })
+    }
+
+    /// Stretches where both haplotypes are copies of the same founder
+    /// haplotype, i.e. autozygous, as (chromosome, tract).
+    pub fn autozygous_tracts(&self) -> Vec<(&str, FounderTract)> {
+        let mut autozygous: Vec<(&str, FounderTract)> = Vec::new();
+        for (chr, [hap1, hap2]) in self.chromosomes.iter() {
+            for (start, end, tract1, tract2) in overlay(hap1, hap2) {
+                if tract1.founder != tract2.founder || tract1.hap != tract2.hap {
+                    continue;
+                }
+                match autozygous.last_mut() {
+                    Some((last_chr, last)) if last_chr == chr && last.end == start
+                            && last.founder == tract1.founder && last.hap == tract1.hap => last.end = end,
+                    _ => autozygous.push((chr, FounderTract { start, end, ..tract1.clone() })),
+                }
+            }
+        }
+        autozygous
+    }
+
+    /// Fraction of the genome that is autozygous.
+    pub fn autozygosity(&self) -> f64 {
+        let autozygous: u64 = self.autozygous_tracts().iter().map(|(_, tract)| tract.end - tract.start).sum();
+        let total: u64 = self.chromosomes.values().filter_map(|[hap1, _]| hap1.last()).map(|tract| tract.end).sum();
+        autozygous as f64 / total.max(1) as f64
+    }
+
+    /// Writes the autozygous stretches as a BED file with the founder and
+    /// the founder haplotype in the 4th and 5th columns.
+    pub fn write_autozygosity(&self, path: &str) -> Result<()> {
+        let mut outputfile = OutputFile::create(path)?;
+        for (chr, tract) in self.autozygous_tracts() {
+            outputfile.write_str(&format!("{}\t{}\t{}\t{}\t{}\n", chr, tract.start, tract.end, tract.founder, tract.hap))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the founder tracts of both haplotypes as a BED file with the
+    /// haplotype (0 for the one from parent1), the founder and the founder
+    /// haplotype in the 4th to 6th columns.
+    pub fn write_founder_tracts(&self, path: &str) -> Result<()> {
+        let mut outputfile = OutputFile::create(path)?;
+        for (chr, haplotypes) in self.chromosomes.iter() {
+            for (hap, tracts) in haplotypes.iter().enumerate() {
+                for tract in tracts {
+                    outputfile.write_str(&format!("{}\t{}\t{}\t{}\t{}\t{}\n", chr, tract.start, tract.end, hap, tract.founder, tract.hap))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Samples of the panel the genome is made of.
+    pub fn founders(&self) -> BTreeSet<&str> {
+        self.chromosomes.values()
+            .flatten()
+            .flatten()
+            .map(|tract| tract.founder.as_str())
+            .collect()
+    }
+}
+
+/// Walks two haplotypes of the same chromosome together, as the
+/// stretches where neither of them changes founder tract:
+/// (start, end, tract of `hap1`, tract of `hap2`).
+pub fn overlay<'a>(hap1: &'a [FounderTract], hap2: &'a [FounderTract]) -> Vec<(u64, u64, &'a FounderTract, &'a FounderTract)> {
+    let mut stretches = Vec::with_capacity(hap1.len() + hap2.len());
+    let (mut idx1, mut idx2) = (0, 0);
+    let mut start = 0;
+    while let (Some(tract1), Some(tract2)) = (hap1.get(idx1), hap2.get(idx2)) {
+        let end = tract1.end.min(tract2.end);
+        if start < end {
+            stretches.push((start, end, tract1, tract2));
+            start = end;
+        }
+        if tract1.end == end {
+            idx1 += 1;
+        }
+        if tract2.end == end {
+            idx2 += 1;
+        }
+    }
+    stretches
+}
+
+/// Copies the parental haplotype selected by each segment, given as
+/// (start, end, haplotype index of the parent), merging the tracts that
+/// continue across segments.
+pub(crate) fn inherit(haplotypes: &[Vec<FounderTract>],
+        segments: impl IntoIterator<Item = (u64, u64, usize)>) -> Vec<FounderTract> {
+    let mut tracts: Vec<FounderTract> = Vec::new();
+    for (segment_start, segment_end, hap) in segments {
+        let overlapping = haplotypes[hap].iter()
+            .filter(|tract| tract.start < segment_end && segment_start < tract.end);
+        for tract in overlapping {
+            let start = tract.start.max(segment_start);
+            let end = tract.end.min(segment_end);
+            match tracts.last_mut() {
+                Some(last) if last.end == start && last.founder == tract.founder && last.hap == tract.hap => {
+                    last.end = end;
+                },
+                _ => tracts.push(FounderTract {
+                    start,
+                    end,
+                    founder: tract.founder.clone(),
+                    hap: tract.hap,
+                }),
+            }
+        }
+    }
+    tracts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::FounderGenome;
+    use crate::meiosis::{HaplotypeSegment, Offspring, Parents};
+
+    #[test]
+    fn test_offspring_follows_segments() {
+        let chromosomes = vec!["chr1".to_string()];
+        let contig_size = HashMap::from([("chr1".to_string(), 1000)]);
+        let parent1 = FounderGenome::founder("A", &chromosomes, &contig_size).unwrap();
+        let parent2 = FounderGenome::founder("B", &chromosomes, &contig_size).unwrap();
+        let segment = |start, end, hap1, hap2| HaplotypeSegment { start, end, hap1, hap2 };
+        let child = Offspring {
+            name: "sib0".to_string(),
+            parents: Parents::new("A", "B"),
+            crossovers: BTreeMap::new(),
+            segments: BTreeMap::from([("chr1".to_string(),
+                vec![segment(0, 100, 0, 1), segment(100, 400, 1, 1), segment(400, 1000, 1, 0)])]),
+        };
+        let child = FounderGenome::offspring(&parent1, &parent2, &child).unwrap();
+        let [hap1, hap2] = child.haplotypes("chr1").unwrap();
+        let tracts = |haplotype: &[super::FounderTract]| -> Vec<(u64, u64, String, usize)> {
+            haplotype.iter().map(|t| (t.start, t.end, t.founder.clone(), t.hap)).collect()
+        };
+        assert_eq!(tracts(hap1), vec![(0, 100, "A".to_string(), 0), (100, 1000, "A".to_string(), 1)]);
+        assert_eq!(tracts(hap2), vec![(0, 400, "B".to_string(), 1), (400, 1000, "B".to_string(), 0)]);
+
+        // a grandchild mixes the tracts of both parents of parent1
+        let grandchild = Offspring {
+            name: "sib1".to_string(),
+            parents: Parents::new("sib0", "B"),
+            crossovers: BTreeMap::new(),
+            segments: BTreeMap::from([("chr1".to_string(),
+                vec![segment(0, 50, 1, 1), segment(50, 1000, 0, 0)])]),
+        };
+        let grandchild = FounderGenome::offspring(&child, &parent2, &grandchild).unwrap();
+        let [hap1, _] = grandchild.haplotypes("chr1").unwrap();
+        assert_eq!(tracts(hap1), vec![(0, 50, "B".to_string(), 1), (50, 100, "A".to_string(), 0), (100, 1000, "A".to_string(), 1)]);
+        assert_eq!(grandchild.founders().into_iter().collect::<Vec<_>>(), vec!["A", "B"]);
+
+        // the grandchild got B hap 1 from both sides on [0, 50)
+        let [_, hap2] = grandchild.haplotypes("chr1").unwrap();
+        assert_eq!(tracts(hap2), vec![(0, 50, "B".to_string(), 1), (50, 1000, "B".to_string(), 0)]);
+        let autozygous: Vec<(u64, u64)> = grandchild.autozygous_tracts().iter().map(|(_, t)| (t.start, t.end)).collect();
+        assert_eq!(autozygous, vec![(0, 50)]);
+        assert!((grandchild.autozygosity() - 0.05).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,154 @@
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::founders::{FounderGenome, FounderTract};
+use crate::utils::OutputFile;
+
+/// Stretch of a chromosome where two individuals share the same number of
+/// haplotypes identical by descent (0, 1 or 2). Coordinates are 0-based,
+/// half-open.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IbdSegment {
+    pub chr: String,
+    pub start: u64,
+    pub end: u64,
+    pub ibd: u8,
+    /// pairs of haplotypes copied from the same founder haplotype, out of
+    /// the four pairs, used for the kinship coefficient
+    shared_pairs: u8,
+}
+
+/// Genome-wide sharing between two individuals.
+#[derive(Clone, Debug, Serialize)]
+pub struct PairRelatedness {
+    pub individual1: String,
+    pub individual2: String,
+    /// fraction of the genome with 0, 1 and 2 haplotypes IBD
+    pub ibd0: f64,
+    pub ibd1: f64,
+    pub ibd2: f64,
+    /// probability that alleles drawn at random from each individual at
+    /// the same position are IBD
+    pub kinship: f64,
+}
+
+/// IBD segments between two individuals, from the founder haplotypes they
+/// are made of. Haplotypes are IBD where they are copies of the same
+/// haplotype of the same founder.
+pub fn ibd_segments(genome1: &FounderGenome, genome2: &FounderGenome) -> Result<Vec<IbdSegment>> {
+    let mut segments: Vec<IbdSegment> = Vec::new();
+    for (chr, [hap1, hap2]) in genome1.chromosomes.iter() {
+        let [other1, other2] = genome2.haplotypes(chr)?;
+        let haplotypes = [hap1.as_slice(), hap2.as_slice(), other1.as_slice(), other2.as_slice()];
+        let mut idx = [0; 4];
+        let mut start = 0;
+        while idx.iter().zip(haplotypes.iter()).all(|(idx, haplotype)| *idx < haplotype.len()) {
+            let tracts: Vec<&FounderTract> = idx.iter().zip(haplotypes.iter()).map(|(idx, haplotype)| &haplotype[*idx]).collect();
+            let end = tracts.iter().map(|tract| tract.end).min().unwrap_or(start);
+            if start < end {
+                let same = |a: &FounderTract, b: &FounderTract| a.founder == b.founder && a.hap == b.hap;
+                let ibd = if (same(tracts[0], tracts[2]) && same(tracts[1], tracts[3]))
+                        || (same(tracts[0], tracts[3]) && same(tracts[1], tracts[2])) {
+                    2
+                } else if [2, 3].iter().any(|other| same(tracts[0], tracts[*other]) || same(tracts[1], tracts[*other])) {
+                    1
+                } else {
+                    0
+                };
+                let shared_pairs = [(0, 2), (0, 3), (1, 2), (1, 3)].iter()
+                    .filter(|(a, b)| same(tracts[*a], tracts[*b]))
+                    .count() as u8;
+                match segments.last_mut() {
+                    Some(last) if last.chr == *chr && last.end == start && last.ibd == ibd && last.shared_pairs == shared_pairs => {
+                        last.end = end;
+                    },
+                    _ => segments.push(IbdSegment { chr: chr.clone(), start, end, ibd, shared_pairs }),
+                }
+                start = end;
+            }
+            for (idx, tract) in idx.iter_mut().zip(tracts.iter()) {
+                if tract.end == end {
+                    *idx += 1;
+                }
+            }
+        }
+    }
+    Ok(segments)
+}
+
+impl PairRelatedness {
+    pub fn new(individual1: &str, individual2: &str, segments: &[IbdSegment]) -> Self {
+        let mut lengths = [0u64; 3];
+        let mut shared = 0u64;
+        for segment in segments {
+            lengths[segment.ibd as usize] += segment.end - segment.start;
+            shared += (segment.end - segment.start) * segment.shared_pairs as u64;
+        }
+        let total = lengths.iter().sum::<u64>().max(1) as f64;
+        Self {
+            individual1: individual1.to_string(),
+            individual2: individual2.to_string(),
+            ibd0: lengths[0] as f64 / total,
+            ibd1: lengths[1] as f64 / total,
+            ibd2: lengths[2] as f64 / total,
+            kinship: shared as f64 / (4.0 * total),
+        }
+    }
+}
+
+/// Writes the IBD segments between every pair of `individuals`, as a BED
+/// file with the two individuals and the IBD state (0, 1 or 2) in the 4th
+/// to 6th columns, and returns the genome-wide sharing of each pair.
+pub fn write_pairwise_ibd(path: &str, individuals: &[(&str, &FounderGenome)]) -> Result<Vec<PairRelatedness>> {
+    let mut outputfile = OutputFile::create(path)?;
+    let mut relatedness = Vec::new();
+    for (idx, (name1, genome1)) in individuals.iter().enumerate() {
+        for (name2, genome2) in individuals.iter().skip(idx + 1) {
+            let segments = ibd_segments(genome1, genome2)?;
+            for segment in segments.iter() {
+                outputfile.write_str(&format!("{}\t{}\t{}\t{}\t{}\t{}\n",
+                    segment.chr, segment.start, segment.end, name1, name2, segment.ibd))?;
+            }
+            relatedness.push(PairRelatedness::new(name1, name2, &segments));
+        }
+    }
+    Ok(relatedness)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{ibd_segments, PairRelatedness};
+    use crate::founders::{FounderGenome, FounderTract};
+
+    #[test]
+    fn test_ibd_segments() {
+        let chromosomes = vec!["chr1".to_string()];
+        let contig_size = HashMap::from([("chr1".to_string(), 1000)]);
+        let parent = FounderGenome::founder("A", &chromosomes, &contig_size).unwrap();
+        let tract = |start, end, founder: &str, hap| FounderTract { start, end, founder: founder.to_string(), hap };
+        let mut child = FounderGenome::default();
+        child.chromosomes.insert("chr1".to_string(), [
+            vec![tract(0, 600, "A", 0), tract(600, 1000, "A", 1)],
+            vec![tract(0, 1000, "B", 0)],
+        ]);
+        let mut sibling = FounderGenome::default();
+        sibling.chromosomes.insert("chr1".to_string(), [
+            vec![tract(0, 1000, "A", 0)],
+            vec![tract(0, 300, "B", 1), tract(300, 1000, "B", 0)],
+        ]);
+
+        // a parent and its child are IBD1 everywhere
+        let segments = ibd_segments(&parent, &child).unwrap();
+        assert_eq!(segments.iter().map(|s| (s.start, s.end, s.ibd)).collect::<Vec<_>>(), vec![(0, 1000, 1)]);
+        assert!((PairRelatedness::new("A", "child", &segments).kinship - 0.25).abs() < 1e-9);
+
+        let segments = ibd_segments(&child, &sibling).unwrap();
+        assert_eq!(segments.iter().map(|s| (s.start, s.end, s.ibd)).collect::<Vec<_>>(),
+            vec![(0, 300, 1), (300, 600, 2), (600, 1000, 1)]);
+        let relatedness = PairRelatedness::new("child", "sibling", &segments);
+        assert!((relatedness.ibd2 - 0.3).abs() < 1e-9);
+        assert!((relatedness.kinship - (0.7 * 0.25 + 0.3 * 0.5)).abs() < 1e-9);
+    }
+}
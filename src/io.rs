@@ -1,9 +1,34 @@
-
 use crate::utils::ensure_directory_exists;
+use crate::error::{MeiosimError, Result};
+
+use log::warn;
+use serde::Serialize;
+use std::path::Path;
 
 pub struct FamilyOut {
     pub prefix: String,
-    pub samples: Vec<SampleOut>
+    pub samples: Vec<SampleOut>,
+    earlier: EarlierOutputs,
+}
+
+/// Zygosity of a pair of twins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Zygosity {
+    /// identical twins, from the same meiosis and with the same DNMs
+    #[serde(rename = "mz")]
+    Monozygotic,
+    /// fraternal twins, ordinary siblings
+    #[serde(rename = "dz")]
+    Dizygotic,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Twin {
+    pub zygosity: Zygosity,
+    pub co_twin: String,
+    /// whether this twin is listed first, the second of MZ twins copies
+    /// the meiosis and the DNMs of the first
+    pub first: bool,
 }
 
 pub struct SampleOut {
@@ -12,30 +37,117 @@ pub struct SampleOut {
     pub parent2: String,
     pub targetvcfout: String,
     pub targetbedout: String,
-    pub targetdnmout: String
+    pub targetdnmout: String,
+    /// local ancestry truth, only written for admixed parents
+    pub targetancestryout: String,
+    /// autozygous regions truth, only written for parents of a pedigree
+    pub targetautozygosityout: String,
+    /// postzygotic mutations truth, only written for MZ twins
+    pub targetpostzygoticout: String,
+    /// reads of the embryo biopsy, only written in embryo mode
+    pub targetbiopsyout: String,
+    /// chromosomal errors truth, only written in embryo mode
+    pub targetkaryotypeout: String,
+    pub twin: Option<Twin>,
 }
 
 impl SampleOut {
-    fn new(prefix: &String, p1name: &String, p2name: &String, name: &String) -> Self {
+    fn new(prefix: &str, p1name: &str, p2name: &str, name: &str) -> Self {
         let targetvcfout = format!("{}/{}_{}_{}_meiosimvariants.txt", prefix, name, p1name, p2name);
         let targetbedout = format!("{}/{}_{}_{}_haplotypes.bed", prefix, name, p1name, p2name);
         let targetdnmout = format!("{}/{}_{}_{}_truednm.txt", prefix, name, p1name, p2name);
+        let targetancestryout = format!("{}/{}_{}_{}_ancestry.bed", prefix, name, p1name, p2name);
+        let targetautozygosityout = format!("{}/{}_{}_{}_autozygosity.bed", prefix, name, p1name, p2name);
+        let targetpostzygoticout = format!("{}/{}_{}_{}_truepostzygotic.txt", prefix, name, p1name, p2name);
+        let targetbiopsyout = format!("{}/{}_{}_{}_biopsyreads.txt", prefix, name, p1name, p2name);
+        let targetkaryotypeout = format!("{}/{}_{}_{}_truekaryotype.bed", prefix, name, p1name, p2name);
         Self {
-            name: name.clone(),
-            parent1: p1name.clone(),
-            parent2: p2name.clone(),
+            name: name.to_string(),
+            parent1: p1name.to_string(),
+            parent2: p2name.to_string(),
             targetvcfout,
             targetbedout,
-            targetdnmout
+            targetdnmout,
+            targetancestryout,
+            targetautozygosityout,
+            targetpostzygoticout,
+            targetbiopsyout,
+            targetkaryotypeout,
+            twin: None,
+        }
+    }
+
+    /// Sibling whose meiosis and DNMs this sample shares, for the second of
+    /// MZ twins.
+    pub fn meiosis_of(&self) -> Option<&str> {
+        match &self.twin {
+            Some(twin) if twin.zygosity == Zygosity::Monozygotic && !twin.first => Some(&twin.co_twin),
+            _ => None,
+        }
+    }
+
+    pub fn is_monozygotic_twin(&self) -> bool {
+        matches!(&self.twin, Some(twin) if twin.zygosity == Zygosity::Monozygotic)
+    }
+
+    pub fn output_files(&self) -> Vec<&String> {
+        vec![&self.targetvcfout, &self.targetbedout, &self.targetdnmout, &self.targetancestryout, &self.targetautozygosityout, &self.targetpostzygoticout, &self.targetbiopsyout, &self.targetkaryotypeout]
+    }
+}
+
+/// Outputs of an individual of a cross, named after it.
+pub struct IndividualOut {
+    pub name: String,
+    pub targetvcfout: String,
+    pub targetfoundersout: String,
+}
+
+impl IndividualOut {
+    pub fn new(prefix: &str, name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            targetvcfout: format!("{}/{}_meiosimvariants.txt", prefix, name),
+            targetfoundersout: format!("{}/{}_founders.bed", prefix, name),
+        }
+    }
+
+    pub fn output_files(&self) -> Vec<&String> {
+        vec![&self.targetvcfout, &self.targetfoundersout]
+    }
+}
+
+/// Outputs of a gamete of `meiosim gametes`, named after it.
+pub struct GameteOut {
+    pub name: String,
+    pub targetvcfout: String,
+    pub targetbedout: String,
+    pub targetcrossoverout: String,
+    /// reads sampled at the heterozygous sites of the donor, with
+    /// `--coverage`
+    pub targetreadsout: String,
+}
+
+impl GameteOut {
+    pub fn new(prefix: &str, name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            targetvcfout: format!("{}/{}_meiosimvariants.txt", prefix, name),
+            targetbedout: format!("{}/{}_haplotypes.bed", prefix, name),
+            targetcrossoverout: format!("{}/{}_truecrossovers.txt", prefix, name),
+            targetreadsout: format!("{}/{}_hetreads.txt", prefix, name),
         }
     }
+
+    pub fn output_files(&self) -> Vec<&String> {
+        vec![&self.targetvcfout, &self.targetbedout, &self.targetcrossoverout, &self.targetreadsout]
+    }
 }
 
 impl FamilyOut {
-    pub fn new(prefix: &String, parent1: &String, parent2: &String, number_of_sibs: u8, verbose: bool) -> Self {
+    pub fn new(prefix: &str, parent1: &str, parent2: &str, number_of_sibs: u8, verbose: bool) -> Result<Self> {
         let mut samples = Vec::new();
         // check if the prefix exists as a folder name and if not create it
-        ensure_directory_exists(&prefix, verbose).expect("Directory could not be created");
+        ensure_directory_exists(prefix, verbose).map_err(|e| MeiosimError::io(prefix, e))?;
         for i in 0..number_of_sibs {
             let name = format!("sib{}", i);
             let sample = SampleOut::new(prefix,
@@ -44,10 +156,133 @@ impl FamilyOut {
                         &name);
             samples.push(sample);
         }
-        Self {
-            prefix: prefix.clone(),
+        Ok(Self {
+            prefix: prefix.to_string(),
             samples,
+            earlier: EarlierOutputs::default(),
+        })
+    }
+
+    /// Adds a co-twin to the first `monozygotic` siblings, and to the next
+    /// `dizygotic` ones. Co-twins are named after the other siblings.
+    pub fn with_twins(mut self, monozygotic: u8, dizygotic: u8) -> Result<Self> {
+        let siblings = self.samples.len();
+        if monozygotic as usize + dizygotic as usize > siblings {
+            return Err(MeiosimError::Config(format!(
+                "{} pairs of twins requested for a family of {} siblings", monozygotic as usize + dizygotic as usize, siblings)));
+        }
+        let twins = std::iter::repeat_n(Zygosity::Monozygotic, monozygotic as usize)
+            .chain(std::iter::repeat_n(Zygosity::Dizygotic, dizygotic as usize));
+        for (sibling_idx, zygosity) in twins.enumerate() {
+            let first = &self.samples[sibling_idx];
+            let mut second = SampleOut::new(&self.prefix, &first.parent1, &first.parent2, &format!("sib{}", self.samples.len()));
+            second.twin = Some(Twin { zygosity, co_twin: first.name.clone(), first: false });
+            self.samples[sibling_idx].twin = Some(Twin { zygosity, co_twin: second.name.clone(), first: true });
+            self.samples.push(second);
         }
+        Ok(self)
+    }
+
+    /// Path of the JSON manifest that records how the family was simulated.
+    pub fn manifest_path(&self) -> String {
+        format!("{}/meiosim_manifest.json", self.prefix)
+    }
+
+    /// Path of the BED file with the IBD segments between the members of
+    /// the family.
+    pub fn ibd_path(&self) -> String {
+        format!("{}/meiosim_ibd.bed", self.prefix)
+    }
+
+    /// Paths of all the output files of the family.
+    fn output_files(&self) -> Vec<String> {
+        self.samples.iter()
+            .flat_map(|sample| sample.output_files())
+            .cloned()
+            .chain([self.ibd_path(), self.manifest_path()])
+            .collect()
+    }
+
+    /// Sets aside the outputs of an earlier run into the same prefix
+    /// before the family is written, see `EarlierOutputs`.
+    pub fn set_aside_earlier_outputs(&mut self) -> Result<()> {
+        self.earlier = EarlierOutputs::set_aside(self.output_files())?;
+        Ok(())
+    }
+
+    /// Removes the output files of the family written by this run, used
+    /// when a run fails so that no partial results are left behind. The
+    /// outputs of an earlier run are put back.
+    pub fn remove_outputs(&self) {
+        self.earlier.restore();
     }
+
+    /// Keeps the output files of the family written by this run, once it
+    /// succeeded.
+    pub fn keep_outputs(&self) {
+        self.earlier.release();
+    }
+}
+
+/// Output files of an earlier run into the same prefix. They are renamed
+/// out of the way before a run writes its outputs, so that a failed run
+/// can remove everything it wrote and put the earlier results back.
+#[derive(Debug, Default)]
+pub struct EarlierOutputs {
+    /// the outputs of this run
+    outputs: Vec<String>,
+    /// the outputs that replace a file of an earlier run
+    replaced: Vec<String>,
 }
 
+impl EarlierOutputs {
+    /// Renames the existing files among `outputs` to `<path>.previous`.
+    pub fn set_aside(outputs: Vec<String>) -> Result<Self> {
+        let mut earlier = Self { outputs: Vec::new(), replaced: Vec::new() };
+        for output in outputs.iter().filter(|output| Path::new(output).exists()) {
+            if let Err(e) = std::fs::rename(output, Self::previous_path(output)) {
+                earlier.restore();
+                return Err(MeiosimError::io(output, e));
+            }
+            earlier.replaced.push(output.clone());
+        }
+        earlier.outputs = outputs;
+        Ok(earlier)
+    }
+
+    fn previous_path(output: &str) -> String {
+        format!("{}.previous", output)
+    }
+
+    /// Removes the outputs written by this run and puts the earlier files
+    /// back, after a failed run.
+    pub fn restore(&self) {
+        for output in self.outputs.iter().filter(|output| Path::new(output).exists()) {
+            match std::fs::remove_file(output) {
+                Ok(_) => warn!("Removed incomplete output {}", output),
+                Err(e) => warn!("Could not remove incomplete output {}: {}", output, e),
+            }
+        }
+        for output in &self.replaced {
+            if let Err(e) = std::fs::rename(Self::previous_path(output), output) {
+                warn!("Could not restore {} from an earlier run: {}", output, e);
+            }
+        }
+    }
+
+    /// Removes the earlier files that an output of this run replaced and
+    /// puts back the ones it did not write, after a successful run.
+    pub fn release(&self) {
+        for output in &self.replaced {
+            let previous = Self::previous_path(output);
+            let result = if Path::new(output).exists() {
+                std::fs::remove_file(&previous)
+            } else {
+                std::fs::rename(&previous, output)
+            };
+            if let Err(e) = result {
+                warn!("Could not clean up {}: {}", previous, e);
+            }
+        }
+    }
+}
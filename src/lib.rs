@@ -0,0 +1,81 @@
+//! Simulate meiosis from a panel of population vcfs (aka 1kG).
+//!
+//! The `meiosim` binary is a thin wrapper around this library, its
+//! subcommands are run by [`commands`]. The main steps of a simulation
+//! are:
+//!
+//! 1. load the recombination maps ([`RecombinationMapGenome::from_path`])
+//!    and the population panel ([`VCFCollection::from_path`]),
+//! 2. define the parents ([`Parents`]),
+//! 3. simulate the meioses into haplotype segments ([`simulate_offspring`]),
+//! 4. stream the child genotypes from the panel
+//!    ([`VCF::stream_child_genotypes`], or
+//!    [`VCF::stream_family_genotypes`] to read each chromosome once for
+//!    all the siblings).
+//!
+//! ```no_run
+//! use meiosim::{CrossoverModel, Parents, RecombinationMapGenome, SeedTree, VCFCollection};
+//!
+//! # fn main() -> meiosim::Result<()> {
+//! let maps = RecombinationMapGenome::from_path("maps/", "map", false)?;
+//! let panel = VCFCollection::from_path("vcfs/", "gz", false)?;
+//! let contig_size = panel.contig_sizes()?;
+//! let parents = Parents::new("NA21123", "NA20752");
+//! let seeds = SeedTree::new(10);
+//!
+//! let child = meiosim::simulate_offspring("sib0",
+//!     &parents,
+//!     CrossoverModel::Map(&maps),
+//!     &panel.chromosomes(),
+//!     &contig_size,
+//!     false,
+//!     &seeds)?;
+//!
+//! for (chr, segments) in child.segments.iter() {
+//!     let vcf = &panel.vcfs[chr];
+//!     for genotype in vcf.stream_child_genotypes(&parents, segments, false)? {
+//!         let genotype = genotype?;
+//!         println!("{}\t{}\t{:?}", genotype.seqname, genotype.pos + 1, genotype.gt);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Parents that are not samples of the panel, e.g. admixed individuals
+//! ([`admixture::simulate_admixture`]) or members of a pedigree
+//! ([`pedigree::Pedigree`]), are followed as the founder
+//! haplotypes they are made of ([`founders::FounderGenome`]).
+//!
+//! All fallible operations return a [`MeiosimError`].
+
+pub mod error;
+pub mod variants;
+pub mod recombination;
+pub mod meiosis;
+pub mod utils;
+pub mod io;
+pub mod workflows;
+pub mod seeds;
+pub mod manifest;
+pub mod config;
+pub mod cohort;
+pub mod founders;
+pub mod admixture;
+pub mod pedigree;
+pub mod ibd;
+pub mod cross;
+pub mod polyploid;
+pub mod embryo;
+pub mod regions;
+pub mod filters;
+pub mod reference;
+pub mod liftover;
+pub mod dnm;
+pub mod commands;
+
+pub use error::{MeiosimError, Result};
+pub use meiosis::{CrossoverModel, HaplotypeSegment, MeiosisModel, Offspring, Parents, simulate_offspring};
+pub use recombination::{Crossover, RecombinationMapGenome};
+pub use seeds::SeedTree;
+pub use variants::{ChildGenotype, FamilyGenotypes, VCF, VCFCollection};
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead};
+
+use crate::error::{MeiosimError, Result};
+
+/// Position lifted over to the other build, 0-based.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LiftedPosition {
+    pub chr: String,
+    pub pos: u64,
+    /// the site maps to the reverse strand, its alleles are complemented
+    pub reverse: bool,
+}
+
+// ungapped block of a chain, 0-based, `q_start` on the strand of the chain
+struct ChainBlock {
+    t_start: u64,
+    q_start: u64,
+    size: u64,
+}
+
+// alignment of a stretch of the source build (t) to the target build (q)
+struct Chain {
+    score: f64,
+    t_start: u64,
+    t_end: u64,
+    q_name: String,
+    q_size: u64,
+    q_reverse: bool,
+    blocks: Vec<ChainBlock>,
+}
+
+impl Chain {
+    fn lift(&self, pos: u64) -> Option<LiftedPosition> {
+        if pos < self.t_start || pos >= self.t_end {
+            return None;
+        }
+        let idx = self.blocks.partition_point(|block| block.t_start <= pos);
+        let block = &self.blocks[idx.checked_sub(1)?];
+        if pos >= block.t_start + block.size {
+            // in a gap of the alignment
+            return None;
+        }
+        let q_pos = block.q_start + pos - block.t_start;
+        Some(LiftedPosition {
+            chr: self.q_name.clone(),
+            pos: if self.q_reverse { self.q_size - 1 - q_pos } else { q_pos },
+            reverse: self.q_reverse,
+        })
+    }
+}
+
+/// UCSC chain file (e.g. `hg19ToHg38.over.chain`), mapping positions of
+/// one genome build to another as `liftOver` does for single bases.
+pub struct ChainFile {
+    // chains per contig of the source build, by decreasing score
+    chains: HashMap<String, Vec<Chain>>,
+}
+
+impl ChainFile {
+    pub fn from_path(path: &str) -> Result<Self> {
+        let file = File::open(path).map_err(|e| MeiosimError::io(path, e))?;
+        Self::from_reader(io::BufReader::new(file), path)
+    }
+
+    /// Reads the chains of `reader`, `path` naming it in the errors.
+    pub fn from_reader(reader: impl BufRead, path: &str) -> Result<Self> {
+        let mut chains: HashMap<String, Vec<Chain>> = HashMap::new();
+        // chain being read, with its contig and the current t and q offsets
+        let mut current: Option<(String, Chain, u64, u64)> = None;
+        for (line_idx, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| MeiosimError::io(path, e))?;
+            let line_number = line_idx + 1;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let number = |field: &str| field.parse::<u64>().map_err(|_| {
+                MeiosimError::parse(path, line_number, &format!("'{}' is not a positive integer", field))
+            });
+            match fields.as_slice() {
+                [] => {
+                    if let Some((t_name, chain, _, _)) = current.take() {
+                        chains.entry(t_name).or_default().push(chain);
+                    }
+                },
+                ["chain", score, t_name, _t_size, _t_strand, t_start, t_end, q_name, q_size, q_strand, q_start, _q_end, ..] => {
+                    if let Some((t_name, chain, _, _)) = current.take() {
+                        chains.entry(t_name).or_default().push(chain);
+                    }
+                    let score = score.parse::<f64>().map_err(|_| {
+                        MeiosimError::parse(path, line_number, &format!("invalid chain score '{}'", score))
+                    })?;
+                    let (t_start, q_start) = (number(t_start)?, number(q_start)?);
+                    let chain = Chain {
+                        score,
+                        t_start,
+                        t_end: number(t_end)?,
+                        q_name: q_name.to_string(),
+                        q_size: number(q_size)?,
+                        q_reverse: *q_strand == "-",
+                        blocks: Vec::new(),
+                    };
+                    current = Some((t_name.to_string(), chain, t_start, q_start));
+                },
+                [size, gaps @ ..] if gaps.is_empty() || gaps.len() == 2 => {
+                    let Some((_, chain, t_offset, q_offset)) = current.as_mut() else {
+                        return Err(MeiosimError::parse(path, line_number, "alignment block outside of a chain"));
+                    };
+                    let size = number(size)?;
+                    chain.blocks.push(ChainBlock { t_start: *t_offset, q_start: *q_offset, size });
+                    *t_offset += size;
+                    *q_offset += size;
+                    if let [dt, dq] = gaps {
+                        *t_offset += number(dt)?;
+                        *q_offset += number(dq)?;
+                    }
+                },
+                _ => return Err(MeiosimError::parse(path, line_number, "expected a chain header or an alignment block")),
+            }
+        }
+        if let Some((t_name, chain, _, _)) = current.take() {
+            chains.entry(t_name).or_default().push(chain);
+        }
+        if chains.is_empty() {
+            return Err(MeiosimError::input(path, "no chains found"));
+        }
+        for contig_chains in chains.values_mut() {
+            contig_chains.sort_by(|a, b| b.score.total_cmp(&a.score));
+        }
+        Ok(Self { chains })
+    }
+
+    /// Position of the base at the 0-based `pos` of `chr` in the other
+    /// build, from the best chain aligning it, `None` if it is not
+    /// aligned.
+    pub fn lift(&self, chr: &str, pos: u64) -> Option<LiftedPosition> {
+        self.chains.get(chr)?.iter().find_map(|chain| chain.lift(pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChainFile, LiftedPosition};
+
+    #[test]
+    fn test_lift_positions() {
+        let chains = "\
+chain 1000 chr1 1000 + 100 300 chr1 2000 + 500 710 1
+50 10 20
+140
+
+chain 10 chr1 1000 + 0 100 chr2 500 - 0 100 2
+100
+";
+        let chain_file = ChainFile::from_reader(chains.as_bytes(), "test.chain").unwrap();
+        let lifted = |chr: &str, pos: u64| chain_file.lift(chr, pos);
+        assert_eq!(lifted("chr1", 100), Some(LiftedPosition { chr: "chr1".to_string(), pos: 500, reverse: false }));
+        assert_eq!(lifted("chr1", 149), Some(LiftedPosition { chr: "chr1".to_string(), pos: 549, reverse: false }));
+        // gap of 10 bases in chr1, 20 in the target
+        assert_eq!(lifted("chr1", 155), None);
+        assert_eq!(lifted("chr1", 160), Some(LiftedPosition { chr: "chr1".to_string(), pos: 570, reverse: false }));
+        // reverse strand of chr2
+        assert_eq!(lifted("chr1", 10), Some(LiftedPosition { chr: "chr2".to_string(), pos: 489, reverse: true }));
+        assert_eq!(lifted("chr1", 300), None);
+        assert_eq!(lifted("chr3", 10), None);
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+
+use rayon::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::admixture::AdmixtureModel;
+use crate::cross::Cross;
+use crate::embryo::{ChromosomeError, EmbryoModel};
+use crate::cohort::{ParentChoice, SampleMetadata, Sex};
+use crate::error::{MeiosimError, Result};
+use crate::io::Twin;
+use crate::ibd::PairRelatedness;
+use crate::pedigree::{Consanguinity, PedigreeMember};
+use crate::recombination::Crossover;
+use crate::utils::OutputFile;
+use crate::workflows::{SimulatedSibling, SimulatedTetraploid};
+
+/// Provenance of a `meiosim main` run, written as JSON next to its outputs.
+#[derive(Debug, Serialize)]
+pub struct RunManifest {
+    pub meiosim_version: String,
+    pub arguments: Vec<String>,
+    pub seed: u64,
+    /// family of a batch run, its outputs are in a directory of that name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family_id: Option<String>,
+    pub parent1: String,
+    /// empty for the gametes of a single donor
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub parent2: String,
+    /// metadata of the parents, when a sample metadata table is given
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parent_metadata: Vec<ParentRecord>,
+    pub recombination: RecombinationMode,
+    /// parent whose meiosis has no crossovers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub achiasmatic: Option<String>,
+    /// homologs per chromosome, when the siblings are not diploid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ploidy: Option<usize>,
+    /// chromosomal errors and amplification artifacts of the embryo
+    /// biopsies of the siblings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embryo: Option<EmbryoModel>,
+    /// admixed population the parents were drawn from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admixture: Option<AdmixtureSummary>,
+    /// pedigree the parents were simulated from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pedigree: Option<PedigreeSummary>,
+    /// experimental cross of `meiosim cross`, from parent1 and parent2
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cross: Option<CrossSummary>,
+    /// gametes of `meiosim gametes`, from parent1
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub gametes: Vec<GameteRecord>,
+    /// mean read depth sampled at the heterozygous sites of the donor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<f64>,
+    pub inputs: Vec<InputFile>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub siblings: Vec<SiblingSummary>,
+    /// IBD sharing and kinship between every pair of parents and siblings
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub relatedness: Vec<PairRelatedness>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ParentRecord {
+    /// "parent1" or "parent2"
+    pub role: String,
+    pub sample: String,
+    /// query the parent was drawn with, if it was not given by name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    pub sex: Sex,
+    pub population: Option<String>,
+    pub superpopulation: Option<String>,
+}
+
+impl ParentRecord {
+    pub fn new(role: &str, sample: &str, choice: &ParentChoice, metadata: &SampleMetadata) -> Self {
+        let info = metadata.get(sample);
+        Self {
+            role: role.to_string(),
+            sample: sample.to_string(),
+            query: match choice {
+                ParentChoice::Query(query) => Some(query.to_string()),
+                ParentChoice::Sample(_) => None,
+            },
+            sex: info.map(|info| info.sex).unwrap_or(Sex::Unknown),
+            population: info.and_then(|info| info.population.clone()),
+            superpopulation: info.and_then(|info| info.superpopulation.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdmixtureSummary {
+    #[serde(flatten)]
+    pub model: AdmixtureModel,
+    /// founder sample -> source population
+    pub founders: BTreeMap<String, String>,
+    /// fraction of the genome from each source population, for the
+    /// parents and each sibling
+    pub ancestry: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PedigreeSummary {
+    /// preset union, when the pedigree was not read from a file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consanguinity: Option<Consanguinity>,
+    pub members: Vec<PedigreeMember>,
+    /// fraction of the genome that is autozygous, for the parents and
+    /// each sibling
+    pub autozygosity: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrossSummary {
+    #[serde(flatten)]
+    pub cross: Cross,
+    pub individuals: Vec<CrossIndividual>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrossIndividual {
+    pub name: String,
+    pub variants_written: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GameteRecord {
+    pub name: String,
+    pub crossovers: usize,
+    pub variants_written: u64,
+    /// heterozygous sites of the donor with at least one read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observed_sites: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum RecombinationMode {
+    Map {
+        folder: String,
+        header: bool,
+    },
+    Simple {
+        crossovers: u8,
+    },
+    /// achiasmatic meioses only
+    None,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct InputFile {
+    /// what the file is used for, e.g. "population_vcf"
+    pub role: String,
+    pub path: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SiblingSummary {
+    pub name: String,
+    pub dnm_file: String,
+    pub crossovers_parent1: usize,
+    pub crossovers_parent2: usize,
+    pub variants_written: u64,
+    pub dnms: usize,
+    /// DNMs and postzygotic mutations at a site with another variant
+    pub dnm_collisions: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub twin: Option<Twin>,
+    /// DNM file the postzygotic mutations of an MZ twin were drawn from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub postzygotic_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub postzygotic: Option<usize>,
+    /// chromosomal errors of the embryo
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub chromosome_errors: Vec<ChromosomeError>,
+    /// sites with reads in the embryo biopsy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub biopsy_sites: Option<u64>,
+}
+
+/// Number of crossovers of `parentid` over all the chromosomes.
+fn count_crossovers(crossovers: &BTreeMap<String, Vec<(String, Crossover)>>, parentid: &str) -> usize {
+    crossovers.values()
+        .flatten()
+        .filter(|(parent, _)| parent == parentid)
+        .count()
+}
+
+impl SiblingSummary {
+    pub fn new(sibling: &SimulatedSibling, dnm_file: &str) -> Self {
+        let crossovers = &sibling.offspring.crossovers;
+        Self {
+            name: sibling.offspring.name.clone(),
+            dnm_file: dnm_file.to_string(),
+            crossovers_parent1: count_crossovers(crossovers, "parent1"),
+            crossovers_parent2: count_crossovers(crossovers, "parent2"),
+            variants_written: sibling.variants_written,
+            dnms: sibling.dnms,
+            dnm_collisions: sibling.dnm_collisions,
+            twin: sibling.twin.clone(),
+            postzygotic_file: sibling.postzygotic.as_ref().map(|(file, _)| file.clone()),
+            postzygotic: sibling.postzygotic.as_ref().map(|(_, count)| *count),
+            chromosome_errors: Vec::new(),
+            biopsy_sites: None,
+        }
+    }
+
+    pub fn tetraploid(sibling: &SimulatedTetraploid, dnm_file: &str) -> Self {
+        let crossovers = &sibling.offspring.crossovers;
+        Self {
+            name: sibling.offspring.name.clone(),
+            dnm_file: dnm_file.to_string(),
+            crossovers_parent1: count_crossovers(crossovers, "parent1"),
+            crossovers_parent2: count_crossovers(crossovers, "parent2"),
+            variants_written: sibling.variants_written,
+            dnms: sibling.dnms,
+            dnm_collisions: sibling.dnm_collisions,
+            twin: None,
+            postzygotic_file: None,
+            postzygotic: None,
+            chromosome_errors: Vec::new(),
+            biopsy_sites: None,
+        }
+    }
+}
+
+impl InputFile {
+    /// Lists the inputs with their checksums, computed in parallel on the
+    /// current thread pool. `files` holds (role, path) pairs.
+    pub fn checksum_all(files: &[(&str, String)]) -> Result<Vec<InputFile>> {
+        files.par_iter()
+            .map(|(role, path)| {
+                Ok(InputFile {
+                    role: role.to_string(),
+                    path: path.clone(),
+                    sha256: sha256_file(path)?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl RunManifest {
+    /// Manifest of a run of this version, the fields of the other modes
+    /// are left empty.
+    pub fn new(arguments: Vec<String>, seed: u64, parent1: &str, parent2: &str, recombination: RecombinationMode, inputs: Vec<InputFile>) -> Self {
+        Self {
+            meiosim_version: env!("CARGO_PKG_VERSION").to_string(),
+            arguments,
+            seed,
+            family_id: None,
+            parent1: parent1.to_string(),
+            parent2: parent2.to_string(),
+            parent_metadata: Vec::new(),
+            recombination,
+            achiasmatic: None,
+            ploidy: None,
+            embryo: None,
+            admixture: None,
+            pedigree: None,
+            cross: None,
+            gametes: Vec::new(),
+            coverage: None,
+            inputs,
+            siblings: Vec::new(),
+            relatedness: Vec::new(),
+        }
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        let mut outputfile = OutputFile::create(path)?;
+        serde_json::to_writer_pretty(&mut outputfile, self)
+            .map_err(|e| MeiosimError::io(path, e.into()))?;
+        outputfile.write_str("\n")
+    }
+}
+
+fn sha256_file(path: &str) -> Result<String> {
+    let file = File::open(path).map_err(|e| MeiosimError::io(path, e))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut BufReader::new(file), &mut hasher).map_err(|e| MeiosimError::io(path, e))?;
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
@@ -0,0 +1,438 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::cohort::Sex;
+use crate::recombination::RecombinationMapGenome;
+use crate::recombination::{generate_simple_chromosome_cx, generate_simple_regions_cx};
+use crate::regions::{self, Regions};
+use crate::recombination::Crossover;
+use crate::variants::VCFCollection;
+use crate::error::{MeiosimError, Result};
+use crate::seeds::{self, SeedTree};
+use crate::utils::OutputFile;
+
+use log::info;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+
+/// How crossovers are drawn for each meiosis.
+#[derive(Clone, Copy)]
+pub enum CrossoverModel<'a> {
+    /// Crossovers drawn from per-chromosome recombination maps.
+    Map(&'a RecombinationMapGenome),
+    /// A fixed number of crossovers per chromosome, uniformly placed.
+    Simple(u8),
+    /// A fixed number of crossovers per chromosome, uniformly placed in
+    /// the regions the simulation is restricted to.
+    SimpleInRegions(u8, &'a Regions),
+    /// No crossovers (achiasmatic meiosis), each chromosome is transmitted
+    /// whole.
+    None,
+}
+
+impl<'a> CrossoverModel<'a> {
+    /// Draws the crossovers of one meiosis of `parentid` on `chr`, `None`
+    /// if there is no recombination map for it.
+    pub fn generate_chromosome_cx(&self, chr: &str, parentid: &str, contig_size: u64, rng: &mut StdRng) -> Option<Vec<(String, Crossover)>> {
+        match self {
+            CrossoverModel::Map(genome_recomb_map) => {
+                genome_recomb_map.generate_chromosome_cx(chr, parentid, rng)
+            },
+            CrossoverModel::Simple(cxcount) => {
+                Some(generate_simple_chromosome_cx(chr, contig_size, parentid, rng, *cxcount))
+            },
+            CrossoverModel::SimpleInRegions(cxcount, regions) => {
+                Some(generate_simple_regions_cx(chr, regions.contig(chr), parentid, rng, *cxcount))
+            },
+            CrossoverModel::None => Some(Vec::new()),
+        }
+    }
+}
+
+/// Crossover models of the meioses of parent1 and parent2, which differ
+/// when the meiosis of one sex has no crossovers, as in Drosophila males.
+#[derive(Clone, Copy)]
+pub struct MeiosisModel<'a> {
+    pub parent1: CrossoverModel<'a>,
+    pub parent2: CrossoverModel<'a>,
+}
+
+impl<'a> MeiosisModel<'a> {
+    /// The same crossover model for both parents.
+    pub fn new(model: CrossoverModel<'a>) -> Self {
+        Self { parent1: model, parent2: model }
+    }
+
+    /// Removes the crossovers of the meiosis of `parentid` ("parent1" or
+    /// "parent2").
+    pub fn achiasmatic(mut self, parentid: &str) -> Result<Self> {
+        match parentid {
+            "parent1" => self.parent1 = CrossoverModel::None,
+            "parent2" => self.parent2 = CrossoverModel::None,
+            _ => return Err(MeiosimError::Config(format!("Unknown parent '{}', use parent1 or parent2", parentid))),
+        }
+        Ok(self)
+    }
+
+    pub fn parent(&self, parentid: &str) -> CrossoverModel<'a> {
+        match parentid {
+            "parent2" => self.parent2,
+            _ => self.parent1,
+        }
+    }
+}
+
+/// Resolves the achiasmatic parent, "male" or "female" to the parent of
+/// that sex given the sexes of parent1 and parent2 in the sample metadata,
+/// "parent1" or "parent2" to that parent. Returns the role of the parent.
+pub fn achiasmatic_parent(achiasmatic: &str, sexes: [Sex; 2]) -> Result<&'static str> {
+    let sex = match achiasmatic {
+        "parent1" => return Ok("parent1"),
+        "parent2" => return Ok("parent2"),
+        "male" => Sex::Male,
+        "female" => Sex::Female,
+        _ => return Err(MeiosimError::Config(format!(
+            "Unknown achiasmatic parent '{}', use male, female, parent1 or parent2", achiasmatic))),
+    };
+    match sexes {
+        [parent1, parent2] if parent1 == sex && parent2.opposite(&sex) => Ok("parent1"),
+        [parent1, parent2] if parent2 == sex && parent1.opposite(&sex) => Ok("parent2"),
+        [parent1, parent2] => Err(MeiosimError::Config(format!(
+            "--achiasmatic {} needs parents of opposite sexes in the sample metadata (--metadata), parent1 is {:?} and parent2 {:?}; use parent1 or parent2 otherwise",
+            achiasmatic, parent1, parent2))),
+    }
+}
+
+impl<'a> From<CrossoverModel<'a>> for MeiosisModel<'a> {
+    fn from(model: CrossoverModel<'a>) -> Self {
+        Self::new(model)
+    }
+}
+
+/// The two parents of a simulated family, as sample names of the population panel.
+#[derive(Clone, Debug)]
+pub struct Parents {
+    pub parent1: String,
+    pub parent2: String,
+}
+
+impl Parents {
+    pub fn new(parent1: &str, parent2: &str) -> Self {
+        Self {
+            parent1: parent1.to_string(),
+            parent2: parent2.to_string(),
+        }
+    }
+
+    pub fn check_in_panel(&self, popvars: &VCFCollection) -> Result<()> {
+        popvars.check_samples(&[self.parent1.clone(), self.parent2.clone()])
+    }
+}
+
+/// A stretch of a chromosome where the offspring carries the same parental
+/// haplotypes. `hap1` is the haplotype index (0/1) inherited from parent1
+/// and `hap2` the one inherited from parent2. Coordinates are 0-based,
+/// half-open.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HaplotypeSegment {
+    pub start: u64,
+    pub end: u64,
+    pub hap1: usize,
+    pub hap2: usize,
+}
+
+/// The outcome of the two meioses that produce one offspring.
+pub struct Offspring {
+    pub name: String,
+    pub parents: Parents,
+    /// crossovers from both parents, per chromosome, sorted by position
+    pub crossovers: BTreeMap<String, Vec<(String, Crossover)>>,
+    /// haplotype segments per chromosome, covering the whole contig
+    pub segments: BTreeMap<String, Vec<HaplotypeSegment>>,
+}
+
+/// Simulates the meioses of both parents and combines them into the
+/// haplotype segments carried by one offspring, for each of `chromosomes`.
+///
+/// The random streams are derived from `seeds` with the offspring name, the
+/// parent and the chromosome, so a chromosome of an offspring does not
+/// depend on the other chromosomes or offspring simulated.
+pub fn simulate_offspring<'a>(name: &str,
+        parents: &Parents,
+        model: impl Into<MeiosisModel<'a>>,
+        chromosomes: &[String],
+        contig_size: &HashMap<String, u64>,
+        verbose: bool,
+        seeds: &SeedTree) -> Result<Offspring> {
+    let model = model.into();
+
+    if verbose {
+        info!("Generating offspring for: {}", name);
+    }
+
+    let mut chr_vector = chromosomes.to_vec();
+    chr_vector.sort();
+
+    let mut crossovers = BTreeMap::new();
+    let mut segments = BTreeMap::new();
+
+    for chr in chr_vector {
+        let contig_size = *contig_size.get(&chr).ok_or_else(|| MeiosimError::ContigNotFound {
+            contig: chr.clone(),
+            source_name: "the contig sizes".to_string(),
+        })?;
+        if verbose {
+            info!("Chromosome: {}", chr);
+        }
+        let mut all_cx: Vec<(String, Crossover)> = Vec::new();
+        for parentid in ["parent1", "parent2"] {
+            let mut rng_cx = seeds.stream(&[name, parentid, &chr, seeds::CROSSOVERS]);
+            all_cx.extend(chromosome_crossovers(model.parent(parentid), &chr, parentid, contig_size, &mut rng_cx)?);
+        }
+
+        // this is per chromosome
+        // 1. choose a random haplotype for parent1
+        let initial_haplotype_parent1: usize = seeds
+            .stream(&[name, "parent1", &chr, seeds::INITIAL_HAPLOTYPE])
+            .gen_range(0..2);
+        // 2. choose a random haplotype for parent2
+        let initial_haplotype_parent2: usize = seeds
+            .stream(&[name, "parent2", &chr, seeds::INITIAL_HAPLOTYPE])
+            .gen_range(0..2);
+
+        if verbose {
+            info!("Initial haplotype parent1: {}", initial_haplotype_parent1);
+            info!("Initial haplotype parent2: {}", initial_haplotype_parent2);
+        }
+
+        // 3. combine cx from both parents, need to get a (hap1, hap2, position)
+        let chr_segments = combine_crossovers(&mut all_cx,
+                initial_haplotype_parent1,
+                initial_haplotype_parent2,
+                contig_size);
+
+        if verbose {
+            info!("Haplotypes: {:?}", chr_segments);
+        }
+
+        crossovers.insert(chr.clone(), all_cx);
+        segments.insert(chr, chr_segments);
+    }
+
+    Ok(Offspring {
+        name: name.to_string(),
+        parents: parents.clone(),
+        crossovers,
+        segments,
+    })
+}
+
+pub(crate) fn chromosome_crossovers(model: CrossoverModel,
+        chr: &str,
+        parentid: &str,
+        contig_size: u64,
+        rng_cx: &mut StdRng) -> Result<Vec<(String, Crossover)>> {
+    model.generate_chromosome_cx(chr, parentid, contig_size, rng_cx)
+        .ok_or_else(|| MeiosimError::ContigNotFound {
+            contig: chr.to_string(),
+            source_name: "the recombination maps".to_string(),
+        })
+}
+
+/// A stretch of a chromosome where a gamete carries the same haplotype
+/// (0/1) of its parent. Coordinates are 0-based, half-open.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameteSegment {
+    pub start: u64,
+    pub end: u64,
+    pub hap: usize,
+}
+
+/// The haploid product of one meiosis.
+pub struct Gamete {
+    pub name: String,
+    /// sample of the panel the gamete comes from
+    pub parent: String,
+    /// crossovers per chromosome, sorted by position
+    pub crossovers: BTreeMap<String, Vec<Crossover>>,
+    /// haplotype segments per chromosome, covering the whole contig
+    pub segments: BTreeMap<String, Vec<GameteSegment>>,
+}
+
+impl Gamete {
+    /// Writes the haplotype segments as a BED file with the haplotype of
+    /// the parent (0/1) in the 4th column, cut down to the `regions` the
+    /// simulation is restricted to.
+    pub fn write_haplotypes(&self, path: &str, regions: Option<&Regions>) -> Result<()> {
+        let mut outputfile = OutputFile::create(path)?;
+        for (chr, segments) in self.segments.iter() {
+            for segment in segments {
+                for (start, end) in regions::clip_to_regions(regions, chr, segment.start, segment.end) {
+                    outputfile.write_str(&format!("{}\t{}\t{}\t{}\n", chr, start, end, segment.hap))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the crossovers as `chr pos hap_before hap_after` lines, with
+    /// the 1-based position of the first base after the switch of
+    /// haplotype.
+    pub fn write_crossovers(&self, path: &str) -> Result<()> {
+        let mut outputfile = OutputFile::create(path)?;
+        for (chr, segments) in self.segments.iter() {
+            for pair in segments.windows(2) {
+                outputfile.write_str(&format!("{}\t{}\t{}\t{}\n", chr, pair[0].end + 1, pair[0].hap, pair[1].hap))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Simulates one meiosis of `parent` and returns the gamete, for each of
+/// `chromosomes`, with the streams of `seeds` named after the gamete and
+/// the chromosome.
+pub fn simulate_gamete(name: &str,
+        parent: &str,
+        model: CrossoverModel,
+        chromosomes: &[String],
+        contig_size: &HashMap<String, u64>,
+        seeds: &SeedTree) -> Result<Gamete> {
+    let mut crossovers = BTreeMap::new();
+    let mut segments = BTreeMap::new();
+    for chr in chromosomes {
+        let size = *contig_size.get(chr).ok_or_else(|| MeiosimError::ContigNotFound {
+            contig: chr.clone(),
+            source_name: "the contig sizes".to_string(),
+        })?;
+        let mut rng_cx = seeds.stream(&[name, chr, seeds::CROSSOVERS]);
+        let initial_hap = seeds.stream(&[name, chr, seeds::INITIAL_HAPLOTYPE]).gen_range(0..2);
+        let mut chr_cx: Vec<Crossover> = chromosome_crossovers(model, chr, "parent1", size, &mut rng_cx)?
+            .into_iter()
+            .map(|(_, crossover)| crossover)
+            .collect();
+        chr_cx.sort();
+        segments.insert(chr.clone(), chromatid_segments(&chr_cx, initial_hap, size));
+        crossovers.insert(chr.clone(), chr_cx);
+    }
+    Ok(Gamete {
+        name: name.to_string(),
+        parent: parent.to_string(),
+        crossovers,
+        segments,
+    })
+}
+
+// segments of a chromatid that starts on `initial_hap` and switches
+// haplotype at each crossover
+pub(crate) fn chromatid_segments(crossovers: &[Crossover], initial_hap: usize, contig_size: u64) -> Vec<GameteSegment> {
+    let mut segments = Vec::with_capacity(crossovers.len() + 1);
+    let (mut start, mut hap) = (0, initial_hap);
+    for crossover in crossovers {
+        segments.push(GameteSegment { start, end: crossover.position, hap });
+        start = crossover.position;
+        hap = 1 - hap;
+    }
+    segments.push(GameteSegment { start, end: contig_size, hap });
+    segments
+}
+
+/// Segments of the offspring between the crossovers of both parents, the
+/// crossovers are sorted by position first.
+fn combine_crossovers(all_cx: &mut [(String, Crossover)], initial_hap1: usize, initial_hap2: usize, contig_size: u64) -> Vec<HaplotypeSegment> {
+    all_cx.sort_by(|a, b| {
+        // a cmp b should be ascending order
+        a.1.cmp(&b.1)
+    } );
+
+    let mut all_hap: Vec<HaplotypeSegment> = Vec::new();
+    // note that this is 0-based
+    let mut last_position: u64 = 0;
+
+    let mut current_hap1 = initial_hap1;
+    let mut current_hap2 = initial_hap2;
+
+    // i am pretty sure if the length of cx is 0, this won't run, that is ok
+    for (parent, crossover) in all_cx.iter() {
+        let inst_position = crossover.position;
+        all_hap.push(HaplotypeSegment {
+            start: last_position,
+            end: inst_position,
+            hap1: current_hap1,
+            hap2: current_hap2,
+        });
+
+        if parent == "parent1" {  // we should create a type for this?
+            current_hap1 = if current_hap1 == 0 {1} else {0};
+        } else {
+            current_hap2 = if current_hap2 == 0 {1} else {0};
+        }
+
+        last_position = inst_position;
+    }
+
+    // add last position, that is the end of the chromosome
+    all_hap.push(HaplotypeSegment {
+        start: last_position,
+        end: contig_size,
+        hap1: current_hap1,
+        hap2: current_hap2,
+    });
+    all_hap
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{achiasmatic_parent, combine_crossovers, simulate_gamete, CrossoverModel};
+    use crate::cohort::Sex;
+    use crate::recombination::Crossover;
+    use crate::seeds::SeedTree;
+
+    #[test]
+    fn test_combine_crossovers() {
+        let cx = |parent: &str, position: u64| {
+            (parent.to_string(), Crossover { seqname: "chr1".to_string(), position })
+        };
+        let mut all_cx = vec![cx("parent1", 300), cx("parent2", 250), cx("parent1", 100)];
+        let segments = combine_crossovers(&mut all_cx, 0, 1, 1000);
+
+        assert_eq!(segments.len(), 4, "Expected one segment more than crossovers");
+        assert_eq!(segments[0].start, 0, "Segments do not start at the beginning of the contig");
+        assert_eq!(segments[3].end, 1000, "Segments do not reach the end of the contig");
+        let haps: Vec<(usize, usize)> = segments.iter().map(|s| (s.hap1, s.hap2)).collect();
+        assert_eq!(haps, vec![(0, 1), (1, 1), (1, 0), (0, 0)]);
+        for pair in segments.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start, "Segments are not contiguous");
+        }
+        let positions: Vec<u64> = all_cx.iter().map(|(_, crossover)| crossover.position).collect();
+        assert_eq!(positions, vec![100, 250, 300], "Crossovers are not sorted by position");
+    }
+    #[test]
+    fn test_achiasmatic_parent_by_sex() {
+        assert_eq!(achiasmatic_parent("male", [Sex::Female, Sex::Male]).unwrap(), "parent2");
+        assert_eq!(achiasmatic_parent("female", [Sex::Female, Sex::Male]).unwrap(), "parent1");
+        assert_eq!(achiasmatic_parent("parent2", [Sex::Unknown, Sex::Unknown]).unwrap(), "parent2");
+        assert!(achiasmatic_parent("male", [Sex::Unknown, Sex::Male]).is_err(), "A parent of unknown sex was taken as female");
+        assert!(achiasmatic_parent("male", [Sex::Male, Sex::Male]).is_err(), "Two male parents were accepted");
+        assert!(achiasmatic_parent("father", [Sex::Female, Sex::Male]).is_err());
+    }
+    #[test]
+    fn test_gamete_switches_at_crossovers() {
+        let chromosomes = vec!["chr1".to_string()];
+        let contig_size = HashMap::from([("chr1".to_string(), 10_000)]);
+        let gamete = simulate_gamete("gamete0", "A", CrossoverModel::Simple(3), &chromosomes, &contig_size, &SeedTree::new(1)).unwrap();
+        let segments = &gamete.segments["chr1"];
+        let crossovers: Vec<u64> = gamete.crossovers["chr1"].iter().map(|crossover| crossover.position).collect();
+        assert_eq!(segments.len(), crossovers.len() + 1);
+        assert_eq!((segments[0].start, segments.last().unwrap().end), (0, 10_000));
+        for (pair, position) in segments.windows(2).zip(crossovers) {
+            assert_eq!(pair[0].end, position);
+            assert_ne!(pair[0].hap, pair[1].hap);
+        }
+
+        let achiasmatic = simulate_gamete("gamete0", "A", CrossoverModel::None, &chromosomes, &contig_size, &SeedTree::new(1)).unwrap();
+        assert_eq!(achiasmatic.segments["chr1"].len(), 1);
+    }
+}
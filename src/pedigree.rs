@@ -0,0 +1,224 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::error::{MeiosimError, Result};
+use crate::founders::FounderGenome;
+use crate::meiosis::{CrossoverModel, Parents, simulate_offspring};
+use crate::seeds::SeedTree;
+
+/// A member of a pedigree, either a founder, which is a sample of the
+/// panel, or the offspring of two other members.
+#[derive(Clone, Debug, Serialize)]
+pub struct PedigreeMember {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent1: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent2: Option<String>,
+}
+
+impl PedigreeMember {
+    pub fn founder(sample: &str) -> Self {
+        Self { name: sample.to_string(), parent1: None, parent2: None }
+    }
+
+    pub fn offspring(name: &str, parent1: &str, parent2: &str) -> Self {
+        Self { name: name.to_string(), parent1: Some(parent1.to_string()), parent2: Some(parent2.to_string()) }
+    }
+
+    pub fn is_founder(&self) -> bool {
+        self.parent1.is_none()
+    }
+}
+
+/// Consanguineous unions simulated by [`Pedigree::consanguineous`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Consanguinity {
+    /// children of two siblings, expected autozygosity 1/16
+    FirstCousins,
+    /// an uncle and the daughter of his sibling, expected autozygosity 1/8
+    UncleNiece,
+}
+
+impl FromStr for Consanguinity {
+    type Err = MeiosimError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "first-cousins" => Ok(Consanguinity::FirstCousins),
+            "uncle-niece" => Ok(Consanguinity::UncleNiece),
+            _ => Err(MeiosimError::Config(format!(
+                "Unknown consanguineous union '{}', use first-cousins or uncle-niece", text))),
+        }
+    }
+}
+
+impl Consanguinity {
+    /// Number of founders of the pedigree.
+    pub fn founders(&self) -> usize {
+        match self {
+            Consanguinity::FirstCousins => 4,
+            Consanguinity::UncleNiece => 3,
+        }
+    }
+}
+
+/// Members of a pedigree, sorted so that parents come before their
+/// children.
+#[derive(Clone, Debug)]
+pub struct Pedigree {
+    members: Vec<PedigreeMember>,
+}
+
+impl Pedigree {
+    /// Sorts the members and checks that every parent is a member.
+    /// `source_name` names the pedigree in the errors.
+    pub fn new(members: Vec<PedigreeMember>, source_name: &str) -> Result<Self> {
+        let mut names = HashSet::new();
+        for member in members.iter() {
+            if !names.insert(member.name.as_str()) {
+                return Err(MeiosimError::input(source_name, &format!("{} is listed twice", member.name)));
+            }
+        }
+        for member in members.iter() {
+            for parent in [&member.parent1, &member.parent2].into_iter().flatten() {
+                if !names.contains(parent.as_str()) {
+                    return Err(MeiosimError::input(source_name,
+                        &format!("{}, parent of {}, is not a member of the pedigree", parent, member.name)));
+                }
+            }
+        }
+
+        let mut sorted: Vec<PedigreeMember> = Vec::with_capacity(members.len());
+        let mut placed: HashSet<String> = HashSet::new();
+        let mut pending = members;
+        while !pending.is_empty() {
+            let (ready, waiting): (Vec<PedigreeMember>, Vec<PedigreeMember>) = pending.into_iter()
+                .partition(|member| [&member.parent1, &member.parent2].into_iter().flatten().all(|parent| placed.contains(parent)));
+            if ready.is_empty() {
+                return Err(MeiosimError::input(source_name,
+                    &format!("{} is its own ancestor", waiting[0].name)));
+            }
+            placed.extend(ready.iter().map(|member| member.name.clone()));
+            sorted.extend(ready);
+            pending = waiting;
+        }
+        Ok(Self { members: sorted })
+    }
+
+    pub fn from_path(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| MeiosimError::io(path, e))?;
+        Self::from_table(&text, path)
+    }
+
+    /// Parses a whitespace separated table with the columns
+    /// `individual parent1 parent2`, founders having `0` as parents. An
+    /// individual with the same parent twice is from self-fertilization.
+    pub fn from_table(text: &str, path: &str) -> Result<Self> {
+        let mut members = Vec::new();
+        for (line_idx, line) in text.lines().enumerate() {
+            let line_number = line_idx + 1;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err(MeiosimError::parse(path, line_number,
+                    &format!("expected 3 columns (individual, parent1, parent2), found {}", fields.len())));
+            }
+            members.push(match (fields[1], fields[2]) {
+                ("0", "0") => PedigreeMember::founder(fields[0]),
+                ("0", _) | (_, "0") => {
+                    return Err(MeiosimError::parse(path, line_number,
+                        &format!("{} needs either both parents or none", fields[0])));
+                },
+                (parent1, parent2) => PedigreeMember::offspring(fields[0], parent1, parent2),
+            });
+        }
+        if members.is_empty() {
+            return Err(MeiosimError::input(path, "the pedigree is empty"));
+        }
+        Self::new(members, path)
+    }
+
+    /// Pedigree of a consanguineous union between founders of the panel,
+    /// with the two members that form the union.
+    pub fn consanguineous(kind: Consanguinity, founders: &[String]) -> Result<(Self, Parents)> {
+        if founders.len() != kind.founders() {
+            return Err(MeiosimError::Config(format!("{} founders are needed, {} given", kind.founders(), founders.len())));
+        }
+        let founder = |idx: usize| PedigreeMember::founder(&founders[idx]);
+        let (members, couple) = match kind {
+            Consanguinity::FirstCousins => (vec![
+                founder(0), founder(1), founder(2), founder(3),
+                PedigreeMember::offspring("sibling1", &founders[0], &founders[1]),
+                PedigreeMember::offspring("sibling2", &founders[0], &founders[1]),
+                PedigreeMember::offspring("cousin1", "sibling1", &founders[2]),
+                PedigreeMember::offspring("cousin2", "sibling2", &founders[3]),
+            ], Parents::new("cousin1", "cousin2")),
+            Consanguinity::UncleNiece => (vec![
+                founder(0), founder(1), founder(2),
+                PedigreeMember::offspring("uncle", &founders[0], &founders[1]),
+                PedigreeMember::offspring("sibling", &founders[0], &founders[1]),
+                PedigreeMember::offspring("niece", "sibling", &founders[2]),
+            ], Parents::new("uncle", "niece")),
+        };
+        Ok((Self::new(members, "the consanguineous pedigree")?, couple))
+    }
+
+    pub fn members(&self) -> &[PedigreeMember] {
+        &self.members
+    }
+
+    /// Founders, the samples of the panel the pedigree starts from.
+    pub fn founders(&self) -> Vec<String> {
+        self.members.iter()
+            .filter(|member| member.is_founder())
+            .map(|member| member.name.clone())
+            .collect()
+    }
+
+    /// Simulates the genomes of all the members, parents first. The
+    /// meioses of each member draw from the streams of `seeds` named after
+    /// it.
+    pub fn simulate(&self,
+            model: CrossoverModel,
+            chromosomes: &[String],
+            contig_size: &HashMap<String, u64>,
+            seeds: &SeedTree) -> Result<BTreeMap<String, FounderGenome>> {
+        let mut genomes: BTreeMap<String, FounderGenome> = BTreeMap::new();
+        for member in self.members.iter() {
+            let genome = match (&member.parent1, &member.parent2) {
+                (Some(parent1), Some(parent2)) => {
+                    let parents = Parents::new(parent1, parent2);
+                    let offspring = simulate_offspring(&member.name, &parents, model, chromosomes, contig_size, false, seeds)?;
+                    FounderGenome::offspring(&genomes[parent1], &genomes[parent2], &offspring)?
+                },
+                _ => FounderGenome::founder(&member.name, chromosomes, contig_size)?,
+            };
+            genomes.insert(member.name.clone(), genome);
+        }
+        Ok(genomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pedigree;
+
+    #[test]
+    fn test_pedigree_table() {
+        let pedigree = Pedigree::from_table("child A B\nA 0 0\nB 0 0\ngrandchild child C\nC 0 0\n", "ped.txt").unwrap();
+        let names: Vec<&str> = pedigree.members().iter().map(|member| member.name.as_str()).collect();
+        assert_eq!(names, vec!["A", "B", "C", "child", "grandchild"]);
+        assert_eq!(pedigree.founders(), vec!["A", "B", "C"]);
+
+        assert!(Pedigree::from_table("A 0 0\nchild A D\n", "ped.txt").is_err());
+        assert!(Pedigree::from_table("A 0 0\nchild A 0\n", "ped.txt").is_err());
+        assert!(Pedigree::from_table("A 0 0\nB C A\nC B A\n", "ped.txt").is_err());
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use rand::Rng;
+
+use crate::dnm::{ChildMutations, MergedMutations, MutationMerge, resample_genome};
+use crate::error::{MeiosimError, Result};
+use crate::founders::{inherit, FounderTract};
+use crate::meiosis::{chromatid_segments, chromosome_crossovers, CrossoverModel, GameteSegment, MeiosisModel, Parents};
+use crate::recombination::Crossover;
+use crate::regions::{self, Regions};
+use crate::seeds::{self, SeedTree};
+use crate::utils::OutputFile;
+use crate::variants::VCFCollection;
+
+/// Pairs of homologs (0-3) of a parent that form the two bivalents of a
+/// chromosome.
+pub type Bivalents = [(usize, usize); 2];
+
+/// Genome of a simulated autotetraploid as the founder tracts of its four
+/// homologs, per chromosome. The two homologs inherited from parent1 come
+/// first. Founders are samples of a tetraploid panel, with haplotype
+/// indices 0 to 3.
+#[derive(Clone, Debug, Default)]
+pub struct TetraploidGenome {
+    pub chromosomes: BTreeMap<String, [Vec<FounderTract>; 4]>,
+}
+
+impl TetraploidGenome {
+    /// Genome of a sample of the panel, each homolog being a single tract.
+    pub fn founder(sample: &str, chromosomes: &[String], contig_size: &HashMap<String, u64>) -> Result<Self> {
+        let mut genome = Self::default();
+        for chr in chromosomes {
+            let size = *contig_size.get(chr).ok_or_else(|| MeiosimError::ContigNotFound {
+                contig: chr.clone(),
+                source_name: "the contig sizes".to_string(),
+            })?;
+            let tract = |hap: usize| vec![FounderTract {
+                start: 0,
+                end: size,
+                founder: sample.to_string(),
+                hap,
+            }];
+            genome.chromosomes.insert(chr.clone(), [tract(0), tract(1), tract(2), tract(3)]);
+        }
+        Ok(genome)
+    }
+
+    /// Genome of `offspring`, following the homolog segments it inherited
+    /// from the genomes of its two parents.
+    pub fn offspring(parent1: &TetraploidGenome, parent2: &TetraploidGenome, offspring: &TetraploidOffspring) -> Result<Self> {
+        let mut genome = Self::default();
+        for (chr, chromatids) in offspring.segments.iter() {
+            let parent1_homologs = parent1.homologs(chr)?;
+            let parent2_homologs = parent2.homologs(chr)?;
+            let homolog = |idx: usize| {
+                let parent = if idx < 2 { parent1_homologs } else { parent2_homologs };
+                inherit(parent, chromatids[idx].iter().map(|segment| (segment.start, segment.end, segment.hap)))
+            };
+            genome.chromosomes.insert(chr.clone(), [homolog(0), homolog(1), homolog(2), homolog(3)]);
+        }
+        Ok(genome)
+    }
+
+    pub fn homologs(&self, chr: &str) -> Result<&[Vec<FounderTract>; 4]> {
+        self.chromosomes.get(chr).ok_or_else(|| MeiosimError::ContigNotFound {
+            contig: chr.to_string(),
+            source_name: "the founder tracts".to_string(),
+        })
+    }
+
+    /// Samples of the panel the genome is made of.
+    pub fn founders(&self) -> BTreeSet<&str> {
+        self.chromosomes.values()
+            .flatten()
+            .flatten()
+            .map(|tract| tract.founder.as_str())
+            .collect()
+    }
+}
+
+/// The outcome of the two meioses that produce one autotetraploid
+/// offspring, each giving a diploid gamete with one chromatid of each of
+/// the two bivalents of the parent.
+pub struct TetraploidOffspring {
+    pub name: String,
+    pub parents: Parents,
+    /// homologs (0-3) of parent1 and parent2 that paired into bivalents,
+    /// per chromosome
+    pub bivalents: BTreeMap<String, [Bivalents; 2]>,
+    /// crossovers of the bivalents of both parents, per chromosome, sorted
+    /// by position
+    pub crossovers: BTreeMap<String, Vec<(String, Crossover)>>,
+    /// segments of the four homologs of the offspring per chromosome, with
+    /// the homolog (0-3) of the parent they come from in `hap`
+    pub segments: BTreeMap<String, [Vec<GameteSegment>; 4]>,
+}
+
+/// Simulates the meioses of two autotetraploid parents with random
+/// bivalent pairing, for each of `chromosomes`.
+///
+/// On each chromosome, homolog 0 pairs with one of the other three drawn
+/// at random, and the remaining two form the second bivalent. Crossovers
+/// and the transmitted chromatid are drawn per bivalent, from streams of
+/// `seeds` named after the offspring, the parent, the chromosome and the
+/// bivalent.
+pub fn simulate_tetraploid_offspring(name: &str,
+        parents: &Parents,
+        model: MeiosisModel,
+        chromosomes: &[String],
+        contig_size: &HashMap<String, u64>,
+        seeds: &SeedTree) -> Result<TetraploidOffspring> {
+    let mut bivalents = BTreeMap::new();
+    let mut crossovers = BTreeMap::new();
+    let mut segments = BTreeMap::new();
+    for chr in chromosomes {
+        let size = *contig_size.get(chr).ok_or_else(|| MeiosimError::ContigNotFound {
+            contig: chr.clone(),
+            source_name: "the contig sizes".to_string(),
+        })?;
+        let mut chr_cx = Vec::new();
+        let (bivalents1, [chromatid1, chromatid2]) = tetraploid_meiosis(name, "parent1", model.parent1, chr, size, &mut chr_cx, seeds)?;
+        let (bivalents2, [chromatid3, chromatid4]) = tetraploid_meiosis(name, "parent2", model.parent2, chr, size, &mut chr_cx, seeds)?;
+        chr_cx.sort_by(|a, b| a.1.cmp(&b.1));
+        bivalents.insert(chr.clone(), [bivalents1, bivalents2]);
+        crossovers.insert(chr.clone(), chr_cx);
+        segments.insert(chr.clone(), [chromatid1, chromatid2, chromatid3, chromatid4]);
+    }
+    Ok(TetraploidOffspring {
+        name: name.to_string(),
+        parents: parents.clone(),
+        bivalents,
+        crossovers,
+        segments,
+    })
+}
+
+// pairs the homologs of `parentid` into two bivalents and draws the
+// chromatid each of them transmits
+fn tetraploid_meiosis(name: &str,
+        parentid: &str,
+        model: CrossoverModel,
+        chr: &str,
+        contig_size: u64,
+        all_cx: &mut Vec<(String, Crossover)>,
+        seeds: &SeedTree) -> Result<(Bivalents, [Vec<GameteSegment>; 2])> {
+    let partner = seeds.stream(&[name, parentid, chr, seeds::BIVALENTS]).gen_range(1..4);
+    let others: Vec<usize> = (1..4).filter(|homolog| *homolog != partner).collect();
+    let bivalents = [(0, partner), (others[0], others[1])];
+    let mut chromatids: [Vec<GameteSegment>; 2] = Default::default();
+    for (idx, (homolog1, homolog2)) in bivalents.iter().enumerate() {
+        let bivalent = idx.to_string();
+        let mut rng_cx = seeds.stream(&[name, parentid, chr, &bivalent, seeds::CROSSOVERS]);
+        let mut bivalent_cx = chromosome_crossovers(model, chr, parentid, contig_size, &mut rng_cx)?;
+        bivalent_cx.sort_by(|a, b| a.1.cmp(&b.1));
+        let initial_hap = seeds.stream(&[name, parentid, chr, &bivalent, seeds::INITIAL_HAPLOTYPE]).gen_range(0..2);
+        let positions: Vec<Crossover> = bivalent_cx.iter().map(|(_, crossover)| crossover.clone()).collect();
+        chromatids[idx] = chromatid_segments(&positions, initial_hap, contig_size).into_iter()
+            .map(|segment| GameteSegment {
+                hap: if segment.hap == 0 { *homolog1 } else { *homolog2 },
+                ..segment
+            })
+            .collect();
+        all_cx.extend(bivalent_cx);
+    }
+    Ok((bivalents, chromatids))
+}
+
+/// Writes the homolog segments of `offspring` as a BED file with the
+/// homolog of the parent carried by each of the four homologs of the
+/// offspring in the 4th to 7th columns, cut down to the `regions` the
+/// simulation is restricted to.
+pub fn write_tetraploid_haplotypes(path: &str, offspring: &TetraploidOffspring, regions: Option<&Regions>) -> Result<()> {
+    let mut outputfile = OutputFile::create(path)?;
+    for (chr, chromatids) in offspring.segments.iter() {
+        let mut idx = [0; 4];
+        let mut start = 0;
+        while idx.iter().zip(chromatids.iter()).all(|(idx, chromatid)| *idx < chromatid.len()) {
+            let segments: Vec<&GameteSegment> = idx.iter().zip(chromatids.iter()).map(|(idx, chromatid)| &chromatid[*idx]).collect();
+            let end = segments.iter().map(|segment| segment.end).min().unwrap_or(start);
+            if start < end {
+                for (start, end) in regions::clip_to_regions(regions, chr, start, end) {
+                    outputfile.write_str(&format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                        chr, start, end, segments[0].hap, segments[1].hap, segments[2].hap, segments[3].hap))?;
+                }
+                start = end;
+            }
+            for (idx, segment) in idx.iter_mut().zip(segments.iter()) {
+                if segment.end == end {
+                    *idx += 1;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the genotypes of autotetraploid individuals from the founder
+/// homologs they are made of, one file per genome in `outputfiles`, as
+/// `chr pos ref alt a|b|c|d` lines (1-based) for the sites where any
+/// homolog carries the alternative allele. The DNMs of each individual in
+/// `mutations` are written among them in position order, as a single
+/// copy. Returns the number of variants written for each individual, DNMs
+/// excluded, and its merged DNMs.
+pub fn write_tetraploid_genotypes(popvars: &VCFCollection,
+        genomes: &[TetraploidGenome],
+        chromosomes: &[String],
+        verbose: bool,
+        outputfiles: &mut [OutputFile],
+        mutations: &[ChildMutations]) -> Result<(Vec<u64>, Vec<MergedMutations>)> {
+    let mut written = vec![0; outputfiles.len()];
+    let mut merged: Vec<MergedMutations> = outputfiles.iter().map(|_| MergedMutations::default()).collect();
+    let genome = resample_genome(mutations, popvars.reference_check())?;
+    for chr in chromosomes {
+        let vcf_obj = popvars.vcfs.get(chr).ok_or_else(|| MeiosimError::ContigNotFound {
+            contig: chr.to_string(),
+            source_name: "the population VCFs".to_string(),
+        })?;
+        // each genome is streamed as two children, one per pair of
+        // homologs
+        let mut children = Vec::with_capacity(2 * genomes.len());
+        for genome in genomes {
+            let [hap1, hap2, hap3, hap4] = genome.homologs(chr)?;
+            children.push([hap1.as_slice(), hap2.as_slice()]);
+            children.push([hap3.as_slice(), hap4.as_slice()]);
+        }
+        let mut merges = mutations.iter()
+            .map(|child_mutations| MutationMerge::new(chr, child_mutations, popvars.regions(), genome.as_ref())?.tetraploid())
+            .collect::<Result<Vec<_>>>()?;
+        for family_genotypes in vcf_obj.stream_founder_genotypes(&children, verbose)? {
+            let family_genotypes = family_genotypes?;
+            for (genome_idx, outputfile) in outputfiles.iter_mut().enumerate() {
+                let (Some((a, b)), Some((c, d))) = (family_genotypes.gts[2 * genome_idx], family_genotypes.gts[2 * genome_idx + 1]) else {
+                    continue;
+                };
+                if [a, b, c, d].iter().all(|allele| *allele == 0) {
+                    continue;
+                }
+                match merges.get_mut(genome_idx) {
+                    Some(merge) => merge.write_inherited_tetraploid(family_genotypes.pos, &family_genotypes.alleles, [a, b, c, d], outputfile)?,
+                    None => outputfile.write_str(&format!("{}\t{}\t{}\t{}\t{}|{}|{}|{}\n",
+                        family_genotypes.seqname,
+                        family_genotypes.pos + 1,
+                        family_genotypes.alleles[0],
+                        family_genotypes.alleles[1],
+                        a, b, c, d))?,
+                }
+                written[genome_idx] += 1;
+            }
+        }
+        for ((merge, outputfile), merged) in merges.into_iter().zip(outputfiles.iter_mut()).zip(merged.iter_mut()) {
+            merged.append(merge.finish(outputfile)?);
+        }
+    }
+    Ok((written, merged))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{simulate_tetraploid_offspring, TetraploidGenome};
+    use crate::meiosis::{CrossoverModel, MeiosisModel, Parents};
+    use crate::seeds::SeedTree;
+
+    #[test]
+    fn test_tetraploid_meiosis() {
+        let chromosomes = vec!["chr1".to_string(), "chr2".to_string()];
+        let contig_size = HashMap::from([("chr1".to_string(), 100_000), ("chr2".to_string(), 50_000)]);
+        let parents = Parents::new("A", "B");
+        let parent1 = TetraploidGenome::founder("A", &chromosomes, &contig_size).unwrap();
+        let parent2 = TetraploidGenome::founder("B", &chromosomes, &contig_size).unwrap();
+        let model = MeiosisModel::new(CrossoverModel::Simple(2)).achiasmatic("parent2").unwrap();
+        for sibling in 0..20 {
+            let name = format!("sib{}", sibling);
+            let offspring = simulate_tetraploid_offspring(&name, &parents, model, &chromosomes, &contig_size, &SeedTree::new(7)).unwrap();
+            for chr in chromosomes.iter() {
+                // the two bivalents of each parent hold all four homologs
+                for [first, second] in offspring.bivalents[chr].iter() {
+                    let mut homologs = vec![first.0, first.1, second.0, second.1];
+                    homologs.sort();
+                    assert_eq!(homologs, vec![0, 1, 2, 3]);
+                }
+                // a gamete never carries both homologs of a bivalent at
+                // the same position
+                let chromatids = &offspring.segments[chr];
+                for position in [0, 25_000, 49_999] {
+                    let hap_at = |idx: usize| chromatids[idx].iter().find(|s| s.start <= position && position < s.end).unwrap().hap;
+                    assert_ne!(hap_at(0), hap_at(1));
+                    assert_ne!(hap_at(2), hap_at(3));
+                }
+                // the achiasmatic parent transmits whole homologs
+                assert_eq!(chromatids[2].len(), 1);
+                assert_eq!(offspring.crossovers[chr].iter().filter(|(parent, _)| parent == "parent1").count(), 4);
+            }
+            let genome = TetraploidGenome::offspring(&parent1, &parent2, &offspring).unwrap();
+            assert_eq!(genome.founders().into_iter().collect::<Vec<_>>(), vec!["A", "B"]);
+        }
+    }
+}
@@ -1,18 +1,22 @@
 
 use crate::utils::list_files_in_directory;
+use crate::error::{MeiosimError, Result};
+use crate::regions::Regions;
 
 use rand::Rng;
 use rand::rngs::StdRng;
 
 use rand_distr::{Poisson, Distribution};
 
+use log::warn;
+
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 use std::collections::HashMap;
 use std::cmp;
 
-#[derive(Clone, Debug, Eq, Default, Hash)]
+#[derive(Clone, Debug, Eq, Default)]
 pub struct Crossover {
     pub seqname: String,
     pub position: u64
@@ -77,8 +81,7 @@ impl RecombinationSegment {
         //println!("lambda: {}", cxlambda);
         let poi = Poisson::new(cxlambda).unwrap();
         let recombination_count = poi.sample(rng);
-        let recombination_count_u8: u8 = recombination_count as u8;
-        recombination_count_u8
+        recombination_count as u8
     }
     pub fn get_cx_position(&self, next_segment: &RecombinationSegment, recombination_count: u8, rnd: &mut StdRng) -> Vec<u64> {
         let mut positions = Vec::new();
@@ -99,41 +102,93 @@ impl RecombinationMap {
             segments,
         }
     }
-    pub fn parse_to_recombination_map(file_path: &str, has_header: bool) -> io::Result<Self> {
+    pub fn parse_to_recombination_map(file_path: &str, has_header: bool) -> Result<Self> {
         // note that one file always needs to be one chromosome!!!
-        let input_file = File::open(&Path::new(file_path))?;
+        let input_file = File::open(Path::new(file_path))
+            .map_err(|e| MeiosimError::io(file_path, e))?;
         let reader = io::BufReader::new(input_file);
-        let mut lines = reader.lines();
+        let mut lines = reader.lines().enumerate();
 
         if has_header {
             let _ = lines.next(); // Skip header line
         }
 
         let mut current_seqname = String::new();
-        let mut segments = Vec::new();
+        let mut segments: Vec<RecombinationSegment> = Vec::new();
 
-        for line in lines {
-            if let Ok(line) = line {
-                let parts: Vec<&str> = line.split(' ').collect();
-                if parts.len() == 4 {
-                    let seqname = parts[0].to_string();
-                    if current_seqname != seqname && !segments.is_empty() {
-                        panic!("Multiple chromosomes in one file are not supported");
-                    }
-                    let position = parts[3].parse::<u64>().unwrap();
-                    let centimorgan = parts[2].parse::<f64>().unwrap();
-                    current_seqname = seqname.clone();
-                    segments.push(RecombinationSegment::new(seqname, position, centimorgan));
-                } else {
-                    panic!("Invalid number of columns in recombination map file");
+        for (line_idx, line) in lines {
+            let line = line.map_err(|e| MeiosimError::io(file_path, e))?;
+            let line_number = line_idx + 1;
+            let parts: Vec<&str> = line.split(' ').collect();
+            if parts.len() != 4 {
+                return Err(MeiosimError::parse(file_path, line_number,
+                    &format!("expected 4 columns in recombination map, found {}", parts.len())));
+            }
+            let seqname = parts[0].to_string();
+            if current_seqname != seqname && !segments.is_empty() {
+                return Err(MeiosimError::parse(file_path, line_number,
+                    "multiple chromosomes in one file are not supported"));
+            }
+            let position = parts[3].parse::<u64>().map_err(|_| {
+                MeiosimError::parse(file_path, line_number, &format!("invalid position '{}'", parts[3]))
+            })?;
+            let centimorgan = parts[2].parse::<f64>().map_err(|_| {
+                MeiosimError::parse(file_path, line_number, &format!("invalid genetic position '{}'", parts[2]))
+            })?;
+            if let Some(last_segment) = segments.last() {
+                // crossover positions are drawn between consecutive positions
+                // and the count from the cM difference, both need to increase
+                if position < last_segment.position
+                    || (position == last_segment.position && centimorgan != last_segment.centimorgan) {
+                    return Err(MeiosimError::parse(file_path, line_number, "positions are not sorted"));
+                }
+                if centimorgan < last_segment.centimorgan {
+                    return Err(MeiosimError::parse(file_path, line_number, "genetic positions are decreasing"));
                 }
             }
+            current_seqname = seqname.clone();
+            segments.push(RecombinationSegment::new(seqname, position, centimorgan));
         }
 
         let recombination_map = RecombinationMap::new(current_seqname, segments);
 
         Ok(recombination_map)
     }
+    /// Genetic position at `position`, interpolated between the positions
+    /// of the map and flat beyond its ends.
+    fn centimorgan_at(&self, position: u64) -> f64 {
+        let idx = self.segments.partition_point(|segment| segment.position <= position);
+        match (idx.checked_sub(1).map(|idx| &self.segments[idx]), self.segments.get(idx)) {
+            (Some(before), Some(after)) => {
+                let fraction = (position - before.position) as f64 / (after.position - before.position) as f64;
+                before.centimorgan + fraction * (after.centimorgan - before.centimorgan)
+            },
+            (Some(before), None) => before.centimorgan,
+            (None, Some(after)) => after.centimorgan,
+            (None, None) => 0.0,
+        }
+    }
+
+    /// The map cut down to `intervals` (sorted, non-overlapping): the
+    /// genetic length between the intervals is removed, so that crossovers
+    /// are only drawn inside them.
+    fn restrict(&self, intervals: &[(u64, u64)]) -> Self {
+        let mut segments = Vec::new();
+        let mut removed = 0.0;
+        let mut last_end: Option<u64> = None;
+        for (start, end) in intervals {
+            removed += self.centimorgan_at(*start) - last_end.map_or(0.0, |last_end| self.centimorgan_at(last_end));
+            let inside = self.segments.iter()
+                .map(|segment| segment.position)
+                .filter(|position| start < position && position < end);
+            for position in std::iter::once(*start).chain(inside).chain(std::iter::once(*end)) {
+                segments.push(RecombinationSegment::new(self.seqname.clone(), position, self.centimorgan_at(position) - removed));
+            }
+            last_end = Some(*end);
+        }
+        Self::new(self.seqname.clone(), segments)
+    }
+
     pub fn generate_cx(&self, rng_cx: &mut StdRng) -> Vec<Crossover>{
         let mut vec_out = Vec::new();
         for i in 0..self.segments.len() {
@@ -143,7 +198,6 @@ impl RecombinationMap {
             }
             let next_segment = &self.segments[next_segment_id];
             let ncx = self.segments[i].sample_from_segment(next_segment, rng_cx);
-            let ncx_usize = ncx as usize;
             if ncx == 0 {
                 continue;
             } else {
@@ -151,10 +205,10 @@ impl RecombinationMap {
                     .get_cx_position(next_segment,
                                      ncx,
                                      rng_cx);
-                for posid in 0..ncx_usize {
+                for position in pos_cx {
                     let cx_inst = Crossover {
                         seqname: self.segments[i].seqname.clone(),
-                        position: pos_cx[posid],
+                        position,
                     };
                     vec_out.push(cx_inst);
                 }
@@ -165,60 +219,103 @@ impl RecombinationMap {
 }
 
 impl RecombinationMapGenome {
-    pub fn from_path(path: &str, extension: &str, recom_header: bool) -> Self {
-        let rm_filenames = list_files_in_directory(path, extension).unwrap();
+    pub fn from_path(path: &str, extension: &str, recom_header: bool) -> Result<Self> {
+        let rm_filenames = list_files_in_directory(path, extension)?;
         let recombination_maps = rm_filenames.iter()
-            .map(|rm_filename|{
-                let recomb_map_result = RecombinationMap::parse_to_recombination_map(rm_filename, recom_header);
-                let recomb_map = match recomb_map_result {
-                    Ok(rm) => {
-                        rm
-                    },
-                    Err(e) => {
-                        panic!("Error: {:?}", e);
-                    }
-                };
-                recomb_map
-            })
-            .collect();
-        Self {
-            recombination_maps,
+            .map(|rm_filename| RecombinationMap::parse_to_recombination_map(rm_filename, recom_header))
+            .collect::<Result<Vec<RecombinationMap>>>()?;
+        if recombination_maps.is_empty() {
+            return Err(MeiosimError::input(path, "no recombination maps found in the folder"));
         }
+        Ok(Self {
+            recombination_maps,
+        })
     }
-    pub fn generate_genome_cx(&self, parentid: String, rng_cx: &mut StdRng) -> HashMap<String, Vec<(String, Crossover)>> {
-        let mut map_out = HashMap::new();
+    pub fn flag_outside_contigs(&self, contig_size: &HashMap<String, u64>) {
+        // maps are not trimmed, we only report positions that would draw
+        // crossovers beyond the declared contig length
         for recombination_map in &self.recombination_maps {
-            let mut cx = recombination_map.generate_cx(rng_cx);
-            // these should not be needed actually
-            cx.sort();
-            let cxout: Vec<(String, Crossover)> = cx.iter().cloned().map(|c| (parentid.clone(), c.clone())).collect();
-            map_out.insert(recombination_map.seqname.clone(), cxout);
+            match contig_size.get(&recombination_map.seqname) {
+                Some(size) => {
+                    let outside = recombination_map.segments.iter()
+                        .filter(|segment| segment.position > *size)
+                        .count();
+                    if outside > 0 {
+                        warn!("Recombination map for {} has {} positions beyond the contig length ({})",
+                            recombination_map.seqname, outside, size);
+                    }
+                },
+                None => {
+                    warn!("Recombination map for {} has no declared contig length", recombination_map.seqname);
+                }
+            }
         }
-        map_out
     }
+    /// The maps of the contigs of `regions`, cut down to them.
+    pub fn restrict(&self, regions: &Regions) -> Self {
+        Self {
+            recombination_maps: self.recombination_maps.iter()
+                .filter(|recombination_map| !regions.contig(&recombination_map.seqname).is_empty())
+                .map(|recombination_map| recombination_map.restrict(regions.contig(&recombination_map.seqname)))
+                .collect(),
+        }
+    }
+    /// Crossovers of one meiosis on `chr`, `None` if there is no map for it.
+    pub fn generate_chromosome_cx(&self, chr: &str, parentid: &str, rng_cx: &mut StdRng) -> Option<Vec<(String, Crossover)>> {
+        self.recombination_maps.iter()
+            .find(|recombination_map| recombination_map.seqname == chr)
+            .map(|recombination_map| generate_map_cx(recombination_map, parentid, rng_cx))
+    }
+}
+
+fn generate_map_cx(recombination_map: &RecombinationMap, parentid: &str, rng_cx: &mut StdRng) -> Vec<(String, Crossover)> {
+    let mut cx = recombination_map.generate_cx(rng_cx);
+    // these should not be needed actually
+    cx.sort();
+    cx.into_iter().map(|c| (parentid.to_string(), c)).collect()
+}
+
+pub fn generate_simple_chromosome_cx(seqname: &str, size: u64, parentid: &str, rng_cx: &mut StdRng, cxcount: u8) -> Vec<(String, Crossover)> {
+    // size here means the size of the chromosome
+    // and seqname should be the names of the chromosome
+    // we can generate two recombination segments, the cM values do not
+    // matter as they won't be used.
+    // we cannot use 0 here because then i think it could generate a 0 (although unlikely)
+    let segment1 = RecombinationSegment::new(seqname.to_string(), 1, 0.0);
+    let segment2 = RecombinationSegment::new(seqname.to_string(), size, 0.0);
+    let positions = segment1.get_cx_position(&segment2, cxcount, rng_cx);
+
+    positions.iter().map(|pos| {
+        (parentid.to_string(), Crossover {
+            seqname: seqname.to_string(),
+            position: *pos,
+        })
+    }).collect()
 }
 
-pub fn generate_simple_recombination(csize: &HashMap<String, u64>, parentid: String, rng_cx: &mut StdRng, cxcount: u8) -> HashMap<String, Vec<(String, Crossover)>> {
-    let mut map_out = HashMap::new();
-    for (seqname, size) in csize.iter() {
-        // size here means the size of the chromosome
-        // and seqname should be the names of the chromosome
-        // we can generate two recombination segments, the cM values do not
-        // matter as they won't be used.
-        // we cannot use 0 here because then i think it could generate a 0 (although unlikely)
-        let segment1 = RecombinationSegment::new(seqname.clone(), 1, 0.0);
-        let segment2 = RecombinationSegment::new(seqname.clone(), *size, 0.0);
-        let positions = segment1.get_cx_position(&segment2, cxcount, rng_cx);
-        
-        let cxout: Vec<(String, Crossover)> = positions.iter().map(|pos| {
-            (parentid.clone(), Crossover {
-                seqname: seqname.clone(),
-                position: *pos,
-            })
-        }).collect();
-        map_out.insert(seqname.clone(), cxout);
+/// Like [`generate_simple_chromosome_cx`], with the crossovers placed
+/// uniformly over the `intervals` of the chromosome only.
+pub fn generate_simple_regions_cx(seqname: &str, intervals: &[(u64, u64)], parentid: &str, rng_cx: &mut StdRng, cxcount: u8) -> Vec<(String, Crossover)> {
+    let length: u64 = intervals.iter().map(|(start, end)| end - start).sum();
+    if length == 0 {
+        return Vec::new();
     }
-    map_out
+    let mut offsets: Vec<u64> = (0..cxcount).map(|_| rng_cx.gen_range(0..length)).collect();
+    offsets.sort();
+    offsets.into_iter().map(|mut offset| {
+        let mut position = 0;
+        for (start, end) in intervals {
+            if offset < end - start {
+                position = start + offset;
+                break;
+            }
+            offset -= end - start;
+        }
+        (parentid.to_string(), Crossover {
+            seqname: seqname.to_string(),
+            position,
+        })
+    }).collect()
 }
 
 #[cfg(test)]
@@ -269,6 +366,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_restricted_map() {
+        let seg1 = RecombinationSegment::new("chr1".to_string(), 0, 0.0);
+        let seg2 = RecombinationSegment::new("chr1".to_string(), 100000, 10.0);
+        let seg3 = RecombinationSegment::new("chr1".to_string(), 200000, 30.0);
+        let recom_map = super::RecombinationMap::new("chr1".to_string(), vec![seg1, seg2, seg3]);
+        let restricted = recom_map.restrict(&[(50000, 100000), (150000, 160000)]);
+        let points: Vec<(u64, f64)> = restricted.segments.iter().map(|s| (s.position, s.centimorgan)).collect();
+        // 5 cM in the first region and 2 cM in the second, none between
+        assert_eq!(points, vec![(50000, 0.0), (100000, 5.0), (150000, 5.0), (160000, 7.0)]);
+    }
+
     #[test]
     fn test_rng_0() {
         let mut rng: StdRng = StdRng::seed_from_u64(44);
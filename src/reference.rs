@@ -0,0 +1,270 @@
+use std::ffi::CString;
+use std::str::FromStr;
+
+use log::{info, warn};
+use rust_htslib::htslib;
+use serde::Serialize;
+
+use crate::error::{MeiosimError, Result};
+use crate::variants::ChildGenotype;
+
+// bases fetched at once by a cursor
+const WINDOW: u64 = 1 << 20;
+
+/// What is done with the variants whose REF allele is not the base of the
+/// reference genome, e.g. a panel or DNM catalogue of another build.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReferencePolicy {
+    /// mismatches are counted and transmitted anyway
+    #[default]
+    Report,
+    /// mismatches are not transmitted
+    Drop,
+    /// a mismatch stops the run
+    Fail,
+}
+
+impl FromStr for ReferencePolicy {
+    type Err = MeiosimError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "report" => Ok(ReferencePolicy::Report),
+            "drop" => Ok(ReferencePolicy::Drop),
+            "fail" => Ok(ReferencePolicy::Fail),
+            _ => Err(MeiosimError::Config(format!(
+                "Unknown reference mismatch policy '{}', use report, drop or fail", text))),
+        }
+    }
+}
+
+impl ReferencePolicy {
+    fn action(&self) -> &'static str {
+        match self {
+            ReferencePolicy::Report => "transmitted anyway",
+            ReferencePolicy::Drop => "dropped",
+            ReferencePolicy::Fail => "rejected",
+        }
+    }
+}
+
+/// A FASTA file read through its index (`.fai`, and `.gzi` if it is
+/// bgzipped), built by htslib when missing as with `samtools faidx`.
+pub struct ReferenceGenome {
+    path: String,
+    inner: *mut htslib::faidx_t,
+}
+
+impl ReferenceGenome {
+    pub fn from_path(path: &str) -> Result<Self> {
+        let cpath = CString::new(path).map_err(|_| MeiosimError::input(path, "invalid path"))?;
+        // SAFETY: htslib copies the path, a null index is checked below
+        let inner = unsafe { htslib::fai_load(cpath.as_ptr()) };
+        if inner.is_null() {
+            return Err(MeiosimError::input(path, "cannot open the FASTA file or build its index"));
+        }
+        Ok(Self { path: path.to_string(), inner })
+    }
+
+    pub fn has_contig(&self, chr: &str) -> bool {
+        let Ok(cname) = CString::new(chr) else {
+            return false;
+        };
+        // SAFETY: the index is valid until dropped
+        unsafe { htslib::faidx_has_seq(self.inner, cname.as_ptr()) == 1 }
+    }
+
+    /// Bases of `start..end` (0-based) of `chr`, in upper case, cut at the
+    /// end of the contig.
+    pub fn fetch(&self, chr: &str, start: u64, end: u64) -> Result<Vec<u8>> {
+        let missing = || MeiosimError::ContigNotFound {
+            contig: chr.to_string(),
+            source_name: self.path.clone(),
+        };
+        if !self.has_contig(chr) {
+            return Err(missing());
+        }
+        let cname = CString::new(chr).map_err(|_| missing())?;
+        // SAFETY: the index is valid until dropped
+        let contig_length = unsafe { htslib::faidx_seq_len(self.inner, cname.as_ptr()) };
+        if start >= contig_length.max(0) as u64 {
+            return Ok(Vec::new());
+        }
+        let mut length: htslib::hts_pos_t = 0;
+        // SAFETY: the index is valid until dropped, htslib allocates the
+        // returned sequence, which is copied and freed here
+        unsafe {
+            let sequence = htslib::faidx_fetch_seq64(self.inner,
+                cname.as_ptr(),
+                start as htslib::hts_pos_t,
+                end.saturating_sub(1) as htslib::hts_pos_t,
+                &mut length);
+            if sequence.is_null() {
+                return Err(MeiosimError::input(&self.path, &format!("cannot read {}:{}-{}", chr, start + 1, end)));
+            }
+            let bases = std::slice::from_raw_parts(sequence as *const u8, length.max(0) as usize).to_ascii_uppercase();
+            htslib::hts_free(sequence as *mut std::ffi::c_void);
+            Ok(bases)
+        }
+    }
+}
+
+// SAFETY: the index is only used by the thread that owns it, it is not
+// shared between threads
+unsafe impl Send for ReferenceGenome {}
+
+impl Drop for ReferenceGenome {
+    fn drop(&mut self) {
+        // SAFETY: the index was loaded by fai_load and is only freed here
+        unsafe { htslib::fai_destroy(self.inner) };
+    }
+}
+
+/// Check of the REF alleles against a reference genome.
+#[derive(Clone, Debug)]
+pub struct ReferenceCheck {
+    pub path: String,
+    pub policy: ReferencePolicy,
+}
+
+impl ReferenceCheck {
+    /// Opens the FASTA once, to fail early when it cannot be read or
+    /// lacks one of `chromosomes`.
+    pub fn new(path: &str, policy: ReferencePolicy, chromosomes: &[String]) -> Result<Self> {
+        let genome = ReferenceGenome::from_path(path)?;
+        if let Some(chr) = chromosomes.iter().find(|chr| !genome.has_contig(chr)) {
+            return Err(MeiosimError::ContigNotFound {
+                contig: chr.clone(),
+                source_name: path.to_string(),
+            });
+        }
+        Ok(Self { path: path.to_string(), policy })
+    }
+
+    /// Cursor over `chr` for the sorted records of `source`.
+    pub fn cursor(&self, chr: &str, source: &str) -> Result<ReferenceCursor> {
+        Ok(ReferenceCursor {
+            genome: ReferenceGenome::from_path(&self.path)?,
+            policy: self.policy,
+            chr: chr.to_string(),
+            source: source.to_string(),
+            window_start: 0,
+            bases: Vec::new(),
+            checked: 0,
+            mismatches: 0,
+        })
+    }
+
+    /// Checks the DNMs read from `source`, dropping the mismatches as set
+    /// by the policy, and logs their number.
+    pub fn check_dnms(&self, dnms: &mut Vec<ChildGenotype>, source: &str, verbose: bool) -> Result<()> {
+        let genome = ReferenceGenome::from_path(&self.path)?;
+        let mut mismatches = 0;
+        let checked = dnms.len();
+        let mut kept = Vec::with_capacity(dnms.len());
+        for dnm in dnms.drain(..) {
+            let base = genome.fetch(&dnm.seqname, dnm.pos as u64, dnm.pos as u64 + 1)?;
+            if matches(&dnm.alleles[0], &base) {
+                kept.push(dnm);
+                continue;
+            }
+            mismatches += 1;
+            let base = String::from_utf8_lossy(&base).to_string();
+            if self.policy == ReferencePolicy::Fail {
+                return Err(mismatch_error(source, &dnm.seqname, dnm.pos, &dnm.alleles[0], &base));
+            }
+            if verbose {
+                warn!("REF {} of the DNM at {}:{} of {} is not the reference base {}", dnm.alleles[0], dnm.seqname, dnm.pos + 1, source, base);
+            }
+            if self.policy == ReferencePolicy::Report {
+                kept.push(dnm);
+            }
+        }
+        *dnms = kept;
+        if mismatches > 0 {
+            warn!("{} of {} DNMs of {} do not match the reference, {}", mismatches, checked, source, self.policy.action());
+        }
+        Ok(())
+    }
+}
+
+/// Checks the REF alleles of the sorted records of one contig, fetching
+/// the reference by windows.
+pub struct ReferenceCursor {
+    genome: ReferenceGenome,
+    policy: ReferencePolicy,
+    chr: String,
+    source: String,
+    window_start: u64,
+    bases: Vec<u8>,
+    checked: u64,
+    mismatches: u64,
+}
+
+impl ReferenceCursor {
+    /// Whether the SNV at the 0-based `pos` with REF `reference` is
+    /// transmitted, an error under [`ReferencePolicy::Fail`] if REF is not
+    /// the reference base.
+    pub fn check(&mut self, pos: u64, reference: &str, verbose: bool) -> Result<bool> {
+        if pos < self.window_start || pos >= self.window_start + self.bases.len() as u64 {
+            self.window_start = pos;
+            self.bases = self.genome.fetch(&self.chr, pos, pos + WINDOW)?;
+        }
+        self.checked += 1;
+        let base = self.bases.get((pos - self.window_start) as usize).map(std::slice::from_ref).unwrap_or_default();
+        if matches(reference, base) {
+            return Ok(true);
+        }
+        self.mismatches += 1;
+        let base = String::from_utf8_lossy(base).to_string();
+        if self.policy == ReferencePolicy::Fail {
+            return Err(mismatch_error(&self.source, &self.chr, pos as i64, reference, &base));
+        }
+        if verbose {
+            warn!("REF {} at {}:{} is not the reference base {}", reference, self.chr, pos + 1, base);
+        }
+        Ok(self.policy == ReferencePolicy::Report)
+    }
+
+    /// Logs the number of SNVs checked and of mismatches.
+    pub fn report(&mut self) {
+        if self.mismatches > 0 {
+            warn!("{} of {} SNVs of {} do not match the reference, {}", self.mismatches, self.checked, self.chr, self.policy.action());
+        } else if self.checked > 0 {
+            info!("The {} SNVs of {} match the reference", self.checked, self.chr);
+        }
+        self.checked = 0;
+        self.mismatches = 0;
+    }
+}
+
+fn matches(reference: &str, base: &[u8]) -> bool {
+    reference.as_bytes().eq_ignore_ascii_case(base)
+}
+
+fn mismatch_error(source: &str, chr: &str, pos: i64, reference: &str, base: &str) -> MeiosimError {
+    MeiosimError::ReferenceMismatch {
+        path: source.to_string(),
+        contig: chr.to_string(),
+        pos: pos + 1,
+        reference: reference.to_string(),
+        base: if base.is_empty() { "(beyond the contig)".to_string() } else { base.to_string() },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matches, ReferencePolicy};
+
+    #[test]
+    fn test_reference_match() {
+        assert!(matches("A", b"A"));
+        // soft-masked bases are upper-cased when fetched, REF may not be
+        assert!(matches("a", b"A"));
+        assert!(!matches("A", b"C"));
+        assert!(!matches("A", b""));
+        assert_eq!("drop".parse::<ReferencePolicy>().unwrap(), ReferencePolicy::Drop);
+        assert!("fatal".parse::<ReferencePolicy>().is_err());
+    }
+}
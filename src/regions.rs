@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use crate::error::{MeiosimError, Result};
+
+/// Genomic regions, as sorted and non-overlapping 0-based half-open
+/// intervals per contig.
+#[derive(Clone, Debug, Default)]
+pub struct Regions {
+    intervals: BTreeMap<String, Vec<(u64, u64)>>,
+}
+
+impl Regions {
+    /// Reads the regions of a BED file (`chr start end`, 0-based), header
+    /// and comment lines being skipped. Overlapping regions are merged.
+    pub fn from_bed(path: &str) -> Result<Self> {
+        let file = File::open(path).map_err(|e| MeiosimError::io(path, e))?;
+        let mut intervals = Vec::new();
+        for (line_idx, line) in io::BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| MeiosimError::io(path, e))?;
+            let line_number = line_idx + 1;
+            if line.trim().is_empty() || line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 3 {
+                return Err(MeiosimError::parse(path, line_number,
+                    &format!("expected at least 3 columns (chr, start, end), found {}", fields.len())));
+            }
+            let position = |field: &str| field.parse::<u64>().map_err(|_| {
+                MeiosimError::parse(path, line_number, &format!("position '{}' is not a positive integer", field))
+            });
+            let (start, end) = (position(fields[1])?, position(fields[2])?);
+            if end <= start {
+                return Err(MeiosimError::parse(path, line_number,
+                    &format!("region {}:{}-{} is empty", fields[0], start, end)));
+            }
+            intervals.push((fields[0].to_string(), start, end));
+        }
+        Ok(Self::from_intervals(intervals))
+    }
+
+    /// Regions given on the command line, either a BED file or a
+    /// comma-separated list of `chr:start-end` regions, with 1-based
+    /// inclusive coordinates as in `samtools`/`bcftools`.
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        if Path::new(spec).is_file() {
+            return Self::from_bed(spec);
+        }
+        let mut intervals = Vec::new();
+        for region in spec.split(',') {
+            let invalid = || MeiosimError::Config(format!(
+                "Invalid region '{}', use chr:start-end (1-based) or a BED file", region));
+            let (chr, range) = region.rsplit_once(':').ok_or_else(invalid)?;
+            let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+            let position = |field: &str| field.replace('_', "").parse::<u64>().map_err(|_| invalid());
+            let (start, end) = (position(start)?, position(end)?);
+            if chr.is_empty() || start == 0 || end < start {
+                return Err(invalid());
+            }
+            intervals.push((chr.to_string(), start - 1, end));
+        }
+        Ok(Self::from_intervals(intervals))
+    }
+
+    /// Regions of `(chr, start, end)` intervals, in any order.
+    pub fn from_intervals(intervals: impl IntoIterator<Item = (String, u64, u64)>) -> Self {
+        let mut by_contig: BTreeMap<String, Vec<(u64, u64)>> = BTreeMap::new();
+        for (chr, start, end) in intervals {
+            by_contig.entry(chr).or_default().push((start, end));
+        }
+        for contig_intervals in by_contig.values_mut() {
+            contig_intervals.sort();
+            let mut merged: Vec<(u64, u64)> = Vec::with_capacity(contig_intervals.len());
+            for (start, end) in contig_intervals.drain(..) {
+                match merged.last_mut() {
+                    Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                    _ => merged.push((start, end)),
+                }
+            }
+            *contig_intervals = merged;
+        }
+        Self { intervals: by_contig }
+    }
+
+    /// Whether the 0-based position `pos` of `chr` is in a region.
+    pub fn contains(&self, chr: &str, pos: u64) -> bool {
+        let intervals = self.contig(chr);
+        let idx = intervals.partition_point(|(_, end)| *end <= pos);
+        intervals.get(idx).is_some_and(|(start, _)| *start <= pos)
+    }
+
+    /// Regions of `chr`, sorted.
+    pub fn contig(&self, chr: &str) -> &[(u64, u64)] {
+        self.intervals.get(chr).map(|intervals| intervals.as_slice()).unwrap_or_default()
+    }
+
+    pub fn contigs(&self) -> impl Iterator<Item = &String> {
+        self.intervals.keys()
+    }
+
+    /// Parts of `start..end` of `chr` that are in a region.
+    pub fn clip(&self, chr: &str, start: u64, end: u64) -> Vec<(u64, u64)> {
+        self.contig(chr).iter()
+            .filter(|(region_start, region_end)| *region_start < end && start < *region_end)
+            .map(|(region_start, region_end)| (start.max(*region_start), end.min(*region_end)))
+            .collect()
+    }
+
+    /// The regions cut at the end of their contig, those beyond it being
+    /// dropped.
+    pub fn within(&self, contig_size: &HashMap<String, u64>) -> Self {
+        let intervals = self.intervals.iter()
+            .map(|(chr, intervals)| {
+                let size = contig_size.get(chr).copied().unwrap_or(u64::MAX);
+                let intervals: Vec<(u64, u64)> = intervals.iter()
+                    .filter(|(start, _)| *start < size)
+                    .map(|(start, end)| (*start, (*end).min(size)))
+                    .collect();
+                (chr.clone(), intervals)
+            })
+            .filter(|(_, intervals)| !intervals.is_empty())
+            .collect();
+        Self { intervals }
+    }
+
+    /// Total length of the regions of `chr`.
+    pub fn length(&self, chr: &str) -> u64 {
+        self.contig(chr).iter().map(|(start, end)| end - start).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+}
+
+/// Parts of `start..end` of `chr` in `regions`, the whole stretch when
+/// the simulation is not restricted to regions.
+pub fn clip_to_regions(regions: Option<&Regions>, chr: &str, start: u64, end: u64) -> Vec<(u64, u64)> {
+    match regions {
+        Some(regions) => regions.clip(chr, start, end),
+        None => vec![(start, end)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Regions;
+
+    #[test]
+    fn test_regions_are_merged() {
+        let regions = Regions::from_intervals(vec![
+            ("chr1".to_string(), 50, 60),
+            ("chr1".to_string(), 10, 20),
+            ("chr1".to_string(), 15, 30),
+            ("chr2".to_string(), 0, 5),
+        ]);
+        assert_eq!(regions.contig("chr1"), &[(10, 30), (50, 60)]);
+        assert!(regions.contains("chr1", 10));
+        assert!(regions.contains("chr1", 29));
+        assert!(!regions.contains("chr1", 30));
+        assert!(!regions.contains("chr1", 60));
+        assert!(regions.contains("chr2", 4));
+        assert!(!regions.contains("chr3", 4));
+        assert_eq!(regions.clip("chr1", 0, 55), vec![(10, 30), (50, 55)]);
+        assert_eq!(regions.length("chr1"), 30);
+    }
+
+    #[test]
+    fn test_regions_from_spec() {
+        let regions = Regions::from_spec("chr21:1-10_000_000,chr22:101-200").unwrap();
+        assert_eq!(regions.contig("chr21"), &[(0, 10_000_000)]);
+        assert_eq!(regions.contig("chr22"), &[(100, 200)]);
+        assert!(Regions::from_spec("chr21:0-100").is_err());
+        assert!(Regions::from_spec("chr21").is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+// labels of the random streams, combined with the sibling, parent and
+// chromosome they are used for
+pub const DNM_CHOICE: &str = "dnm-choice";
+pub const DNM_STRAND: &str = "dnm-strand";
+pub const DNM_COLLISION: &str = "dnm-collision";
+pub const CROSSOVERS: &str = "crossovers";
+pub const INITIAL_HAPLOTYPE: &str = "initial-haplotype";
+pub const PAIRING: &str = "pairing";
+pub const PARENT_CHOICE: &str = "parent-choice";
+pub const ADMIXTURE: &str = "admixture";
+pub const FOUNDERS: &str = "founders";
+pub const MATING: &str = "mating";
+pub const PEDIGREE: &str = "pedigree";
+pub const POSTZYGOTIC: &str = "postzygotic";
+pub const BIVALENTS: &str = "bivalents";
+pub const COVERAGE: &str = "coverage";
+pub const EMBRYO: &str = "embryo";
+pub const PHASING: &str = "phasing";
+pub const MISSING: &str = "missing";
+
+/// Derives independent random streams from the run seed.
+///
+/// Each stream is seeded from a hash of the run seed and a path of labels,
+/// for example `["sib0", "parent1", "chr21", "crossovers"]`. A stream does
+/// not depend on which other streams are drawn, or in which order, so the
+/// same seed gives the same child for a chromosome whatever the number of
+/// siblings or chromosomes simulated.
+#[derive(Clone, Copy, Debug)]
+pub struct SeedTree {
+    seed: u64,
+}
+
+impl SeedTree {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Seed of the stream identified by `path`.
+    pub fn derive(&self, path: &[&str]) -> u64 {
+        // FNV-1a, as the std hashers are not guaranteed to be stable
+        // across Rust versions
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut feed = |byte: u8| {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        };
+        self.seed.to_le_bytes().iter().for_each(|byte| feed(*byte));
+        for label in path {
+            // 0xff never appears in UTF-8, so paths cannot collide
+            feed(0xff);
+            label.bytes().for_each(&mut feed);
+        }
+        splitmix64(hash)
+    }
+
+    /// Tree of a part of the simulation, e.g. one family of a batch, with
+    /// streams independent from the ones of `self`.
+    pub fn child(&self, label: &str) -> SeedTree {
+        SeedTree::new(self.derive(&[label]))
+    }
+
+    pub fn stream(&self, path: &[&str]) -> StdRng {
+        StdRng::seed_from_u64(self.derive(path))
+    }
+}
+
+// final mix of splitmix64, spreads the FNV hash over all the bits
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SeedTree;
+
+    #[test]
+    fn test_derive_is_stable() {
+        // changing these values changes the output of every seed
+        let seeds = SeedTree::new(3);
+        assert_eq!(seeds.derive(&["sib0", "parent1", "chr21", "crossovers"]), 9845874312510161479);
+    }
+
+    #[test]
+    fn test_derive_depends_on_path() {
+        let seeds = SeedTree::new(3);
+        let a = seeds.derive(&["sib0", "parent1", "chr21", "crossovers"]);
+        assert_ne!(a, seeds.derive(&["sib1", "parent1", "chr21", "crossovers"]));
+        assert_ne!(a, seeds.derive(&["sib0", "parent2", "chr21", "crossovers"]));
+        assert_ne!(a, seeds.derive(&["sib0", "parent1", "chr22", "crossovers"]));
+        assert_ne!(a, SeedTree::new(4).derive(&["sib0", "parent1", "chr21", "crossovers"]));
+        // labels are delimited
+        assert_ne!(seeds.derive(&["ab", "c"]), seeds.derive(&["a", "bc"]));
+    }
+}
@@ -9,10 +9,14 @@ use std::collections::HashMap;
 use std::path::Path;
 use std::io::Write;
 
-pub fn list_files_in_directory(input_folder: &str, extension: &str) -> io::Result<Vec<String>> {
+use crate::error::{MeiosimError, Result};
+
+pub fn list_files_in_directory(input_folder: &str, extension: &str) -> Result<Vec<String>> {
     let mut file_names = Vec::new();
-    for entry in fs::read_dir(input_folder)? {
-        let entry = entry?;
+    let entries = fs::read_dir(input_folder)
+        .map_err(|e| MeiosimError::io(input_folder, e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| MeiosimError::io(input_folder, e))?;
         // so this is only the filename, could we improve it until
         // it is the full path.
         let path = entry.path();
@@ -23,6 +27,8 @@ pub fn list_files_in_directory(input_folder: &str, extension: &str) -> io::Resul
             }
         }
     }
+    // read_dir order depends on the filesystem
+    file_names.sort();
     Ok(file_names)
 }
 
@@ -32,31 +38,24 @@ pub fn ensure_directory_exists(path: &str, verbose: bool) -> io::Result<()> {
         if verbose {
             info!("Directory {} created", path);
         }
-    } else {
-        if verbose {
-            warn!("Directory {} already exists", path);
-        }
+    } else if verbose {
+        warn!("Directory {} already exists", path);
     }
     Ok(())
 }
 
-pub fn capture_chromosome_from_file_name(text: &str, verbose: bool) -> String {
+pub fn capture_chromosome_from_file_name(text: &str, verbose: bool) -> Result<String> {
     let re = Regex::new(r"chr\d+|chrX|chrY").unwrap();
-    let caps = re.captures(text).unwrap();
+    let caps = re.captures(text)
+        .ok_or_else(|| MeiosimError::input(text, "No chromosome found in file name"))?;
     if verbose {
         info!("Captures: {:?}", caps);
     }
-    if caps.len() == 0 {
-        panic!("No chromosome found in file name");
-    } else if caps.len() > 1 {
-        panic!("More than one chromosome found in file name");
-    } else {
-        let chromosome = caps[0].to_string();
-        if verbose {
-            info!("Chromosome found: {}", chromosome);
-        }
-        chromosome
-    }  
+    let chromosome = caps[0].to_string();
+    if verbose {
+        info!("Chromosome found: {}", chromosome);
+    }
+    Ok(chromosome)
 }
 
 pub fn from_vu8_to_string(x: Vec<&[u8]>)-> Vec<String> {
@@ -66,23 +65,79 @@ pub fn from_vu8_to_string(x: Vec<&[u8]>)-> Vec<String> {
 }
 
 
-pub fn read_genome_file(file_path: &String) -> HashMap<String, u64> {
+pub fn read_genome_file(file_path: &str) -> Result<HashMap<String, u64>> {
+    // works both for a two-column genome file (contig, size) and for a
+    // samtools faidx index (.fai), as the first two columns are the same.
     let mut contig_size = HashMap::new();
-    let input_file = File::open(&Path::new(file_path)).unwrap();
+    let input_file = File::open(Path::new(file_path))
+        .map_err(|e| MeiosimError::io(file_path, e))?;
     let reader = io::BufReader::new(input_file);
     let lines = reader.lines();
-    for line in lines {
-        let line = line.unwrap();
+    for (line_idx, line) in lines.enumerate() {
+        let line = line.map_err(|e| MeiosimError::io(file_path, e))?;
+        let line_number = line_idx + 1;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
         let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 2 {
+            return Err(MeiosimError::parse(file_path, line_number,
+                &format!("expected at least 2 columns (contig, size), found {}", fields.len())));
+        }
         let contig = fields[0].to_string();
-        let size = fields[1].parse::<u64>().unwrap();
-        contig_size.insert(contig, size);
+        let size = fields[1].parse::<u64>().map_err(|_| {
+            MeiosimError::parse(file_path, line_number,
+                &format!("contig size '{}' is not a positive integer", fields[1]))
+        })?;
+        if size == 0 {
+            return Err(MeiosimError::parse(file_path, line_number,
+                &format!("contig {} has size 0", contig)));
+        }
+        if let Some(previous) = contig_size.insert(contig.clone(), size) {
+            if previous != size {
+                return Err(MeiosimError::parse(file_path, line_number,
+                    &format!("contig {} declared twice with different sizes ({} and {})", contig, previous, size)));
+            }
+        }
     }
-    contig_size
+    if contig_size.is_empty() {
+        return Err(MeiosimError::input(file_path, "the genome file does not contain any contig"));
+    }
+    Ok(contig_size)
+}
+
+pub fn push_haps_to_bed(hap1: usize, hap2: usize, chr: &str, pos_from: u64, pos_to: u64, outputfile_bed: &mut OutputFile) -> Result<()> {
+    writeln!(outputfile_bed, "{}\t{}\t{}\t{}\t{}", chr, pos_from, pos_to, hap1, hap2)
+        .map_err(|e| MeiosimError::io(&outputfile_bed.path, e))
 }
 
-pub fn push_haps_to_bed(hap1: &usize, hap2: &usize, chr: &String, pos_from: &u64, pos_to: &u64, outputfile_bed: &mut File) -> () {
-    let lineout = format!("{}\t{}\t{}\t{}\t{}\n", chr, pos_from, pos_to, hap1, hap2);
-    write!(outputfile_bed, "{}", lineout).expect("Unable to write to file");
+/// An output file that keeps its path, so that write errors can be
+/// reported with it.
+pub struct OutputFile {
+    pub path: String,
+    file: File,
+}
+
+impl OutputFile {
+    pub fn create(path: &str) -> Result<Self> {
+        let file = File::create(path).map_err(|e| MeiosimError::io(path, e))?;
+        Ok(Self {
+            path: path.to_string(),
+            file,
+        })
+    }
+
+    pub fn write_str(&mut self, text: &str) -> Result<()> {
+        self.file.write_all(text.as_bytes()).map_err(|e| MeiosimError::io(&self.path, e))
+    }
 }
 
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
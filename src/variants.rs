@@ -3,109 +3,764 @@
 use crate::utils::list_files_in_directory;
 use crate::utils::capture_chromosome_from_file_name;
 use crate::utils::from_vu8_to_string;
-
-use core::panic;
-use std::fs::File;
-use std::io::Write;
+use crate::utils::OutputFile;
+use crate::meiosis::{HaplotypeSegment, Parents};
+use crate::founders::FounderTract;
+use crate::error::{MeiosimError, Result};
+use crate::filters::VariantFilter;
+use crate::reference::{ReferenceCheck, ReferenceCursor};
+use crate::regions::Regions;
+use crate::seeds::{self, SeedTree};
 
 use std::collections::HashMap;
+use std::ffi::CString;
+use std::str::FromStr;
+use std::sync::Arc;
 use rust_htslib::bcf;
+use rust_htslib::htslib;
 use bcf::Read;
-use bcf::record::GenotypeAllele;
+use bcf::record::{GenotypeAllele, Numeric};
+use serde::Serialize;
 
 use log::warn;
 use log::info;
 
 use rand::Rng;
 use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+/// How the heterozygous genotypes of the panel that are not phased are
+/// handled, e.g. for a clinical cohort genotyped without phasing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PhasingPolicy {
+    /// unphased heterozygous genotypes are an error
+    #[default]
+    Refuse,
+    /// unphased heterozygous genotypes are phased at random
+    Random,
+    /// phased genotypes are phased within the blocks of their PS tag, as
+    /// written by read-backed phasing tools (WhatsHap, HapCUT2), and the
+    /// blocks are oriented at random. Unphased heterozygous genotypes are
+    /// phased at random
+    PhaseSet,
+}
+
+impl FromStr for PhasingPolicy {
+    type Err = MeiosimError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "refuse" => Ok(PhasingPolicy::Refuse),
+            "random" => Ok(PhasingPolicy::Random),
+            "phase-set" => Ok(PhasingPolicy::PhaseSet),
+            _ => Err(MeiosimError::Config(format!(
+                "Unknown phasing policy '{}', use refuse, random or phase-set", text))),
+        }
+    }
+}
+
+/// How the parental genotypes with a missing allele are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MissingPolicy {
+    /// children inheriting the missing allele get no genotype at the site
+    #[default]
+    Skip,
+    /// the missing allele is the reference allele
+    Ref,
+    /// the missing allele is drawn from the INFO/AF allele frequency of
+    /// the site
+    Impute,
+    /// a missing allele is an error
+    Fail,
+}
+
+impl FromStr for MissingPolicy {
+    type Err = MeiosimError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "skip" => Ok(MissingPolicy::Skip),
+            "ref" => Ok(MissingPolicy::Ref),
+            "impute" => Ok(MissingPolicy::Impute),
+            "fail" => Ok(MissingPolicy::Fail),
+            _ => Err(MeiosimError::Config(format!(
+                "Unknown missing genotype policy '{}', use skip, ref, impute or fail", text))),
+        }
+    }
+}
+
+/// How the unphased and missing genotypes of a panel are handled, with
+/// the seeds of the random phases and imputed alleles.
+///
+/// The draws for a sample come from streams named after the sample and the
+/// chromosome, so that every pass over a VCF resolves the genotypes of a
+/// sample the same way, whatever the other samples read along with it.
+#[derive(Clone, Copy, Debug)]
+pub struct GenotypePolicy {
+    pub phasing: PhasingPolicy,
+    pub missing: MissingPolicy,
+    pub seeds: SeedTree,
+}
+
+impl GenotypePolicy {
+    pub fn new(phasing: PhasingPolicy, missing: MissingPolicy, seeds: SeedTree) -> Self {
+        Self { phasing, missing, seeds }
+    }
+}
+
+impl Default for GenotypePolicy {
+    fn default() -> Self {
+        Self::new(PhasingPolicy::Refuse, MissingPolicy::Skip, SeedTree::new(0))
+    }
+}
+
+// phases the genotypes of one sample along a chromosome and fills in its
+// missing alleles
+struct GenotypeResolver {
+    sample: String,
+    policy: GenotypePolicy,
+    phasing_rng: StdRng,
+    missing_rng: StdRng,
+    // order of the haplotypes of each phase set, by PS value
+    blocks: HashMap<i32, Vec<usize>>,
+    // unphased heterozygous genotypes phased at random
+    randomly_phased: u64,
+    // sites with a missing allele
+    missing_sites: u64,
+}
+
+impl GenotypeResolver {
+    fn new(sample: &str, seqname: &str, policy: &GenotypePolicy) -> Self {
+        Self {
+            sample: sample.to_string(),
+            policy: *policy,
+            phasing_rng: policy.seeds.stream(&[sample, seqname, seeds::PHASING]),
+            missing_rng: policy.seeds.stream(&[sample, seqname, seeds::MISSING]),
+            blocks: HashMap::new(),
+            randomly_phased: 0,
+            missing_sites: 0,
+        }
+    }
+
+    /// Alleles of the genotype in haplotype order, `None` for a missing
+    /// allele that is skipped. `phase_set` is the PS value of the genotype
+    /// and `allele_frequency` the INFO/AF of the site, if any.
+    fn resolve(&mut self,
+            genotype: &[GenotypeAllele],
+            phase_set: Option<i32>,
+            allele_frequency: Option<f64>) -> std::result::Result<Vec<Option<i32>>, String> {
+        let mut alleles = self.phase(genotype, phase_set)?;
+        if alleles.iter().all(|allele| allele.is_some()) {
+            return Ok(alleles);
+        }
+        self.missing_sites += 1;
+        match self.policy.missing {
+            MissingPolicy::Skip => {},
+            MissingPolicy::Ref => alleles.iter_mut().for_each(|allele| *allele = allele.or(Some(0))),
+            MissingPolicy::Impute => {
+                let allele_frequency = allele_frequency.ok_or_else(|| format!(
+                    "no INFO/AF to impute the missing genotype of {}", self.sample))?;
+                for allele in alleles.iter_mut().filter(|allele| allele.is_none()) {
+                    *allele = Some(self.missing_rng.gen_bool(allele_frequency) as i32);
+                }
+            },
+            MissingPolicy::Fail => return Err(format!(
+                "the genotype of {} is missing, use --missing skip, ref or impute", self.sample)),
+        }
+        Ok(alleles)
+    }
+
+    fn phase(&mut self, genotype: &[GenotypeAllele], phase_set: Option<i32>) -> std::result::Result<Vec<Option<i32>>, String> {
+        let alleles: Vec<Option<i32>> = genotype.iter().map(|allele| extract_value(allele).copied()).collect();
+        // the phase of a genotype is carried by its alleles after the first
+        let phased = genotype.iter()
+            .skip(1)
+            .all(|allele| matches!(allele, GenotypeAllele::Phased(_) | GenotypeAllele::PhasedMissing));
+        let called: Vec<i32> = alleles.iter().flatten().copied().collect();
+        let heterozygous = called.iter().any(|allele| *allele != called[0]);
+        if !heterozygous {
+            return Ok(alleles);
+        }
+        if phased {
+            return match (self.policy.phasing, phase_set) {
+                (PhasingPolicy::PhaseSet, Some(phase_set)) => {
+                    let order = self.block_order(phase_set, alleles.len());
+                    Ok(order.iter().map(|hap| alleles[*hap]).collect())
+                },
+                _ => Ok(alleles),
+            };
+        }
+        match self.policy.phasing {
+            PhasingPolicy::Refuse => Err(format!(
+                "the genotype of {} is not phased, use --unphased random or phase-set", self.sample)),
+            PhasingPolicy::Random | PhasingPolicy::PhaseSet => {
+                let mut alleles = alleles;
+                alleles.shuffle(&mut self.phasing_rng);
+                self.randomly_phased += 1;
+                Ok(alleles)
+            },
+        }
+    }
+
+    // order of the haplotypes of the phase set, drawn at its first site
+    fn block_order(&mut self, phase_set: i32, ploidy: usize) -> Vec<usize> {
+        match self.blocks.get(&phase_set) {
+            Some(order) if order.len() == ploidy => order.clone(),
+            _ => {
+                let mut order: Vec<usize> = (0..ploidy).collect();
+                order.shuffle(&mut self.phasing_rng);
+                self.blocks.insert(phase_set, order.clone());
+                order
+            },
+        }
+    }
+
+    fn report(&mut self, seqname: &str) {
+        if self.randomly_phased > 0 {
+            warn!("{} unphased heterozygous genotypes of {} on {} were phased at random",
+                self.randomly_phased, self.sample, seqname);
+            self.randomly_phased = 0;
+        }
+        if self.missing_sites > 0 {
+            let action = match self.policy.missing {
+                MissingPolicy::Skip => "not transmitted",
+                MissingPolicy::Ref => "set to the reference allele",
+                MissingPolicy::Impute => "imputed from the allele frequency",
+                MissingPolicy::Fail => "rejected",
+            };
+            warn!("{} sites with a missing allele of {} on {}, the missing alleles were {}",
+                self.missing_sites, self.sample, seqname, action);
+            self.missing_sites = 0;
+        }
+    }
+}
 
+// INFO/AF of the first alternative allele, or else AC/AN
+fn allele_frequency(record: &bcf::Record) -> Option<f64> {
+    if let Ok(Some(values)) = record.info(b"AF").float() {
+        if let Some(value) = values.first().filter(|value| !value.is_missing()) {
+            return Some((*value as f64).clamp(0.0, 1.0));
+        }
+    }
+    let count = |tag: &[u8]| match record.info(tag).integer() {
+        Ok(Some(values)) => values.first().copied().filter(|value| !value.is_missing()),
+        _ => None,
+    };
+    match (count(b"AC"), count(b"AN")) {
+        (Some(ac), Some(an)) if an > 0 => Some((ac as f64 / an as f64).clamp(0.0, 1.0)),
+        _ => None,
+    }
+}
+
+// PS value of each sample of the record, when the tag is defined
+fn phase_sets(record: &bcf::Record, samples: usize) -> Vec<Option<i32>> {
+    match record.format(b"PS").integer() {
+        Ok(values) => values.iter()
+            .map(|value| value.first().copied().filter(|value| !value.is_missing()))
+            .collect(),
+        Err(_) => vec![None; samples],
+    }
+}
 
 pub struct VCF {
     pub file_path: String,
     pub seqname: String,
     pub samples: Vec<String>,
+    // length of seqname as declared in the ##contig header line, if any
+    pub contig_length: Option<u64>,
+    pub genotype_policy: GenotypePolicy,
+    /// records transmitted to the children
+    pub filter: Arc<VariantFilter>,
+    /// regions the simulation is restricted to, only their records are
+    /// read, through the index
+    pub regions: Option<Arc<Regions>>,
+    /// check of the REF alleles of the transmitted records
+    pub reference: Option<Arc<ReferenceCheck>>,
 }
 
 impl VCF {
-    pub fn new(file_path: String, seqname: String) -> VCF {
-        let file_path2 = file_path.clone();
-        let bcf = bcf::Reader::from_path(file_path).expect("Error opening VCF file");
+    pub fn new(file_path: String, seqname: String) -> Result<VCF> {
+        let bcf = bcf::Reader::from_path(&file_path)
+            .map_err(|e| MeiosimError::htslib(&file_path, e))?;
         let header = bcf.header();
         let sample_names_vu8: Vec<& [u8]> = header.samples();
         let sample_names = from_vu8_to_string(sample_names_vu8);
+        let contig_length = contig_length_from_header(header, &seqname);
 
-        VCF {
-            file_path: file_path2,
+        Ok(VCF {
+            file_path,
             seqname,
             samples: sample_names,
+            contig_length,
+            genotype_policy: GenotypePolicy::default(),
+            filter: Arc::new(VariantFilter::default()),
+            regions: None,
+            reference: None,
+        })
+    }
+
+    pub fn count_records_from(&self, chromosome: &str, pos_from: u64) -> Result<u64> {
+        // used to flag records that lie outside the declared contig length
+        let mut bcf = bcf::IndexedReader::from_path(&self.file_path)
+            .map_err(|e| MeiosimError::htslib(&self.file_path, e))?;
+        let chr_id = match bcf.header().name2rid(chromosome.as_bytes()) {
+            Ok(chr_id) => chr_id,
+            Err(_) => return Ok(0),
+        };
+        if bcf.fetch(chr_id, pos_from, None).is_err() {
+            return Ok(0);
         }
+        let mut current_record = bcf.empty_record();
+        let mut count = 0;
+        while let Some(result) = bcf.read(&mut current_record) {
+            result.map_err(|e| MeiosimError::htslib(&self.file_path, e))?;
+            count += 1;
+        }
+        Ok(count)
     }
 
-    pub fn get_records_two_parents_from_to(&self,
-            parent1: &String, 
-            parent2: &String, 
-            hap1: usize, 
-            hap2: usize, 
-            chromosome: String, 
-            pos_from: u64, 
-            pos_to: u64, 
-            verbose: bool,
-            output_writter: &mut File) -> (){
-        // s.as_bytes()
-        let fpath = self.file_path.clone();
-        let mut bcf = bcf::IndexedReader::from_path(fpath)
-            .expect("Cannot open the file");
-        let header = bcf.header();
-        let parent1_idx = header.sample_id(parent1.as_bytes()).expect("Parent1 not found");
-        let parent2_idx = header.sample_id(parent2.as_bytes()).expect("Parent2 not found");
+    /// Streams the genotypes of a child over this VCF, following the
+    /// haplotype segments it inherited from `parents`.
+    pub fn stream_child_genotypes(&self,
+            parents: &Parents,
+            segments: &[HaplotypeSegment],
+            verbose: bool) -> Result<impl Iterator<Item = Result<ChildGenotype>>> {
+        let stream = self.stream_family_genotypes(parents, vec![segments], verbose)?;
+        Ok(stream.filter_map(|family_genotypes| {
+            match family_genotypes {
+                Ok(family_genotypes) => family_genotypes.child(0).map(Ok),
+                Err(e) => Some(Err(e)),
+            }
+        }))
+    }
 
-        let chr_id = header.name2rid(chromosome.as_bytes()).expect("Chromosome not found");
-        bcf.fetch(chr_id, pos_from, Some(pos_to)).expect("Cannot fetch the region");
+    /// Streams the genotypes of all the children of `parents` in a single
+    /// pass over this VCF. `children` holds the haplotype segments of each
+    /// child for this chromosome. Only the genotypes of the two parents are
+    /// decoded.
+    pub fn stream_family_genotypes(&self,
+            parents: &Parents,
+            children: Vec<&[HaplotypeSegment]>,
+            verbose: bool) -> Result<FamilyGenotypeStream> {
+        let children = children.into_iter().map(|segments| (parents, segments)).collect();
+        self.stream_cohort_genotypes(children, verbose)
+    }
 
-        let mut current_record = bcf.empty_record();
+    /// Like [`VCF::stream_family_genotypes`], for children of different
+    /// parents, so that many families are simulated in a single pass. Only
+    /// the genotypes of the parents are decoded.
+    pub fn stream_cohort_genotypes(&self,
+            children: Vec<(&Parents, &[HaplotypeSegment])>,
+            verbose: bool) -> Result<FamilyGenotypeStream> {
+        let tracts = |parent: &String, segments: &[HaplotypeSegment], hap: fn(&HaplotypeSegment) -> usize| {
+            segments.iter().map(|segment| FounderTract {
+                start: segment.start,
+                end: segment.end,
+                founder: parent.clone(),
+                hap: hap(segment),
+            }).collect::<Vec<_>>()
+        };
+        let children: Vec<[Vec<FounderTract>; 2]> = children.into_iter()
+            .map(|(parents, segments)| [
+                tracts(&parents.parent1, segments, |segment| segment.hap1),
+                tracts(&parents.parent2, segments, |segment| segment.hap2),
+            ])
+            .collect();
+        let children: Vec<[&[FounderTract]; 2]> = children.iter()
+            .map(|[hap1, hap2]| [hap1.as_slice(), hap2.as_slice()])
+            .collect();
+        self.stream_founder_genotypes(&children, verbose)
+    }
 
-        while bcf.read(&mut current_record).is_some() {
-            let pos = current_record.pos(); //  0-based position
-            let pos1based = pos + 1;
-            /*
-                    I understand that for DWGSIM, the positions are 1-based
-                    https://github.com/nh13/DWGSIM/blob/main/docs/03_Simulating_Reads.md#output-mutations-file
-             */
-            let alleles = from_vu8_to_string(current_record.alleles());
+    fn rid(&self, header: &bcf::header::HeaderView) -> Result<u32> {
+        header.name2rid(self.seqname.as_bytes())
+            .map_err(|_| MeiosimError::ContigNotFound {
+                contig: self.seqname.clone(),
+                source_name: self.file_path.clone(),
+            })
+    }
 
-            let issnp = from_alleles_to_issnp(&alleles);
-            if !issnp {
-                if verbose {
-                    warn!("Skipping record at {}:{} because it's not SNP/SNV", chromosome, pos);
+    fn sample_idx(&self, header: &bcf::header::HeaderView, sample: &str) -> Result<usize> {
+        header.sample_id(sample.as_bytes()).ok_or_else(|| MeiosimError::SampleNotFound {
+            sample: sample.to_string(),
+            path: self.file_path.clone(),
+        })
+    }
+
+    /// Streams the genotypes of children given as the founder tracts of
+    /// their two haplotypes on this chromosome, e.g. the chromosomes of a
+    /// [`FounderGenome`](crate::founders::FounderGenome). Only the
+    /// genotypes of the founders are decoded.
+    pub fn stream_founder_genotypes(&self,
+            children: &[[&[FounderTract]; 2]],
+            verbose: bool) -> Result<FamilyGenotypeStream> {
+        let founders: Vec<&String> = children.iter()
+            .flatten()
+            .flat_map(|tracts| tracts.iter().map(|tract| &tract.founder))
+            .collect();
+        // with regions, only their records are fetched through the index.
+        // The indexed reader ignores a sample subset, so the founders are
+        // picked from the full header.
+        let (reader, record, samples, sample_names, rid) = match &self.regions {
+            Some(regions) => {
+                let reader = bcf::IndexedReader::from_path(&self.file_path)
+                    .map_err(|e| MeiosimError::htslib(&self.file_path, e))?;
+                let header = reader.header();
+                let rid = self.rid(header)?;
+                let mut samples = founders.iter()
+                    .map(|founder| self.sample_idx(header, founder))
+                    .collect::<Result<Vec<usize>>>()?;
+                samples.sort();
+                samples.dedup();
+                let header_samples = header.samples();
+                let sample_names = from_vu8_to_string(samples.iter().map(|idx| header_samples[*idx]).collect());
+                let record = reader.empty_record();
+                let intervals = regions.contig(&self.seqname).to_vec();
+                (RecordReader::Regions { reader, rid, intervals, next: 0, fetched: false }, record, samples, sample_names, rid)
+            },
+            None => {
+                let reader = bcf::Reader::from_path(&self.file_path)
+                    .map_err(|e| MeiosimError::htslib(&self.file_path, e))?;
+                let header = reader.header();
+                for founder in founders.iter() {
+                    self.sample_idx(header, founder)?;
                 }
-                continue;
+                if !founders.is_empty() {
+                    subset_samples(header, &founders, &self.file_path)?;
+                }
+                let rid = self.rid(header)?;
+                // indices change once the header is subset
+                let sample_names = from_vu8_to_string(header.samples());
+                let samples = (0..sample_names.len()).collect();
+                let record = reader.empty_record();
+                (RecordReader::Full(reader), record, samples, sample_names, rid)
+            },
+        };
+        let sample_positions: HashMap<&str, usize> = sample_names.iter()
+            .enumerate()
+            .map(|(position, name)| (name.as_str(), position))
+            .collect();
+        let cursor = |tracts: &[FounderTract]| TractCursor {
+            tracts: tracts.iter()
+                .map(|tract| (tract.end, sample_positions[tract.founder.as_str()], tract.hap))
+                .collect(),
+            current: 0,
+        };
+        let children = children.iter()
+            .map(|[hap1, hap2]| [cursor(hap1), cursor(hap2)])
+            .collect();
+        let resolvers = sample_names.iter()
+            .map(|sample| GenotypeResolver::new(sample, &self.seqname, &self.genotype_policy))
+            .collect();
+        let reference = self.reference.as_ref()
+            .map(|reference| reference.cursor(&self.seqname, &self.file_path))
+            .transpose()?;
+
+        Ok(FamilyGenotypeStream {
+            reader,
+            record,
+            file_path: self.file_path.clone(),
+            seqname: self.seqname.clone(),
+            rid,
+            children,
+            samples,
+            resolvers,
+            policy: self.genotype_policy,
+            filter: Arc::clone(&self.filter),
+            regions: self.regions.clone(),
+            filtered: 0,
+            reference,
+            verbose,
+        })
+    }
+}
+
+fn subset_samples(header: &bcf::header::HeaderView, samples: &[&String], path: &str) -> Result<()> {
+    // let htslib skip the FORMAT fields of every other sample when
+    // unpacking records, as bcftools view -s does
+    let mut unique_samples: Vec<&str> = samples.iter().map(|sample| sample.as_str()).collect();
+    unique_samples.sort();
+    unique_samples.dedup();
+    let sample_list = CString::new(unique_samples.join(","))
+        .map_err(|_| MeiosimError::input(path, "invalid sample name"))?;
+    // SAFETY: the header belongs to a reader that has not read any record
+    // yet, and htslib copies the sample list.
+    let ret = unsafe { htslib::bcf_hdr_set_samples(header.inner, sample_list.as_ptr(), 0) };
+    if ret != 0 {
+        return Err(MeiosimError::input(path, &format!("cannot subset the samples {}", unique_samples.join(","))));
+    }
+    Ok(())
+}
+
+/// Genotype of a simulated child at one SNV site. `gt.0` is the allele
+/// transmitted by parent1 and `gt.1` the one transmitted by parent2.
+#[derive(Clone, Debug)]
+pub struct ChildGenotype {
+    pub seqname: String,
+    /// 0-based position
+    pub pos: i64,
+    pub alleles: Vec<String>,
+    pub gt: (i32, i32),
+}
+
+impl ChildGenotype {
+    /// Line in DWGSIM mutation format, empty if the child is homozygous for
+    /// the reference allele.
+    pub fn to_dwgsim(&self) -> Result<String> {
+        /*
+                I understand that for DWGSIM, the positions are 1-based
+                https://github.com/nh13/DWGSIM/blob/main/docs/03_Simulating_Reads.md#output-mutations-file
+         */
+        if self.gt == (0, 0) {
+            return Ok(String::new());
+        }
+        compose_dwgsim_format(&self.seqname, self.pos + 1, self.alleles.clone(), &self.gt.0, &self.gt.1)
+    }
+}
+
+/// Genotypes of all the children of a family (or cohort) at one SNV site,
+/// in the order the children were given to the stream. A genotype
+/// is `None` when one of the transmitted parental alleles is missing.
+#[derive(Clone, Debug)]
+pub struct FamilyGenotypes {
+    pub seqname: String,
+    /// 0-based position
+    pub pos: i64,
+    pub alleles: Vec<String>,
+    pub gts: Vec<Option<(i32, i32)>>,
+}
+
+impl FamilyGenotypes {
+    pub fn child(&self, child_idx: usize) -> Option<ChildGenotype> {
+        self.gts[child_idx].map(|gt| ChildGenotype {
+            seqname: self.seqname.clone(),
+            pos: self.pos,
+            alleles: self.alleles.clone(),
+            gt,
+        })
+    }
+}
+
+// one haplotype of a child, as (end, sample index, haplotype index) of
+// the founder tracts it is made of
+struct TractCursor {
+    tracts: Vec<(u64, usize, usize)>,
+    current: usize,
+}
+
+impl TractCursor {
+    // records come sorted, so the cursor only moves forward
+    fn founder_at(&mut self, pos: u64) -> Option<(usize, usize)> {
+        while let Some((end, sample_idx, hap)) = self.tracts.get(self.current) {
+            if pos < *end {
+                return Some((*sample_idx, *hap));
             }
+            self.current += 1;
+        }
+        None
+    }
+}
 
-            let genotypes = current_record.genotypes().expect("Error reading genotypes");
+// records of a chromosome, read from the whole VCF or fetched region by
+// region through the index
+enum RecordReader {
+    Full(bcf::Reader),
+    Regions {
+        reader: bcf::IndexedReader,
+        rid: u32,
+        intervals: Vec<(u64, u64)>,
+        // next region to fetch, and whether a region is being read
+        next: usize,
+        fetched: bool,
+    },
+}
 
-            let parent1_gt = genotypes.get(parent1_idx);
-            let parent2_gt = genotypes.get(parent2_idx);
+impl RecordReader {
+    fn read(&mut self, record: &mut bcf::Record) -> Option<rust_htslib::errors::Result<()>> {
+        match self {
+            RecordReader::Full(reader) => reader.read(record),
+            RecordReader::Regions { reader, rid, intervals, next, fetched } => loop {
+                if *fetched {
+                    match reader.read(record) {
+                        None => *fetched = false,
+                        result => return result,
+                    }
+                }
+                let (start, end) = *intervals.get(*next)?;
+                *next += 1;
+                // the seek fails when the index has no record there
+                *fetched = end > start && reader.fetch(*rid, start, Some(end - 1)).is_ok();
+            },
+        }
+    }
+}
 
-            let parent1_gt_hapl = extract_value(parent1_gt.get(hap1).unwrap());
-            let parent2_gt_hapl = extract_value(parent2_gt.get(hap2).unwrap());
+/// Iterator over the [`FamilyGenotypes`] of one chromosome, reading the VCF
+/// once from start to end, or only the records of the regions.
+pub struct FamilyGenotypeStream {
+    reader: RecordReader,
+    record: bcf::Record,
+    file_path: String,
+    seqname: String,
+    rid: u32,
+    children: Vec<[TractCursor; 2]>,
+    // header index of each founder
+    samples: Vec<usize>,
+    // one per founder, in the order of `samples`
+    resolvers: Vec<GenotypeResolver>,
+    policy: GenotypePolicy,
+    filter: Arc<VariantFilter>,
+    regions: Option<Arc<Regions>>,
+    // SNVs that did not pass the filter
+    filtered: u64,
+    reference: Option<ReferenceCursor>,
+    verbose: bool,
+}
 
-            if parent1_gt_hapl.is_none() || parent2_gt_hapl.is_none() {
-                if verbose {
-                    warn!("Skipping record at {}:{} because one of the parents has missing genotype", chromosome, pos);
+impl FamilyGenotypeStream {
+    fn genotype_error(&self, pos: i64, message: &str) -> MeiosimError {
+        MeiosimError::Genotype {
+            path: self.file_path.clone(),
+            contig: self.seqname.clone(),
+            pos: pos + 1,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Iterator for FamilyGenotypeStream {
+    type Item = Result<FamilyGenotypes>;
+
+    fn next(&mut self) -> Option<Result<FamilyGenotypes>> {
+        let site = self.next_site();
+        if site.is_none() {
+            for resolver in self.resolvers.iter_mut() {
+                resolver.report(&self.seqname);
+            }
+            if self.filtered > 0 {
+                info!("{} SNVs of {} did not pass the variant filters", self.filtered, self.seqname);
+                self.filtered = 0;
+            }
+            if let Some(reference) = self.reference.as_mut() {
+                reference.report();
+            }
+        }
+        site
+    }
+}
+
+impl FamilyGenotypeStream {
+    fn next_site(&mut self) -> Option<Result<FamilyGenotypes>> {
+        loop {
+            match self.reader.read(&mut self.record) {
+                None => return None,
+                Some(Err(e)) => {
+                    return Some(Err(MeiosimError::htslib(&self.file_path, e)));
+                },
+                Some(Ok(())) => {}
+            }
+
+            if self.record.rid() != Some(self.rid) {
+                continue;
+            }
+
+            let pos = self.record.pos(); //  0-based position
+            // the index also returns the records that start before a
+            // region and overlap it
+            if self.regions.as_ref().is_some_and(|regions| !regions.contains(&self.seqname, pos as u64)) {
+                continue;
+            }
+            let alleles = from_vu8_to_string(self.record.alleles());
+
+            let issnp = from_alleles_to_issnp(&alleles);
+            if !issnp {
+                if self.verbose {
+                    warn!("Skipping record at {}:{} because it's not SNP/SNV", self.seqname, pos);
                 }
                 continue;
-            } else {
-                let parent1_gt_hapl = parent1_gt_hapl.unwrap();
-                let parent2_gt_hapl = parent2_gt_hapl.unwrap();
-                if parent1_gt_hapl == &(0 as i32) && parent2_gt_hapl == &(0 as i32) {
-                    continue;
+            }
+            if self.filter.is_active() {
+                let pass = !self.filter.pass_only || self.record.has_filter("PASS".as_bytes());
+                let allele_frequency = if self.filter.needs_allele_frequency() {
+                    allele_frequency(&self.record)
                 } else {
-                    let line_out = compose_dwgsim_format(&chromosome, pos1based, alleles, parent1_gt_hapl, parent2_gt_hapl);
-                    write!(output_writter, "{}", line_out).expect("Unable to write to file");
+                    None
+                };
+                if !self.filter.keep(&self.seqname, pos as u64, &alleles, pass, allele_frequency) {
+                    self.filtered += 1;
+                    continue;
+                }
+            }
+            if let Some(reference) = self.reference.as_mut() {
+                match reference.check(pos as u64, &alleles[0], self.verbose) {
+                    Ok(true) => {},
+                    Ok(false) => continue,
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+
+            let mut founders = Vec::with_capacity(self.children.len());
+            for [hap1, hap2] in self.children.iter_mut() {
+                match (hap1.founder_at(pos as u64), hap2.founder_at(pos as u64)) {
+                    (Some(founder1), Some(founder2)) => founders.push((founder1, founder2)),
+                    // tracts end at the contig length, anything beyond
+                    // is not transmitted
+                    _ => return None,
                 }
             }
-        }
 
+            let genotypes = match self.record.genotypes() {
+                Ok(genotypes) => genotypes,
+                Err(e) => return Some(Err(MeiosimError::htslib(&self.file_path, e))),
+            };
+
+            let sample_count = self.record.sample_count() as usize;
+            let phase_sets = match self.policy.phasing {
+                PhasingPolicy::PhaseSet => phase_sets(&self.record, sample_count),
+                _ => vec![None; sample_count],
+            };
+            let allele_frequency = match self.policy.missing {
+                MissingPolicy::Impute => allele_frequency(&self.record),
+                _ => None,
+            };
+            let mut parent_gts = Vec::with_capacity(self.samples.len());
+            for (resolver, sample_idx) in self.resolvers.iter_mut().zip(self.samples.iter()) {
+                match resolver.resolve(&genotypes.get(*sample_idx), phase_sets[*sample_idx], allele_frequency) {
+                    Ok(alleles) => parent_gts.push(alleles),
+                    Err(message) => return Some(Err(self.genotype_error(pos, &message))),
+                }
+            }
+
+            let mut gts = Vec::with_capacity(founders.len());
+            for ((sample1_idx, hap1), (sample2_idx, hap2)) in founders {
+                let parent1_gt = &parent_gts[sample1_idx];
+                let parent2_gt = &parent_gts[sample2_idx];
+                let (parent1_allele, parent2_allele) = match (parent1_gt.get(hap1), parent2_gt.get(hap2)) {
+                    (Some(parent1_allele), Some(parent2_allele)) => (parent1_allele, parent2_allele),
+                    _ => return Some(Err(self.genotype_error(pos, "the parents have fewer haplotypes than the ploidy simulated"))),
+                };
+                match (parent1_allele, parent2_allele) {
+                    (Some(parent1_gt_hapl), Some(parent2_gt_hapl)) => {
+                        gts.push(Some((*parent1_gt_hapl, *parent2_gt_hapl)));
+                    },
+                    _ => {
+                        if self.verbose {
+                            warn!("Skipping record at {}:{} because one of the parents has missing genotype", self.seqname, pos);
+                        }
+                        gts.push(None);
+                    }
+                }
+            }
+
+            return Some(Ok(FamilyGenotypes {
+                seqname: self.seqname.clone(),
+                pos,
+                alleles,
+                gts,
+            }));
+        }
     }
 }
 
@@ -114,31 +769,174 @@ pub struct VCFCollection {
 }
 
 impl VCFCollection {
-    pub fn from_path(path: &str, extension: &str, verbose: bool) -> VCFCollection {
-        let list_of_files = list_files_in_directory(path, extension).unwrap();
+    pub fn from_path(path: &str, extension: &str, verbose: bool) -> Result<VCFCollection> {
+        let list_of_files = list_files_in_directory(path, extension)?;
 
         let mut vcfs = HashMap::new();
         for file in list_of_files {
-            let seqname = capture_chromosome_from_file_name(&file, verbose);
-            let seqname2 = seqname.clone();
-            let vcf: VCF = VCF::new(file, seqname);
-            vcfs.insert(seqname2, vcf);
+            let seqname = capture_chromosome_from_file_name(&file, verbose)?;
+            let vcf: VCF = VCF::new(file, seqname.clone())?;
+            vcfs.insert(seqname, vcf);
         }
 
-        if vcfs.len() == 0 {
-            panic!("No VCF files found in the folder");
-        } else {
-            if verbose {
-                info!("Total VCFs found: {:?}", vcfs.len());
-            }
+        if vcfs.is_empty() {
+            return Err(MeiosimError::input(path, "No VCF files found in the folder"));
+        } else if verbose {
+            info!("Total VCFs found: {:?}", vcfs.len());
         }
 
-        VCFCollection {
+        Ok(VCFCollection {
             vcfs,
+        })
+    }
+
+    /// Sets how the unphased and missing genotypes of the panel are
+    /// handled.
+    pub fn set_genotype_policy(&mut self, policy: GenotypePolicy) {
+        for vcf in self.vcfs.values_mut() {
+            vcf.genotype_policy = policy;
+        }
+    }
+
+    /// Restricts the simulation to `regions`: only the VCFs of their
+    /// contigs are kept, and only the records inside them are transmitted.
+    pub fn set_regions(&mut self, regions: Regions) -> Result<()> {
+        if let Some(chr) = regions.contigs().find(|chr| !self.vcfs.contains_key(*chr)) {
+            return Err(MeiosimError::Config(format!("No population VCF for {}, a contig of the regions", chr)));
+        }
+        let regions = Arc::new(regions);
+        self.vcfs.retain(|chr, _| !regions.contig(chr).is_empty());
+        for vcf in self.vcfs.values_mut() {
+            vcf.regions = Some(Arc::clone(&regions));
+        }
+        Ok(())
+    }
+
+    /// Checks the REF alleles of the transmitted records against a
+    /// reference genome.
+    pub fn set_reference_check(&mut self, reference: ReferenceCheck) {
+        let reference = Arc::new(reference);
+        for vcf in self.vcfs.values_mut() {
+            vcf.reference = Some(Arc::clone(&reference));
+        }
+    }
+
+    /// Check of the REF alleles against a reference genome, if any, also
+    /// used for the DNMs.
+    pub fn reference_check(&self) -> Option<&ReferenceCheck> {
+        self.vcfs.values().next().and_then(|vcf| vcf.reference.as_deref())
+    }
+
+    /// Regions the simulation is restricted to, if any.
+    pub fn regions(&self) -> Option<&Regions> {
+        self.vcfs.values().next().and_then(|vcf| vcf.regions.as_deref())
+    }
+
+    /// Whether the 0-based position `pos` of `chr` is simulated, i.e. in
+    /// the regions the simulation is restricted to, if any.
+    pub fn in_regions(&self, chr: &str, pos: u64) -> bool {
+        self.regions().is_none_or(|regions| regions.contains(chr, pos))
+    }
+
+    /// Sets the filters of the records transmitted to the children.
+    pub fn set_variant_filter(&mut self, filter: VariantFilter) {
+        let filter = Arc::new(filter);
+        for vcf in self.vcfs.values_mut() {
+            vcf.filter = Arc::clone(&filter);
+        }
+    }
+
+    pub fn contig_sizes(&self) -> Result<HashMap<String, u64>> {
+        // contig sizes taken from the ##contig header lines of each VCF,
+        // only for the chromosome that each file contains
+        self.vcfs.iter().map(|(seqname, vcf)| {
+            let size = vcf.contig_length.ok_or_else(|| {
+                MeiosimError::input(&vcf.file_path,
+                    &format!("no length declared for {} in the ##contig header, please provide a genome file or .fai index (--genome)", seqname))
+            })?;
+            Ok((seqname.clone(), size))
+        }).collect()
+    }
+
+    /// Samples present in all the VCFs, sorted.
+    pub fn samples(&self) -> Vec<String> {
+        let mut vcfs = self.vcfs.values();
+        let mut samples: Vec<String> = match vcfs.next() {
+            Some(vcf) => vcf.samples.clone(),
+            None => return Vec::new(),
+        };
+        for vcf in vcfs {
+            samples.retain(|sample| vcf.samples.contains(sample));
+        }
+        samples.sort();
+        samples
+    }
+
+    /// Checks that all the `samples` are in every VCF.
+    pub fn check_samples(&self, samples: &[String]) -> Result<()> {
+        for vcf_obj in self.vcfs.values() {
+            for sample in samples {
+                if !vcf_obj.samples.contains(sample) {
+                    return Err(MeiosimError::SampleNotFound {
+                        sample: sample.clone(),
+                        path: vcf_obj.file_path.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn chromosomes(&self) -> Vec<String> {
+        let mut chr_vector: Vec<String> = self.vcfs.keys().cloned().collect();
+        chr_vector.sort();
+        chr_vector
+    }
+
+    pub fn flag_records_outside_contigs(&self, contig_size: &HashMap<String, u64>) -> Result<()> {
+        for (seqname, vcf) in self.vcfs.iter() {
+            if let Some(size) = contig_size.get(seqname) {
+                let outside_records = vcf.count_records_from(seqname, *size)?;
+                if outside_records > 0 {
+                    warn!("{} records in {} lie beyond the declared length of {} ({}) and will not be transmitted",
+                        outside_records, vcf.file_path, seqname, size);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flag_contig_mismatches(&self, contig_size: &HashMap<String, u64>) {
+        for (seqname, vcf) in self.vcfs.iter() {
+            match (contig_size.get(seqname), vcf.contig_length) {
+                (None, _) => {
+                    warn!("{} from {} is missing in the genome file", seqname, vcf.file_path);
+                },
+                (Some(size), Some(header_size)) if *size != header_size => {
+                    warn!("{} has length {} in the genome file but {} in the header of {}",
+                        seqname, size, header_size, vcf.file_path);
+                },
+                _ => {}
+            }
         }
     }
 }
 
+fn contig_length_from_header(header: &bcf::header::HeaderView, seqname: &String) -> Option<u64> {
+    header.header_records().iter().find_map(|record| {
+        match record {
+            bcf::header::HeaderRecord::Contig { values, .. } => {
+                if values.get("ID") == Some(seqname) {
+                    values.get("length").and_then(|length| length.parse::<u64>().ok())
+                } else {
+                    None
+                }
+            },
+            _ => None,
+        }
+    })
+}
+
 
 fn extract_value(allele: &GenotypeAllele) -> Option<&i32> {
     match allele {
@@ -149,7 +947,7 @@ fn extract_value(allele: &GenotypeAllele) -> Option<&i32> {
     }
 }
 
-fn compose_dwgsim_format(chromosome: &String, pos1based: i64, mut alleles: Vec<String>, parent1_gt_hapl: &i32, parent2_gt_hapl: &i32) -> String {
+fn compose_dwgsim_format(chromosome: &str, pos1based: i64, mut alleles: Vec<String>, parent1_gt_hapl: &i32, parent2_gt_hapl: &i32) -> Result<String> {
     // see here https://github.com/nh13/DWGSIM/blob/main/docs/03_Simulating_Reads.md#output-mutations-file
     // I am unsure what the strand means here,
     // I am assuming all SNPs are strand 1 means from parent1 and 2 from parent 2.
@@ -158,160 +956,251 @@ fn compose_dwgsim_format(chromosome: &String, pos1based: i64, mut alleles: Vec<S
     if parent1_gt_hapl == &0 || parent2_gt_hapl == &0 && parent1_gt_hapl != parent2_gt_hapl{
         // this would be the case where the variant is heterozygous, as not 0|0 should be reported.
         // then we have to define the strand as 1 or 2.
-        let strand: String;
-        if parent1_gt_hapl == &0 {
-            strand = "1".to_string();
+        let strand = if parent1_gt_hapl == &0 {
+            "1"
         } else {
-            strand = "2".to_string();
-        }
+            "2"
+        };
         let ref_all = alleles[0].clone();
         // this is a bit stupid!
-        let _ = alleles.sort();
-        let iupac_code = get_iupac_representation(&alleles[0], &alleles[1]);
+        alleles.sort();
+        let iupac_code = get_iupac_representation(&alleles[0], &alleles[1])?;
         string_out = format!("{}\t{}\t{}\t{}\t{}\n", chromosome, pos1based, ref_all, iupac_code, strand);
     } else if parent1_gt_hapl == &1 && parent2_gt_hapl == &1 {
-        // this would be the case where the SNP is homozygous, strand does not matter     
+        // this would be the case where the SNP is homozygous, strand does not matter
         let ref_all = &alleles[0];
         let alt_all = &alleles[1];
         string_out = format!("{}\t{}\t{}\t{}\t3\n", chromosome, pos1based, ref_all, alt_all);
     }
-    string_out
+    Ok(string_out)
 }
 
-pub fn get_iupac_representation(x: &str, y: &str) -> String {
+pub fn get_iupac_representation(x: &str, y: &str) -> Result<String> {
     // see here https://genome.ucsc.edu/goldenPath/help/iupac.html
-    if x == "A" && y == "G" {
-        "R".to_string()
-    } else if x == "C" && y == "T" {
-        "Y".to_string()
-    } else if x == "C" && y == "G" {
-        "S".to_string()
-    } else if x == "A" && y == "T" {
-        "W".to_string()
-    } else if x == "G" && y == "T" {
-        "K".to_string()
-    } else if x == "A" && y == "C" {
-        "M".to_string()
-    } else {
-        panic!("IUPAC code not found for {} and {}", x, y);
-    }
-}
-
-pub fn flush_dnm_to_file(output_writter: &mut File, truepos_writter: &mut File, dnm: &String, verbose: bool, rng_dnm: &mut StdRng) -> () {
-    // is this dumb?
-    let dnm_reader1 = bcf::Reader::from_path(dnm)
-            .expect("Cannot open the file");
-    let header = dnm_reader1.header();
+    let code = match (x, y) {
+        ("A", "G") => "R",
+        ("C", "T") => "Y",
+        ("C", "G") => "S",
+        ("A", "T") => "W",
+        ("G", "T") => "K",
+        ("A", "C") => "M",
+        _ => return Err(MeiosimError::Iupac(x.to_string(), y.to_string())),
+    };
+    Ok(code.to_string())
+}
+
+pub fn flush_dnm_to_file(output_writter: &mut OutputFile, truepos_writter: &mut OutputFile, dnm: &str, verbose: bool, rng_dnm: &mut StdRng) -> Result<()> {
+    let dnms = read_dnm_genotypes(dnm, verbose, rng_dnm)?;
+    write_dnm_genotypes(output_writter, truepos_writter, &dnms)
+}
 
+/// Reads the de novo SNVs of a child, each one assigned to a random
+/// parental strand.
+pub fn read_dnm_genotypes(dnm: &str, verbose: bool, rng_dnm: &mut StdRng) -> Result<Vec<ChildGenotype>> {
     let mut dnm_reader = bcf::Reader::from_path(dnm)
-            .expect("Cannot open the file");
+            .map_err(|e| MeiosimError::htslib(dnm, e))?;
+    let header = dnm_reader.header().clone();
 
-    dnm_reader.records().into_iter()
-        .for_each(|x|{
-            let record = x.unwrap();
-            let pos = record.pos(); //  0-based position
-            let pos1based = pos + 1;
-            let chrom_u8 = Vec::from(header.rid2name(record.rid().unwrap()).unwrap());
-            let chrom = String::from_utf8(chrom_u8).unwrap();
-            let alleles = from_vu8_to_string(record.alleles());
+    let mut dnms = Vec::new();
+    for x in dnm_reader.records() {
+        let record = x.map_err(|e| MeiosimError::htslib(dnm, e))?;
+        let pos = record.pos(); //  0-based position
+        let chrom = record_contig(&header, &record, dnm)?;
+        let alleles = from_vu8_to_string(record.alleles());
 
-            let issnp = from_alleles_to_issnp(&alleles);
-            if !issnp {
-                if verbose {
-                    warn!("Skipping record at {}:{} because it's not SNP/SNV", chrom, pos);
-                }
-                return;
+        let issnp = from_alleles_to_issnp(&alleles);
+        if !issnp {
+            if verbose {
+                warn!("Skipping record at {}:{} because it's not SNP/SNV", chrom, pos);
             }
+            continue;
+        }
 
-            // I am forcing them to be "heterozygous" but
-            // the parent of choice is "random"
-            
-            let gts = if rng_dnm.gen_range(0..2) == 0 { (0, 1) } else { (1, 0) };
-        
-            let line_out = compose_dwgsim_format(
-                        &chrom,
-                        pos1based,
-                        alleles,
-                        &gts.0, 
-                        &gts.1);
-            write!(output_writter, "{}", line_out).expect("Unable to write to file");
-            write!(truepos_writter, "{}\t{}\n", chrom, pos1based).expect("Unable to write to file");
+        // I am forcing them to be "heterozygous" but
+        // the parent of choice is "random"
+
+        let gt = if rng_dnm.gen_range(0..2) == 0 { (0, 1) } else { (1, 0) };
+
+        dnms.push(ChildGenotype {
+            seqname: chrom,
+            pos,
+            alleles,
+            gt,
         });
-        ()
+    }
+    Ok(dnms)
 }
 
-pub fn flush_vcf_to_file(output_writter: &mut File, vcfname: &String, verbose: bool) -> () {
-    // is this dumb?
-    let vcf_reader1 = bcf::Reader::from_path(vcfname)
-            .expect("Cannot open the file");
-    let header = vcf_reader1.header();
+/// Appends the de novo SNVs to the DWGSIM file of a child, and their
+/// 1-based positions to its truth file.
+pub fn write_dnm_genotypes(output_writter: &mut OutputFile, truepos_writter: &mut OutputFile, dnms: &[ChildGenotype]) -> Result<()> {
+    for dnm in dnms {
+        output_writter.write_str(&dnm.to_dwgsim()?)?;
+        truepos_writter.write_str(&format!("{}\t{}\n", dnm.seqname, dnm.pos + 1))?;
+    }
+    Ok(())
+}
 
+/// Writes the genotypes of the first sample of a VCF in DWGSIM format,
+/// resolving its unphased and missing genotypes as set by `policy`.
+pub fn flush_vcf_to_file(output_writter: &mut OutputFile, vcfname: &str, policy: &GenotypePolicy, verbose: bool) -> Result<()> {
     let mut vcf_reader = bcf::Reader::from_path(vcfname)
-            .expect("Cannot open the file");
+            .map_err(|e| MeiosimError::htslib(vcfname, e))?;
+    let header = vcf_reader.header().clone();
+    let sample = from_vu8_to_string(header.samples()).into_iter().next().unwrap_or_default();
+    // the phases are drawn per chromosome, as in the population panel
+    let mut resolvers: Vec<(String, GenotypeResolver)> = Vec::new();
 
-    vcf_reader.records().into_iter()
-        .for_each(|x|{
-            let record = x.unwrap();
-            let pos = record.pos(); //  0-based position
-            let pos1based = pos + 1;
-            let chrom_u8 = Vec::from(header.rid2name(record.rid().unwrap()).unwrap());
-            let chrom = String::from_utf8(chrom_u8).unwrap();
-            let alleles = from_vu8_to_string(record.alleles());
+    for x in vcf_reader.records() {
+        let record = x.map_err(|e| MeiosimError::htslib(vcfname, e))?;
+        let pos = record.pos(); //  0-based position
+        let pos1based = pos + 1;
+        let chrom = record_contig(&header, &record, vcfname)?;
+        let alleles = from_vu8_to_string(record.alleles());
 
-            let issnp = from_alleles_to_issnp(&alleles);
-            if !issnp {
-                if verbose {
-                    warn!("Skipping record at {}:{} because it's not SNP/SNV", chrom, pos);
-                }
-                return;
+        let issnp = from_alleles_to_issnp(&alleles);
+        if !issnp {
+            if verbose {
+                warn!("Skipping record at {}:{} because it's not SNP/SNV", chrom, pos);
             }
-            // currently we are assuming the vcf is uni-sample here as I
-            // have it implemented it like this in the pipeline,
-            // this can be improved by adding a specific sample.
-            // I think however this way is easier to parall.
-            let gts_raw: bcf::record::Genotype = record.genotypes().unwrap().get(0);
-            let gt1_ga: &GenotypeAllele = gts_raw.get(0).unwrap();
-            let gt2_ga : &GenotypeAllele = gts_raw.get(1).unwrap();
-
-            // the first record should always be unphased
-            // https://docs.rs/rust-htslib/latest/rust_htslib/bcf/record/struct.Genotypes.html#implementations
-            let gt1_int = match gt1_ga {
-                GenotypeAllele::Unphased(value) => value,
-                GenotypeAllele::Phased(_value) => panic!("problem with phasing??"),
-                GenotypeAllele::UnphasedMissing => panic!("Missing GT error"),
-                GenotypeAllele::PhasedMissing => panic!("Phased missing GT error"),
-            };
+            continue;
+        }
+        let genotype_error = |message: &str| MeiosimError::Genotype {
+            path: vcfname.to_string(),
+            contig: chrom.clone(),
+            pos: pos1based,
+            message: message.to_string(),
+        };
+        // currently we are assuming the vcf is uni-sample here as I
+        // have it implemented it like this in the pipeline,
+        // this can be improved by adding a specific sample.
+        // I think however this way is easier to parall.
+        let genotypes = record.genotypes().map_err(|e| MeiosimError::htslib(vcfname, e))?;
+        let gts_raw: bcf::record::Genotype = genotypes.get(0);
+        let phase_set = match policy.phasing {
+            PhasingPolicy::PhaseSet => phase_sets(&record, 1).into_iter().next().flatten(),
+            _ => None,
+        };
+        let allele_frequency = match policy.missing {
+            MissingPolicy::Impute => allele_frequency(&record),
+            _ => None,
+        };
+        if resolvers.last().is_none_or(|(seqname, _)| *seqname != chrom) {
+            resolvers.push((chrom.clone(), GenotypeResolver::new(&sample, &chrom, policy)));
+        }
+        let resolver = &mut resolvers.last_mut().unwrap().1;
+        let resolved = resolver.resolve(&gts_raw, phase_set, allele_frequency).map_err(|message| genotype_error(&message))?;
+        let (gt1_int, gt2_int) = match resolved.as_slice() {
+            [Some(gt1_int), Some(gt2_int)] => (gt1_int, gt2_int),
+            // the site is not written, as for a child
+            [_, _] => continue,
+            _ => return Err(genotype_error("the sample is expected to be diploid")),
+        };
 
-            let gt2_int = match gt2_ga {
-                GenotypeAllele::Unphased(_value) => panic!("problem with phasing??"),
-                GenotypeAllele::Phased(value) => value,
-                GenotypeAllele::UnphasedMissing => panic!("Missing GT error"),
-                GenotypeAllele::PhasedMissing => panic!("Phased missing GT error"),
-            };
+        let line_out = compose_dwgsim_format(
+                    &chrom,
+                    pos1based,
+                    alleles,
+                    gt1_int,
+                    gt2_int)?;
+        output_writter.write_str(&line_out)?;
+    }
+    for (seqname, resolver) in resolvers.iter_mut() {
+        resolver.report(seqname);
+    }
+    Ok(())
+}
 
-            let line_out = compose_dwgsim_format(
-                        &chrom,
-                        pos1based,
-                        alleles,
-                        &gt1_int, 
-                        &gt2_int);
-            write!(output_writter, "{}", line_out).expect("Unable to write to file");
-        });
-        ()
+fn record_contig(header: &bcf::header::HeaderView, record: &bcf::Record, path: &str) -> Result<String> {
+    let contig_error = || MeiosimError::input(path, &format!("record at position {} has no contig", record.pos() + 1));
+    let rid = record.rid().ok_or_else(contig_error)?;
+    let chrom_u8 = header.rid2name(rid).map_err(|_| contig_error())?;
+    Ok(String::from_utf8_lossy(chrom_u8).to_string())
+}
+
+fn from_alleles_to_issnp(alleles: &[String]) -> bool {
+    let any_greater_than_one = alleles.iter().any(|allele| allele.len() > 1);
+    alleles.len() == 2 && !any_greater_than_one
 }
 
-fn from_alleles_to_issnp(alleles: &Vec<String>) -> bool {
-    let alleles_len: Vec<usize>= alleles.iter()
-        .map(|allele| {allele.len()})
-        .collect();
-    let any_greater_than_one = alleles_len.iter().any(|&len| len > 1);
+#[cfg(test)]
+mod tests {
+    use super::{GenotypePolicy, GenotypeResolver, MissingPolicy, PhasingPolicy};
+    use crate::seeds::SeedTree;
+    use rust_htslib::bcf::record::GenotypeAllele;
 
-    if alleles.len() != 2{
-        false
-    } else if any_greater_than_one{
-        false
-    } else {
-        true
+    fn unphased(first: i32, second: i32) -> [GenotypeAllele; 2] {
+        [GenotypeAllele::Unphased(first), GenotypeAllele::Unphased(second)]
+    }
+
+    fn phased(first: i32, second: i32) -> [GenotypeAllele; 2] {
+        [GenotypeAllele::Unphased(first), GenotypeAllele::Phased(second)]
     }
-}
 
+    fn resolver(phasing: PhasingPolicy, missing: MissingPolicy) -> GenotypeResolver {
+        GenotypeResolver::new("S1", "chr1", &GenotypePolicy::new(phasing, missing, SeedTree::new(7)))
+    }
+
+    #[test]
+    fn test_refuse_unphased_heterozygous() {
+        let mut resolver = resolver(PhasingPolicy::Refuse, MissingPolicy::Skip);
+        assert!(resolver.phase(&unphased(0, 1), None).is_err(), "An unphased heterozygous genotype was accepted");
+        assert_eq!(resolver.phase(&unphased(1, 1), None).unwrap(), vec![Some(1), Some(1)]);
+        assert_eq!(resolver.phase(&phased(1, 0), None).unwrap(), vec![Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn test_random_phases_are_seeded() {
+        let phases = || {
+            let mut resolver = resolver(PhasingPolicy::Random, MissingPolicy::Skip);
+            let phases: Vec<Vec<Option<i32>>> = (0..50).map(|_| resolver.phase(&unphased(0, 1), None).unwrap()).collect();
+            assert_eq!(resolver.randomly_phased, 50, "Not every unphased genotype was counted");
+            phases
+        };
+        let first = phases();
+        assert_eq!(first, phases(), "The random phases do not follow the seed");
+        assert!(first.contains(&vec![Some(0), Some(1)]) && first.contains(&vec![Some(1), Some(0)]),
+            "The random phases always keep the same order");
+    }
+
+    #[test]
+    fn test_phase_sets_keep_relative_phase() {
+        let mut resolver = resolver(PhasingPolicy::PhaseSet, MissingPolicy::Skip);
+        // whether each site of two interleaved phase sets kept its order
+        let mut kept: [Vec<bool>; 2] = [Vec::new(), Vec::new()];
+        for site in 0..40 {
+            let (first, second) = if site % 4 < 2 { (0, 1) } else { (1, 0) };
+            let alleles = resolver.phase(&phased(first, second), Some([100, 200][site % 2])).unwrap();
+            kept[site % 2].push(alleles == vec![Some(first), Some(second)]);
+        }
+        for block in &kept {
+            assert!(block.iter().all(|&k| k == block[0]), "A phase set was reoriented within the block");
+        }
+        assert_eq!(resolver.randomly_phased, 0, "Phased genotypes were counted as randomly phased");
+    }
+
+    #[test]
+    fn test_missing_policies() {
+        let missing = [GenotypeAllele::UnphasedMissing, GenotypeAllele::Phased(1)];
+        let resolve = |policy: MissingPolicy, allele_frequency: Option<f64>| {
+            resolver(PhasingPolicy::Refuse, policy).resolve(&missing, None, allele_frequency)
+        };
+        assert_eq!(resolve(MissingPolicy::Skip, None).unwrap(), vec![None, Some(1)]);
+        assert_eq!(resolve(MissingPolicy::Ref, None).unwrap(), vec![Some(0), Some(1)]);
+        assert_eq!(resolve(MissingPolicy::Impute, Some(1.0)).unwrap(), vec![Some(1), Some(1)]);
+        assert_eq!(resolve(MissingPolicy::Impute, Some(0.0)).unwrap(), vec![Some(0), Some(1)]);
+        assert!(resolve(MissingPolicy::Impute, None).unwrap_err().contains("no INFO/AF"), "Imputed without an allele frequency");
+        assert!(resolve(MissingPolicy::Fail, Some(0.5)).is_err(), "A missing genotype was accepted");
+    }
+
+    #[test]
+    fn test_missing_sites_are_counted() {
+        let mut resolver = resolver(PhasingPolicy::Refuse, MissingPolicy::Ref);
+        resolver.resolve(&[GenotypeAllele::UnphasedMissing, GenotypeAllele::UnphasedMissing], None, None).unwrap();
+        resolver.resolve(&phased(0, 1), None, None).unwrap();
+        resolver.resolve(&[GenotypeAllele::Unphased(0), GenotypeAllele::PhasedMissing], None, None).unwrap();
+        assert_eq!(resolver.missing_sites, 2, "Wrong count of sites with a missing allele");
+        resolver.report("chr1");
+        assert_eq!(resolver.missing_sites, 0, "The count was not reset by the report");
+    }
+}
@@ -1,182 +1,606 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
 
-use std::collections::HashMap;
-
-use crate::io::SampleOut;
-use crate::recombination::RecombinationMapGenome;
-use crate::recombination::generate_simple_recombination;
-use crate::recombination::Crossover;
-use crate::variants::{self, VCFCollection};
-use crate::utils::push_haps_to_bed;
+use crate::dnm::{ChildMutations, CollisionPolicy, MergedMutations, MutationMerge, resample_genome};
+use crate::founders::{FounderGenome, FounderTract};
+use crate::io::{FamilyOut, SampleOut, Twin};
+use crate::meiosis::{HaplotypeSegment, MeiosisModel, Offspring, Parents, simulate_offspring};
+use crate::polyploid::{self, TetraploidGenome, TetraploidOffspring};
+use crate::regions;
+use crate::variants::{self, ChildGenotype, GenotypePolicy, VCFCollection};
+use crate::utils::{push_haps_to_bed, OutputFile};
+use crate::error::{MeiosimError, Result};
+use crate::seeds::{self, SeedTree};
 
 use log::info;
+use rand::seq::index;
+use rand_distr::{Distribution, Poisson};
+use rayon::prelude::*;
+
+/// A simulated sibling, with the number of records written to its outputs.
+pub struct SimulatedSibling {
+    pub offspring: Offspring,
+    /// founder tracts of the sibling
+    pub genome: FounderGenome,
+    /// inherited variants written to the DWGSIM file, DNMs excluded
+    pub variants_written: u64,
+    /// DNMs written, collisions skipped excluded
+    pub dnms: usize,
+    /// DNMs and postzygotic mutations at a site already written
+    pub dnm_collisions: usize,
+    pub twin: Option<Twin>,
+    /// DNM file the postzygotic mutations were drawn from, and their number
+    pub postzygotic: Option<(String, usize)>,
+}
+
+/// Postzygotic mutations of an MZ twin, drawn from the records of a DNM
+/// file that no sibling of the family uses.
+#[derive(Clone, Debug)]
+pub struct PostzygoticSource {
+    pub file: String,
+    pub count: usize,
+}
+
+/// Simulates all the siblings of a family. Each chromosome of the
+/// population panel is read once, and every record is routed to all the
+/// siblings at the same time.
+///
+/// Siblings and chromosomes are processed on `threads` threads (0 uses
+/// all the available cores). The random streams of each sibling are
+/// derived from `seeds`, so the result does not depend on the number of
+/// threads.
+#[allow(clippy::too_many_arguments)]
+pub fn wrk_generate_family(family: &FamilyOut,
+        model: MeiosisModel,
+        popvars: &VCFCollection,
+        denovo: &[String],
+        verbose: bool,
+        contig_size: &HashMap<String, u64>,
+        seeds: &SeedTree,
+        threads: usize) -> Result<Vec<SimulatedSibling>> {
+    let job = FamilyJob {
+        family,
+        denovo,
+        seeds: *seeds,
+        meiosis: model,
+        parent_genomes: None,
+        postzygotic: &[],
+        collision: CollisionPolicy::default(),
+    };
+    let mut siblings = wrk_generate_cohort(&[job], popvars, verbose, contig_size, threads)?;
+    Ok(siblings.pop().unwrap_or_default())
+}
 
-use rand::rngs::StdRng;
-use rand::Rng;
-use std::fs::File;
+/// A family to simulate in [`wrk_generate_cohort`], with the DNM files of
+/// its siblings and the seeds of its random streams.
+pub struct FamilyJob<'a> {
+    pub family: &'a FamilyOut,
+    pub denovo: &'a [String],
+    pub seeds: SeedTree,
+    /// crossover models of the meioses of parent1 and parent2
+    pub meiosis: MeiosisModel<'a>,
+    /// genomes of parent1 and parent2 when they are simulated individuals,
+    /// `None` when they are the samples of the panel named in `family`
+    pub parent_genomes: Option<&'a [FounderGenome; 2]>,
+    /// postzygotic mutations of each sibling, in the order of the samples
+    /// of `family`, empty if no sibling has any
+    pub postzygotic: &'a [Option<PostzygoticSource>],
+    /// what is done with the mutations at a site where a sibling carries
+    /// an inherited variant
+    pub collision: CollisionPolicy,
+}
 
-pub fn wrk_generate_offspring(sample: &SampleOut,
-        genome_recomb_map_opt: Option<&RecombinationMapGenome>,
-        simple_recombination_opt: Option<u8>,
+/// Simulates many families in a single pass over each chromosome of the
+/// population panel, as [`wrk_generate_family`] does for one. Returns the
+/// siblings of each family, in the order of `families`.
+pub fn wrk_generate_cohort(families: &[FamilyJob],
         popvars: &VCFCollection,
-        denovo: &String,
         verbose: bool,
         contig_size: &HashMap<String, u64>,
-        seeded_rng: &mut StdRng) -> () {
-    
-    if verbose {
-        info!("Generating offspring for: {}", sample.name);
+        threads: usize) -> Result<Vec<Vec<SimulatedSibling>>> {
+
+    let chromosomes = popvars.chromosomes();
+    if families.iter().any(|job| job.collision == CollisionPolicy::Resample) && popvars.reference_check().is_none() {
+        return Err(MeiosimError::Config("Resampled DNM positions need a reference genome (--reference)".to_string()));
     }
 
-    let cx_parent1: HashMap<String, Vec<(String, Crossover)>> = match (genome_recomb_map_opt, simple_recombination_opt) {
-        (Some(genome_recomb_map), None) => {
-            genome_recomb_map.generate_genome_cx("parent1".to_string(), seeded_rng)
-        },
-        (None, Some(simple_recombination)) => {
-            generate_simple_recombination(contig_size, "parent1".to_string(), seeded_rng, simple_recombination)
-        },
-        _ => {
-            panic!("Invalid combination of parameters");
+    // siblings of all the families, one after the other
+    let mut jobs = Vec::new();
+    let mut family_parents = Vec::with_capacity(families.len());
+    for (family_idx, job) in families.iter().enumerate() {
+        let parent_genomes = match (job.parent_genomes, job.family.samples.first()) {
+            (Some(parent_genomes), _) => parent_genomes.clone(),
+            (None, Some(sample)) => [
+                FounderGenome::founder(&sample.parent1, &chromosomes, contig_size)?,
+                FounderGenome::founder(&sample.parent2, &chromosomes, contig_size)?,
+            ],
+            (None, None) => Default::default(),
+        };
+        let founders: Vec<String> = parent_genomes.iter()
+            .flat_map(|genome| genome.founders())
+            .map(|founder| founder.to_string())
+            .collect();
+        popvars.check_samples(&founders)?;
+        family_parents.push(parent_genomes);
+        for (sample_idx, (sample, dnm_file)) in job.family.samples.iter().zip(job.denovo.iter()).enumerate() {
+            let postzygotic = job.postzygotic.get(sample_idx).and_then(|source| source.as_ref());
+            jobs.push((family_idx, sample, dnm_file, postzygotic));
         }
-    };
-        
-    let cx_parent2: HashMap<String, Vec<(String, Crossover)>> = match (genome_recomb_map_opt, simple_recombination_opt) {
-        (Some(genome_recomb_map), None) => {
-            genome_recomb_map.generate_genome_cx("parent2".to_string(), seeded_rng)
-        },
-        (None, Some(simple_recombination)) => {
-            generate_simple_recombination(contig_size, "parent2".to_string(), seeded_rng, simple_recombination)
-        },
-        _ => {
-            panic!("Invalid combination of parameters");
-        }   
-    };
-        
-    let mut chr_vector = popvars.vcfs.keys().collect::<Vec<&String>>();
-    chr_vector.sort();
-    let mut outputfile = File::create(&sample.targetvcfout).expect("Unable to create file");
-    let mut outputfile_bed = File::create(&sample.targetbedout).expect("Unable to create file");
-    let mut output_truednm = File::create(&sample.targetdnmout).expect("Unable to create file");
+    }
 
-    for chr in chr_vector {
+    let pool = thread_pool(threads)?;
 
-        let contig_size = contig_size.get(chr).unwrap().clone();
-        // check if samples are in the vcf
-        let vcf_obj = popvars.vcfs.get(chr).unwrap();
+    pool.install(|| {
+        let simulated = jobs.par_iter()
+            .map(|(family_idx, sample, dnm_file, postzygotic)| {
+                let seeds = &families[*family_idx].seeds;
+                let parents = Parents::new(&sample.parent1, &sample.parent2);
+                // the second of MZ twins draws the streams of the first
+                let meiosis_name = sample.meiosis_of().unwrap_or(&sample.name);
+                let mut offspring = simulate_offspring(meiosis_name,
+                    &parents,
+                    families[*family_idx].meiosis,
+                    &chromosomes,
+                    contig_size,
+                    verbose,
+                    seeds)?;
+                offspring.name = sample.name.clone();
+                let [parent1_genome, parent2_genome] = &family_parents[*family_idx];
+                let genome = FounderGenome::offspring(parent1_genome, parent2_genome, &offspring)?;
+                let mut rng_dnm = seeds.stream(&[meiosis_name, seeds::DNM_STRAND]);
+                let mut dnms = variants::read_dnm_genotypes(dnm_file, verbose, &mut rng_dnm)?;
+                dnms.retain(|dnm| popvars.in_regions(&dnm.seqname, dnm.pos as u64));
+                if let Some(reference) = popvars.reference_check() {
+                    reference.check_dnms(&mut dnms, dnm_file, verbose)?;
+                }
+                let postzygotic = match postzygotic {
+                    Some(source) => read_postzygotic_genotypes(source, &sample.name, popvars, verbose, seeds)?,
+                    None => Vec::new(),
+                };
+                Ok(((offspring, genome), (dnms, postzygotic)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let (simulated, mutations): (Vec<_>, Vec<_>) = simulated.into_iter().unzip();
+        let (offspring, genomes): (Vec<Offspring>, Vec<FounderGenome>) = simulated.into_iter().unzip();
+        let (dnms, postzygotic): (Vec<Vec<ChildGenotype>>, Vec<Vec<ChildGenotype>>) = mutations.into_iter().unzip();
+        let samples: Vec<&SampleOut> = jobs.iter().map(|(_, sample, _, _)| *sample).collect();
 
-        if ! vcf_obj.samples.contains(&sample.parent1) {
-            panic!("Sample {} not found in VCF", sample.parent1);
+        let mut outputfiles = Vec::with_capacity(offspring.len());
+        let mut outputfiles_bed = Vec::with_capacity(offspring.len());
+        let mut outputs_truednm = Vec::with_capacity(offspring.len());
+        for sample in samples.iter() {
+            outputfiles.push(OutputFile::create(&sample.targetvcfout)?);
+            outputfiles_bed.push(OutputFile::create(&sample.targetbedout)?);
+            outputs_truednm.push(OutputFile::create(&sample.targetdnmout)?);
         }
 
-        if !vcf_obj.samples.contains(&sample.parent2) {
-            panic!("Sample {} not found in VCF", sample.parent2);
+        for chr in chromosomes.iter() {
+            for (child, outputfile_bed) in offspring.iter().zip(outputfiles_bed.iter_mut()) {
+                for segment in chromosome_segments(child, chr)? {
+                    for (start, end) in regions::clip_to_regions(popvars.regions(), chr, segment.start, segment.end) {
+                        push_haps_to_bed(segment.hap1, segment.hap2, chr, start, end, outputfile_bed)?;
+                    }
+                }
+            }
         }
 
-        // these should be sorted
-        let vcf_obj = popvars.vcfs.get(chr).unwrap();
-        if verbose {
-            info!("Chromosome: {}", chr);
+        // the DNMs and postzygotic mutations are written among the
+        // inherited variants, in position order
+        let mutations: Vec<ChildMutations> = jobs.iter().zip(dnms).zip(postzygotic)
+            .map(|(((family_idx, sample, _, _), dnms), postzygotic)| ChildMutations {
+                policy: families[*family_idx].collision,
+                seeds: families[*family_idx].seeds.child(sample.meiosis_of().unwrap_or(&sample.name)),
+                dnms,
+                postzygotic,
+            })
+            .collect();
+        let (variants_written, mut merged) = write_genotypes(popvars, &genomes, &chromosomes, verbose, &mut outputfiles, &mutations)?;
+        // and those of the contigs without population variants at the end
+        write_other_contigs(popvars, &chromosomes, &mutations, &mut outputfiles, &mut merged, false)?;
+
+        for (((_, sample, _, source), output_truednm), child_merged) in jobs.iter().zip(outputs_truednm.iter_mut()).zip(merged.iter()) {
+            for line in child_merged.dnm_truth.iter() {
+                output_truednm.write_str(line)?;
+            }
+            // and the postzygotic mutations of the MZ twins
+            if source.is_some() {
+                let mut output_truepostzygotic = OutputFile::create(&sample.targetpostzygoticout)?;
+                for line in child_merged.postzygotic_truth.iter() {
+                    output_truepostzygotic.write_str(line)?;
+                }
+            }
+            if child_merged.collisions > 0 {
+                info!("{} mutations of {} are at a site already carrying a variant, see {}",
+                    child_merged.collisions, sample.name, sample.targetdnmout);
+            }
         }
-        let cx_chr_inst_p1 = cx_parent1.get(chr).unwrap();
-        let cx_chr_inst_p2 = cx_parent2.get(chr).unwrap();
-
-        let mut all_cx = Vec::new();
-        all_cx.extend(cx_chr_inst_p1);
-        all_cx.extend(cx_chr_inst_p2);
-        
-        all_cx.sort_by(|a, b| {
-            // a cmp b should be ascending order
-            a.1.cmp(&b.1)
-        } );
-
-        // this is per chromosome
-        // need to 
-        // 1. choose a random haplotype for parent1
-        // I did test this and it seems to be different value for each generation kinda thing
-        // I did remove the generator from the chromosome so it will be different
-        // in each chromosome
-        let initial_haplotype_parent1: usize = seeded_rng.gen_range(0..2);
-        // 2. choose a random haplotype for parent2
-        let initial_haplotype_parent2: usize = seeded_rng.gen_range(0..2);
-
-        // 3. combine cx from both parents, need to get a (hap1, hap2, position)
-        let mut all_hap: Vec<(usize, usize, u64, u64)> = Vec::new();
-        // note that this is 0-based
-        let mut last_position: u64 = 0;
-
-        let mut current_hap1 = initial_haplotype_parent1.clone();
-        let mut current_hap2 = initial_haplotype_parent2.clone();
-
-        if verbose {
-            info!("Initial haplotype parent1: {}", current_hap1);
-            info!("Initial haplotype parent2: {}", current_hap2);
+
+        let mut siblings: Vec<Vec<SimulatedSibling>> = families.iter().map(|_| Vec::new()).collect();
+        let simulated = offspring.into_iter().zip(genomes).zip(variants_written).zip(merged);
+        for ((((offspring, genome), variants_written), child_merged), (family_idx, sample, _, source)) in simulated.zip(jobs.iter()) {
+            siblings[*family_idx].push(SimulatedSibling {
+                offspring,
+                genome,
+                variants_written,
+                dnms: child_merged.dnms,
+                dnm_collisions: child_merged.collisions,
+                twin: sample.twin.clone(),
+                postzygotic: source.map(|source| (source.file.clone(), child_merged.postzygotic)),
+            });
         }
+        Ok(siblings)
+    })
+}
 
-        // i am pretty sure if the length of cx is 0, this won't run, that is ok
-        all_cx.iter().for_each(|(parent, crossover)| {
-            let inst_position = crossover.position;
-            info!("{}: {}", parent, inst_position);
-            if last_position > inst_position {
-                panic!("Crossover positions are not sorted");
+/// A simulated autotetraploid sibling, with the number of records written
+/// to its outputs.
+pub struct SimulatedTetraploid {
+    pub offspring: TetraploidOffspring,
+    pub genome: TetraploidGenome,
+    /// inherited variants written to the genotype file, DNMs excluded
+    pub variants_written: u64,
+    pub dnms: usize,
+    /// DNMs at a site already carrying a variant
+    pub dnm_collisions: usize,
+}
+
+/// Simulates all the siblings of a family of two autotetraploid samples
+/// of the panel. Each sibling gets its de novo mutations from the file of
+/// `denovo` at the same index, as a single copy, their collisions with
+/// the inherited variants being resolved as set by `collision`.
+///
+/// The genotypes are written as `chr pos ref alt a|b|c|d` lines instead
+/// of the DWGSIM format, which only holds diploid genotypes, and the
+/// haplotype BED file has the homolog of the parent carried by each of
+/// the four homologs of the sibling.
+#[allow(clippy::too_many_arguments)]
+pub fn wrk_generate_tetraploid_family(family: &FamilyOut,
+        model: MeiosisModel,
+        popvars: &VCFCollection,
+        denovo: &[String],
+        verbose: bool,
+        contig_size: &HashMap<String, u64>,
+        seeds: &SeedTree,
+        collision: CollisionPolicy,
+        threads: usize) -> Result<Vec<SimulatedTetraploid>> {
+    let chromosomes = popvars.chromosomes();
+    let Some(first) = family.samples.first() else {
+        return Ok(Vec::new());
+    };
+    let parents = Parents::new(&first.parent1, &first.parent2);
+    parents.check_in_panel(popvars)?;
+    let parent1_genome = TetraploidGenome::founder(&parents.parent1, &chromosomes, contig_size)?;
+    let parent2_genome = TetraploidGenome::founder(&parents.parent2, &chromosomes, contig_size)?;
+
+    thread_pool(threads)?.install(|| {
+        let simulated = family.samples.par_iter()
+            .zip(denovo.par_iter())
+            .map(|(sample, dnm_file)| {
+                let offspring = polyploid::simulate_tetraploid_offspring(&sample.name,
+                    &parents,
+                    model,
+                    &chromosomes,
+                    contig_size,
+                    seeds)?;
+                let genome = TetraploidGenome::offspring(&parent1_genome, &parent2_genome, &offspring)?;
+                let mut rng_dnm = seeds.stream(&[&sample.name, seeds::DNM_STRAND]);
+                let mut dnms = variants::read_dnm_genotypes(dnm_file, verbose, &mut rng_dnm)?;
+                dnms.retain(|dnm| popvars.in_regions(&dnm.seqname, dnm.pos as u64));
+                if let Some(reference) = popvars.reference_check() {
+                    reference.check_dnms(&mut dnms, dnm_file, verbose)?;
+                }
+                Ok((offspring, genome, dnms))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut outputfiles = Vec::with_capacity(simulated.len());
+        for (sample, (offspring, _, _)) in family.samples.iter().zip(simulated.iter()) {
+            outputfiles.push(OutputFile::create(&sample.targetvcfout)?);
+            polyploid::write_tetraploid_haplotypes(&sample.targetbedout, offspring, popvars.regions())?;
+        }
+        let genomes: Vec<TetraploidGenome> = simulated.iter().map(|(_, genome, _)| genome.clone()).collect();
+        let mutations: Vec<ChildMutations> = family.samples.iter().zip(simulated.iter())
+            .map(|(sample, (_, _, dnms))| ChildMutations {
+                policy: collision,
+                seeds: seeds.child(&sample.name),
+                dnms: dnms.clone(),
+                postzygotic: Vec::new(),
+            })
+            .collect();
+        let (variants_written, mut merged) = polyploid::write_tetraploid_genotypes(popvars, &genomes, &chromosomes, verbose, &mut outputfiles, &mutations)?;
+        write_other_contigs(popvars, &chromosomes, &mutations, &mut outputfiles, &mut merged, true)?;
+
+        let mut siblings = Vec::with_capacity(simulated.len());
+        let outputs = family.samples.iter().zip(variants_written).zip(merged);
+        for (((sample, variants_written), merged), (offspring, genome, _)) in outputs.zip(simulated) {
+            let mut output_truednm = OutputFile::create(&sample.targetdnmout)?;
+            for line in merged.dnm_truth.iter() {
+                output_truednm.write_str(line)?;
             }
-            all_hap.push((current_hap1.clone(),
-                          current_hap2.clone(),
-                          last_position,
-                          inst_position));
-
-            if parent == "parent1" {  // we should create a type for this?
-                current_hap1 = if current_hap1 == 0 {1} else {0};
-            } else {
-                current_hap2 = if current_hap2 == 0 {1} else {0};
+            if merged.collisions > 0 {
+                info!("{} mutations of {} are at a site already carrying a variant, see {}",
+                    merged.collisions, sample.name, sample.targetdnmout);
             }
-        
-            last_position = inst_position.clone();
-        });
-
-        // add last position, that is the end of the chromosome
-        all_hap.push(
-            (current_hap1.clone(),
-             current_hap2.clone(),
-             last_position,
-             contig_size.clone())
-        );
-
-        // 4. iteratively print the positions where any of the parents has a variant
-        // in that position 
-        if verbose {
-            info!("Haplotypes: {:?}", all_hap);
+            siblings.push(SimulatedTetraploid {
+                offspring,
+                genome,
+                variants_written,
+                dnms: merged.dnms,
+                dnm_collisions: merged.collisions,
+            });
         }
+        Ok(siblings)
+    })
+}
 
-        all_hap.iter().for_each(|(hap1, hap2, pos_from, pos_to)| {
-                if verbose {
-                    info!("Getting records for {}:{}-{}", chr, pos_from, pos_to);
+// draws the postzygotic mutations of `sample` among the records of the
+// file in the simulated regions, each on a random haplotype
+fn read_postzygotic_genotypes(source: &PostzygoticSource, sample: &str, popvars: &VCFCollection, verbose: bool, seeds: &SeedTree) -> Result<Vec<ChildGenotype>> {
+    let mut rng_postzygotic = seeds.stream(&[sample, seeds::POSTZYGOTIC]);
+    let mut candidates = variants::read_dnm_genotypes(&source.file, verbose, &mut rng_postzygotic)?;
+    candidates.retain(|candidate| popvars.in_regions(&candidate.seqname, candidate.pos as u64));
+    if let Some(reference) = popvars.reference_check() {
+        reference.check_dnms(&mut candidates, &source.file, verbose)?;
+    }
+    let mut chosen = index::sample(&mut rng_postzygotic, candidates.len(), source.count.min(candidates.len())).into_vec();
+    chosen.sort_unstable();
+    Ok(chosen.into_iter().map(|idx| candidates[idx].clone()).collect())
+}
+
+/// Writes the genotypes of simulated individuals in DWGSIM format, from
+/// the founder haplotypes they are made of, one file per genome in
+/// `outputs`. Returns the number of variants written for each individual.
+pub fn wrk_write_genotypes(popvars: &VCFCollection,
+        genomes: &[FounderGenome],
+        outputs: &[String],
+        verbose: bool,
+        threads: usize) -> Result<Vec<u64>> {
+    let founders: BTreeSet<&str> = genomes.iter().flat_map(|genome| genome.founders()).collect();
+    popvars.check_samples(&founders.into_iter().map(|founder| founder.to_string()).collect::<Vec<_>>())?;
+    let chromosomes = popvars.chromosomes();
+    thread_pool(threads)?.install(|| {
+        let mut outputfiles = outputs.iter()
+            .map(|output| OutputFile::create(output))
+            .collect::<Result<Vec<_>>>()?;
+        let (variants_written, _) = write_genotypes(popvars, genomes, &chromosomes, verbose, &mut outputfiles, &[])?;
+        Ok(variants_written)
+    })
+}
+
+/// Samples reads of haploid gametes at the heterozygous sites of their
+/// donor, as low-coverage single-cell sequencing would. The depth at each
+/// site is drawn from a Poisson distribution of mean `coverage`, and every
+/// read carries the allele of the gamete. Sites with reads are written as
+/// `chr pos ref alt ref_reads alt_reads` lines (1-based), one file per
+/// gamete in `outputs`, and the number of sites observed in each gamete
+/// is returned.
+///
+/// The depths of a gamete are drawn from streams of `seeds` named after
+/// it (in `names`) and the chromosome.
+#[allow(clippy::too_many_arguments)]
+pub fn wrk_sample_gamete_reads(popvars: &VCFCollection,
+        donor: &FounderGenome,
+        gametes: &[FounderGenome],
+        names: &[String],
+        outputs: &[String],
+        coverage: f64,
+        verbose: bool,
+        seeds: &SeedTree) -> Result<Vec<u64>> {
+    let depth = Poisson::new(coverage)
+        .map_err(|_| MeiosimError::Config(format!("Invalid coverage {}, it must be positive", coverage)))?;
+    let mut outputfiles = outputs.iter()
+        .map(|output| OutputFile::create(output))
+        .collect::<Result<Vec<_>>>()?;
+    let mut observed = vec![0; gametes.len()];
+    for chr in popvars.chromosomes() {
+        let vcf_obj = popvars.vcfs.get(&chr).ok_or_else(|| MeiosimError::ContigNotFound {
+            contig: chr.clone(),
+            source_name: "the population VCFs".to_string(),
+        })?;
+        // the donor is streamed after the gametes
+        let mut children = Vec::with_capacity(gametes.len() + 1);
+        for genome in gametes.iter().chain(std::iter::once(donor)) {
+            let [hap1, hap2] = genome.haplotypes(&chr)?;
+            children.push([hap1.as_slice(), hap2.as_slice()]);
+        }
+        let mut rngs: Vec<_> = names.iter().map(|name| seeds.stream(&[name, &chr, seeds::COVERAGE])).collect();
+        for family_genotypes in vcf_obj.stream_founder_genotypes(&children, verbose)? {
+            let family_genotypes = family_genotypes?;
+            match family_genotypes.gts[gametes.len()] {
+                Some((allele1, allele2)) if allele1 != allele2 => {},
+                _ => continue,
+            }
+            for (gamete_idx, (outputfile, rng)) in outputfiles.iter_mut().zip(rngs.iter_mut()).enumerate() {
+                let reads = depth.sample(rng) as u64;
+                let Some((allele, _)) = family_genotypes.gts[gamete_idx] else {
+                    continue;
+                };
+                if reads == 0 {
+                    continue;
                 }
+                let (ref_reads, alt_reads) = if allele == 0 { (reads, 0) } else { (0, reads) };
+                outputfile.write_str(&format!("{}\t{}\t{}\t{}\t{}\t{}\n",
+                    family_genotypes.seqname,
+                    family_genotypes.pos + 1,
+                    family_genotypes.alleles[0],
+                    family_genotypes.alleles[1],
+                    ref_reads,
+                    alt_reads))?;
+                observed[gamete_idx] += 1;
+            }
+        }
+    }
+    Ok(observed)
+}
 
-                push_haps_to_bed(hap1, hap2, chr, pos_from, pos_to, &mut outputfile_bed);
-            
-                vcf_obj.get_records_two_parents_from_to(
-                    &sample.parent1,
-                    &sample.parent2,
-                    *hap1, 
-                    *hap2,
-                    chr.clone(),
-                    *pos_from,
-                    *pos_to,
-                    verbose,
-                    &mut outputfile);
-                });
+// writes each chromosome in turn on a single thread, or else in parallel
+// to part files that are then appended in order to the output of each
+// individual
+fn write_genotypes(popvars: &VCFCollection,
+        genomes: &[FounderGenome],
+        chromosomes: &[String],
+        verbose: bool,
+        outputfiles: &mut [OutputFile],
+        mutations: &[ChildMutations]) -> Result<(Vec<u64>, Vec<MergedMutations>)> {
+    if rayon::current_num_threads() == 1 {
+        let mut variants_written = vec![0; outputfiles.len()];
+        let mut merged: Vec<MergedMutations> = mutations.iter().map(|_| MergedMutations::default()).collect();
+        for chr in chromosomes.iter() {
+            let (written, chr_merged) = write_chromosome_genotypes(popvars, genomes, chr, verbose, outputfiles, mutations)?;
+            add_counts(&mut variants_written, &written);
+            add_merged(&mut merged, chr_merged);
+        }
+        return Ok((variants_written, merged));
+    }
+    let part_files: Vec<Vec<String>> = chromosomes.iter().map(|chr| {
+        outputfiles.iter().map(|outputfile| format!("{}.{}.part", outputfile.path, chr)).collect()
+    }).collect();
+    let result = write_chromosome_parts(popvars, genomes, chromosomes, &part_files, verbose, outputfiles, mutations);
+    for part_file in part_files.iter().flatten() {
+        if Path::new(part_file).exists() {
+            let _ = fs::remove_file(part_file);
+        }
+    }
+    result
+}
+
+/// Thread pool with `threads` threads, 0 uses all the available cores.
+pub fn thread_pool(threads: usize) -> Result<rayon::ThreadPool> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|e| MeiosimError::Config(format!("Cannot start {} threads: {}", threads, e)))
+}
+
+fn add_counts(total: &mut [u64], counts: &[u64]) {
+    total.iter_mut().zip(counts).for_each(|(total, count)| *total += count);
+}
+
+// writes the mutations of the contigs without population variants, at
+// the end of the genotype files
+fn write_other_contigs(popvars: &VCFCollection,
+        chromosomes: &[String],
+        mutations: &[ChildMutations],
+        outputfiles: &mut [OutputFile],
+        merged: &mut [MergedMutations],
+        tetraploid: bool) -> Result<()> {
+    let genome = resample_genome(mutations, popvars.reference_check())?;
+    for ((child_mutations, outputfile), child_merged) in mutations.iter().zip(outputfiles.iter_mut()).zip(merged.iter_mut()) {
+        let mut contigs: Vec<&str> = Vec::new();
+        for mutation in child_mutations.dnms.iter().chain(child_mutations.postzygotic.iter()) {
+            if !chromosomes.contains(&mutation.seqname) && !contigs.contains(&mutation.seqname.as_str()) {
+                contigs.push(&mutation.seqname);
+            }
+        }
+        for chr in contigs {
+            let mut merge = MutationMerge::new(chr, child_mutations, popvars.regions(), genome.as_ref())?;
+            if tetraploid {
+                merge = merge.tetraploid()?;
+            }
+            child_merged.append(merge.finish(outputfile)?);
+        }
     }
+    Ok(())
+}
 
-    // get the DNM and add them to the file:
-    variants::flush_dnm_to_file(&mut outputfile, 
-            &mut output_truednm,
-            denovo, 
-            verbose,
-            seeded_rng,);
-    ()
+fn add_merged(total: &mut [MergedMutations], merged: Vec<MergedMutations>) {
+    total.iter_mut().zip(merged).for_each(|(total, merged)| total.append(merged));
 }
 
-pub fn wrk_format_vcf(outputfilename: &String, vcf_file: &String, verbose: bool) -> () {
-    let mut outputfile = File::create(&outputfilename).expect("Unable to create file");
-    variants::flush_vcf_to_file(&mut outputfile, vcf_file, verbose);
+fn chromosome_segments<'a>(child: &'a Offspring, chr: &str) -> Result<&'a [HaplotypeSegment]> {
+    child.segments.get(chr)
+        .map(|segments| segments.as_slice())
+        .ok_or_else(|| MeiosimError::ContigNotFound {
+            contig: chr.to_string(),
+            source_name: format!("the segments of {}", child.name),
+        })
 }
 
+// writes the genotypes of the children on `chr`, with their mutations if
+// `mutations` has those of each child
+fn write_chromosome_genotypes(popvars: &VCFCollection,
+        genomes: &[FounderGenome],
+        chr: &str,
+        verbose: bool,
+        outputfiles: &mut [OutputFile],
+        mutations: &[ChildMutations]) -> Result<(Vec<u64>, Vec<MergedMutations>)> {
+    let vcf_obj = popvars.vcfs.get(chr).ok_or_else(|| MeiosimError::ContigNotFound {
+        contig: chr.to_string(),
+        source_name: "the population VCFs".to_string(),
+    })?;
+    let children = genomes.iter()
+        .map(|genome| {
+            let [hap1, hap2] = genome.haplotypes(chr)?;
+            Ok([hap1.as_slice(), hap2.as_slice()])
+        })
+        .collect::<Result<Vec<[&[FounderTract]; 2]>>>()?;
+
+    // 4. iteratively print the positions where any of the parents has a variant
+    // in that position
+    let genome = resample_genome(mutations, popvars.reference_check())?;
+    let mut merges = mutations.iter()
+        .map(|child_mutations| MutationMerge::new(chr, child_mutations, popvars.regions(), genome.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    let mut written = vec![0; outputfiles.len()];
+    for family_genotypes in vcf_obj.stream_founder_genotypes(&children, verbose)? {
+        let family_genotypes = family_genotypes?;
+        for (child_idx, outputfile) in outputfiles.iter_mut().enumerate() {
+            if let Some(genotype) = family_genotypes.child(child_idx) {
+                let line_written = match merges.get_mut(child_idx) {
+                    Some(merge) => merge.write_inherited(genotype, outputfile)?,
+                    None => {
+                        let line_out = genotype.to_dwgsim()?;
+                        if !line_out.is_empty() {
+                            outputfile.write_str(&line_out)?;
+                        }
+                        !line_out.is_empty()
+                    },
+                };
+                if line_written {
+                    written[child_idx] += 1;
+                }
+            }
+        }
+    }
+    let merged = merges.into_iter()
+        .zip(outputfiles.iter_mut())
+        .map(|(merge, outputfile)| merge.finish(outputfile))
+        .collect::<Result<Vec<_>>>()?;
+    Ok((written, merged))
+}
+
+fn write_chromosome_parts(popvars: &VCFCollection,
+        genomes: &[FounderGenome],
+        chromosomes: &[String],
+        part_files: &[Vec<String>],
+        verbose: bool,
+        outputfiles: &mut [OutputFile],
+        mutations: &[ChildMutations]) -> Result<(Vec<u64>, Vec<MergedMutations>)> {
+    let chr_written = chromosomes.par_iter().zip(part_files.par_iter()).map(|(chr, chr_part_files)| {
+        let mut part_outputs = chr_part_files.iter()
+            .map(|part_file| OutputFile::create(part_file))
+            .collect::<Result<Vec<_>>>()?;
+        write_chromosome_genotypes(popvars, genomes, chr, verbose, &mut part_outputs, mutations)
+    }).collect::<Result<Vec<_>>>()?;
+
+    for chr_part_files in part_files {
+        for (part_file, outputfile) in chr_part_files.iter().zip(outputfiles.iter_mut()) {
+            let mut part = File::open(part_file).map_err(|e| MeiosimError::io(part_file, e))?;
+            io::copy(&mut part, outputfile).map_err(|e| MeiosimError::io(&outputfile.path, e))?;
+        }
+    }
+    let mut variants_written = vec![0; outputfiles.len()];
+    let mut merged: Vec<MergedMutations> = mutations.iter().map(|_| MergedMutations::default()).collect();
+    for (written, chr_merged) in chr_written {
+        add_counts(&mut variants_written, &written);
+        add_merged(&mut merged, chr_merged);
+    }
+    Ok((variants_written, merged))
+}
+
+pub fn wrk_format_vcf(outputfilename: &str, vcf_file: &str, policy: &GenotypePolicy, verbose: bool) -> Result<()> {
+    let mut outputfile = OutputFile::create(outputfilename)?;
+    variants::flush_vcf_to_file(&mut outputfile, vcf_file, policy, verbose)
+}